@@ -0,0 +1,5 @@
+-- Add a status to subscriptions.
+-- Subscribers stored so far never went through a confirmation step: consider them confirmed.
+ALTER TABLE subscriptions ADD COLUMN status TEXT NULL;
+UPDATE subscriptions SET status = 'confirmed' WHERE status IS NULL;
+ALTER TABLE subscriptions ALTER COLUMN status SET NOT NULL;
@@ -0,0 +1,18 @@
+-- Create Erasure Tokens Table
+-- Emailed to subscribers so that they confirm they own the address before erasure.
+CREATE TABLE erasure_tokens(
+  token TEXT PRIMARY KEY,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+  created_at timestamptz NOT NULL
+);
+
+-- Create Subscriber Erasures Table
+-- What remains of an erased subscriber: no personal data, but enough to keep
+-- aggregate counts intact. Doubles as the audit trail of erasures.
+CREATE TABLE subscriber_erasures(
+  subscriber_id uuid PRIMARY KEY,
+  status TEXT NOT NULL,
+  subscribed_at timestamptz NOT NULL,
+  erased_at timestamptz NOT NULL,
+  requested_by TEXT NOT NULL
+);
@@ -0,0 +1,15 @@
+-- Create Audit Events Table
+CREATE TABLE audit_events(
+  id BIGSERIAL PRIMARY KEY,
+  occurred_at timestamptz NOT NULL,
+  -- `subscriber`, `admin:<username>` or `system`.
+  actor TEXT NOT NULL,
+  action TEXT NOT NULL,
+  -- `<kind>:<id>`, e.g. `subscriber:<uuid>`.
+  target TEXT NOT NULL,
+  request_id TEXT,
+  -- Never personal data: it outlives erasures.
+  details JSONB
+);
+CREATE INDEX audit_events_target_idx ON audit_events (target, occurred_at);
+CREATE INDEX audit_events_occurred_at_idx ON audit_events (occurred_at);
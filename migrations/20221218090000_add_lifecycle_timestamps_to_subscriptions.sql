@@ -0,0 +1,15 @@
+-- Track when subscribers confirmed, unsubscribed, were last changed and were (softly) deleted.
+ALTER TABLE subscriptions ADD COLUMN confirmed_at timestamptz NULL;
+ALTER TABLE subscriptions ADD COLUMN unsubscribed_at timestamptz NULL;
+ALTER TABLE subscriptions ADD COLUMN updated_at timestamptz NULL;
+ALTER TABLE subscriptions ADD COLUMN deleted_at timestamptz NULL;
+
+-- The actual times were never recorded: the signup time is the best lower bound we have.
+UPDATE subscriptions SET confirmed_at = subscribed_at WHERE status = 'confirmed';
+UPDATE subscriptions SET unsubscribed_at = subscribed_at WHERE status = 'unsubscribed';
+UPDATE subscriptions SET updated_at = subscribed_at;
+ALTER TABLE subscriptions ALTER COLUMN updated_at SET NOT NULL;
+
+-- Deleted subscribers keep their row but must not keep their address from signing up again.
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+CREATE UNIQUE INDEX subscriptions_email_key ON subscriptions (email) WHERE deleted_at IS NULL;
@@ -0,0 +1,31 @@
+-- Subscribers are unique by their canonical email: the address with its domain normalised
+-- and its local part lowercased, so that `Foo@Example.com` and `foo@example.com` are one subscriber.
+-- Punycode conversion cannot be done here: internationalised domains are canonicalised from Rust
+-- once the migrations have run.
+UPDATE subscriptions
+SET email = left(email, length(email) - strpos(reverse(email), '@'))
+    || '@' || lower(right(email, strpos(reverse(email), '@') - 1));
+ALTER TABLE subscriptions ADD COLUMN email_canonical TEXT NULL;
+UPDATE subscriptions SET email_canonical = lower(email);
+ALTER TABLE subscriptions ALTER COLUMN email_canonical SET NOT NULL;
+
+-- Addresses that only differed by case become duplicates: keep the confirmed subscription,
+-- or the oldest one, and report how many were removed.
+DO $$
+DECLARE
+    removed integer;
+BEGIN
+    UPDATE subscriptions SET deleted_at = now(), updated_at = now()
+    WHERE deleted_at IS NULL AND EXISTS (
+        SELECT 1 FROM subscriptions AS kept
+        WHERE kept.email_canonical = subscriptions.email_canonical
+            AND kept.deleted_at IS NULL
+            AND (kept.status <> 'confirmed', kept.subscribed_at, kept.id)
+                < (subscriptions.status <> 'confirmed', subscriptions.subscribed_at, subscriptions.id)
+    );
+    GET DIAGNOSTICS removed = ROW_COUNT;
+    RAISE NOTICE 'Removed % duplicate subscription(s)', removed;
+END $$;
+
+DROP INDEX subscriptions_email_key;
+CREATE UNIQUE INDEX subscriptions_email_key ON subscriptions (email_canonical) WHERE deleted_at IS NULL;
@@ -0,0 +1,7 @@
+-- Admin-managed blocklist and allowlist for the domains of new subscribers.
+CREATE TABLE email_domain_rules(
+  domain TEXT NOT NULL PRIMARY KEY,
+  rule TEXT NOT NULL CHECK (rule IN ('block', 'allow')),
+  created_at timestamptz NOT NULL,
+  created_by TEXT NOT NULL
+);
@@ -0,0 +1,8 @@
+-- Token buckets for rate limiting, shared by every instance of the application.
+-- Keys are HMACs of the client IP or email address, never the values themselves.
+CREATE TABLE rate_limit_buckets(
+  key TEXT NOT NULL PRIMARY KEY,
+  tokens DOUBLE PRECISION NOT NULL,
+  updated_at timestamptz NOT NULL
+);
+CREATE INDEX rate_limit_buckets_updated_at_idx ON rate_limit_buckets (updated_at);
@@ -0,0 +1,8 @@
+-- Create Subscription Tokens Table
+-- Sent to subscribers in confirmation and unsubscribe links.
+CREATE TABLE subscription_tokens(
+  subscription_token TEXT PRIMARY KEY,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+  created_at timestamptz NOT NULL
+);
+CREATE INDEX subscription_tokens_subscriber_id_idx ON subscription_tokens (subscriber_id);
@@ -0,0 +1,10 @@
+-- Templates newsletter issues are rendered from, for each subscriber.
+CREATE TABLE newsletter_templates(
+  id uuid NOT NULL PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE,
+  subject TEXT NOT NULL,
+  html_body TEXT NOT NULL,
+  text_body TEXT NOT NULL,
+  created_at timestamptz NOT NULL,
+  updated_at timestamptz NOT NULL
+);
@@ -0,0 +1,25 @@
+-- Newsletter issues, copied from a template when scheduled.
+CREATE TABLE newsletter_issues(
+  id uuid NOT NULL PRIMARY KEY,
+  template_id uuid REFERENCES newsletter_templates (id) ON DELETE SET NULL,
+  name TEXT NOT NULL,
+  subject TEXT NOT NULL,
+  html_body TEXT NOT NULL,
+  text_body TEXT NOT NULL,
+  status TEXT NOT NULL,
+  scheduled_at timestamptz NOT NULL,
+  published_at timestamptz,
+  created_at timestamptz NOT NULL,
+  updated_at timestamptz NOT NULL
+);
+CREATE INDEX newsletter_issues_due_idx ON newsletter_issues (scheduled_at) WHERE status = 'scheduled';
+
+-- One row per email still to send for a published issue.
+CREATE TABLE issue_delivery_queue(
+  newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+  n_retries INT NOT NULL DEFAULT 0,
+  execute_after timestamptz NOT NULL,
+  PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
+CREATE INDEX issue_delivery_queue_execute_after_idx ON issue_delivery_queue (execute_after);
@@ -0,0 +1,6 @@
+-- An IANA time zone name, for issues sent at a local time.
+ALTER TABLE subscriptions ADD COLUMN timezone TEXT NULL;
+
+-- When set, each subscriber gets the issue at this time of day in their own time zone,
+-- the first time it comes round once the issue is published.
+ALTER TABLE newsletter_issues ADD COLUMN local_time TIME NULL;
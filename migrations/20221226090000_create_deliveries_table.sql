@@ -0,0 +1,14 @@
+-- What happened to each email of a published issue, kept once it has left the queue.
+CREATE TABLE deliveries(
+  newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+  status TEXT NOT NULL,
+  -- The id the email provider gave the email, which bounce reports refer to.
+  provider_message_id TEXT,
+  queued_at timestamptz NOT NULL,
+  sent_at timestamptz,
+  bounced_at timestamptz,
+  updated_at timestamptz NOT NULL,
+  PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
+CREATE INDEX deliveries_provider_message_id_idx ON deliveries (provider_message_id);
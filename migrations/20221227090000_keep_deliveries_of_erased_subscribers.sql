@@ -0,0 +1,9 @@
+-- Erasing a subscriber keeps their deliveries without the subscriber, so that the
+-- counts reported for each issue do not change.
+ALTER TABLE deliveries DROP CONSTRAINT deliveries_pkey;
+ALTER TABLE deliveries ALTER COLUMN subscriber_id DROP NOT NULL;
+ALTER TABLE deliveries DROP CONSTRAINT deliveries_subscriber_id_fkey;
+ALTER TABLE deliveries ADD CONSTRAINT deliveries_subscriber_id_fkey
+  FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE SET NULL;
+CREATE UNIQUE INDEX deliveries_newsletter_issue_id_subscriber_id_idx
+  ON deliveries (newsletter_issue_id, subscriber_id);
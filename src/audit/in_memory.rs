@@ -0,0 +1,60 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use super::{AuditEvent, AuditFilter, AuditLog, NewAuditEvent};
+
+/// Keeps audit events in a `Vec`, for tests.
+#[derive(Default)]
+pub struct InMemoryAuditLog {
+    events: Mutex<Vec<AuditEvent>>,
+}
+
+impl InMemoryAuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_events<T>(&self, f: impl FnOnce(&mut Vec<AuditEvent>) -> T) -> T {
+        let mut events = self
+            .events
+            .lock()
+            .expect("The audit events lock is poisoned");
+        f(&mut events)
+    }
+}
+
+#[async_trait]
+impl AuditLog for InMemoryAuditLog {
+    async fn record(&self, event: NewAuditEvent) -> anyhow::Result<()> {
+        self.with_events(|events| {
+            events.push(AuditEvent {
+                id: events.len() as i64 + 1,
+                occurred_at: Utc::now(),
+                actor: event.actor.to_string(),
+                action: event.action,
+                target: event.target.as_ref().to_owned(),
+                request_id: event.request_id,
+                details: event.details,
+            })
+        });
+        Ok(())
+    }
+
+    async fn query(&self, filter: &AuditFilter) -> anyhow::Result<Vec<AuditEvent>> {
+        Ok(self.with_events(|events| {
+            events
+                .iter()
+                .rev()
+                .filter(|e| filter.matches(e))
+                .take(
+                    filter
+                        .limit
+                        .map_or(usize::MAX, |limit| limit.max(0) as usize),
+                )
+                .cloned()
+                .collect()
+        }))
+    }
+}
@@ -0,0 +1,261 @@
+mod in_memory;
+mod postgres;
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use uuid::Uuid;
+
+pub use in_memory::InMemoryAuditLog;
+pub use postgres::PgAuditLog;
+
+/// Who performed an audited action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Actor {
+    /// A subscriber acting on their own subscription.
+    Subscriber,
+    Admin(String),
+    /// The CLI and background jobs.
+    System,
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Actor::Subscriber => write!(f, "subscriber"),
+            Actor::Admin(username) => write!(f, "admin:{}", username),
+            Actor::System => write!(f, "system"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    SubscriberCreated,
+    SubscriptionConfirmed,
+    Unsubscribed,
+    SubscriberUpdated,
+    SubscriberDeleted,
+    SubscriberErased,
+    SubscribersImported,
+    SubscribersExported,
+    DataExportSent,
+    AdminCreated,
+    NewsletterPublished,
+    EmailDomainRuleSet,
+    EmailDomainRuleRemoved,
+    NewsletterTemplateCreated,
+    NewsletterTemplateUpdated,
+    NewsletterTemplateDeleted,
+    NewsletterScheduled,
+    NewsletterRescheduled,
+    NewsletterCancelled,
+}
+
+impl AuditAction {
+    const ALL: [AuditAction; 19] = [
+        AuditAction::SubscriberCreated,
+        AuditAction::SubscriptionConfirmed,
+        AuditAction::Unsubscribed,
+        AuditAction::SubscriberUpdated,
+        AuditAction::SubscriberDeleted,
+        AuditAction::SubscriberErased,
+        AuditAction::SubscribersImported,
+        AuditAction::SubscribersExported,
+        AuditAction::DataExportSent,
+        AuditAction::AdminCreated,
+        AuditAction::NewsletterPublished,
+        AuditAction::EmailDomainRuleSet,
+        AuditAction::EmailDomainRuleRemoved,
+        AuditAction::NewsletterTemplateCreated,
+        AuditAction::NewsletterTemplateUpdated,
+        AuditAction::NewsletterTemplateDeleted,
+        AuditAction::NewsletterScheduled,
+        AuditAction::NewsletterRescheduled,
+        AuditAction::NewsletterCancelled,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::SubscriberCreated => "subscriber_created",
+            AuditAction::SubscriptionConfirmed => "subscription_confirmed",
+            AuditAction::Unsubscribed => "unsubscribed",
+            AuditAction::SubscriberUpdated => "subscriber_updated",
+            AuditAction::SubscriberDeleted => "subscriber_deleted",
+            AuditAction::SubscriberErased => "subscriber_erased",
+            AuditAction::SubscribersImported => "subscribers_imported",
+            AuditAction::SubscribersExported => "subscribers_exported",
+            AuditAction::DataExportSent => "data_export_sent",
+            AuditAction::AdminCreated => "admin_created",
+            AuditAction::NewsletterPublished => "newsletter_published",
+            AuditAction::EmailDomainRuleSet => "email_domain_rule_set",
+            AuditAction::EmailDomainRuleRemoved => "email_domain_rule_removed",
+            AuditAction::NewsletterTemplateCreated => "newsletter_template_created",
+            AuditAction::NewsletterTemplateUpdated => "newsletter_template_updated",
+            AuditAction::NewsletterTemplateDeleted => "newsletter_template_deleted",
+            AuditAction::NewsletterScheduled => "newsletter_scheduled",
+            AuditAction::NewsletterRescheduled => "newsletter_rescheduled",
+            AuditAction::NewsletterCancelled => "newsletter_cancelled",
+        }
+    }
+}
+
+impl TryFrom<String> for AuditAction {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| anyhow!("{} is not a valid audit action.", s))
+    }
+}
+
+/// What an audited action was performed on, as `<kind>:<id>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditTarget(String);
+
+impl AuditTarget {
+    pub fn subscriber(id: Uuid) -> Self {
+        Self(format!("subscriber:{}", id))
+    }
+
+    /// The subscriber list as a whole, for bulk operations.
+    pub fn subscriber_list() -> Self {
+        Self("subscribers".to_owned())
+    }
+
+    pub fn user(user_id: Uuid) -> Self {
+        Self(format!("user:{}", user_id))
+    }
+
+    pub fn newsletter(id: Uuid) -> Self {
+        Self(format!("newsletter:{}", id))
+    }
+
+    pub fn email_domain(domain: &str) -> Self {
+        Self(format!("email_domain:{}", domain))
+    }
+
+    pub fn newsletter_template(id: Uuid) -> Self {
+        Self(format!("newsletter_template:{}", id))
+    }
+}
+
+impl AsRef<str> for AuditTarget {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NewAuditEvent {
+    pub actor: Actor,
+    pub action: AuditAction,
+    pub target: AuditTarget,
+    pub request_id: Option<String>,
+    /// Extra context. Never personal data: events outlive erasures.
+    pub details: Option<serde_json::Value>,
+}
+
+impl NewAuditEvent {
+    pub fn new(actor: Actor, action: AuditAction, target: AuditTarget) -> Self {
+        Self {
+            actor,
+            action,
+            target,
+            request_id: None,
+            details: None,
+        }
+    }
+
+    pub fn request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    pub fn details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub actor: String,
+    pub action: AuditAction,
+    pub target: String,
+    pub request_id: Option<String>,
+    pub details: Option<serde_json::Value>,
+}
+
+/// Criteria for [`AuditLog::query`]. Unset fields do not filter.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub target: Option<String>,
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Keyset pagination: only events with a smaller id.
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl AuditFilter {
+    fn matches(&self, event: &AuditEvent) -> bool {
+        self.target.as_deref().is_none_or(|t| event.target == t)
+            && self.actor.as_deref().is_none_or(|a| event.actor == a)
+            && self.action.is_none_or(|a| event.action == a)
+            && self.since.is_none_or(|since| event.occurred_at >= since)
+            && self.until.is_none_or(|until| event.occurred_at < until)
+            && self.before.is_none_or(|before| event.id < before)
+    }
+}
+
+/// An append-only record of who did what.
+#[async_trait]
+pub trait AuditLog: Send + Sync {
+    async fn record(&self, event: NewAuditEvent) -> anyhow::Result<()>;
+
+    /// Events matching `filter`, newest first.
+    async fn query(&self, filter: &AuditFilter) -> anyhow::Result<Vec<AuditEvent>>;
+}
+
+pub type SharedAuditLog = Arc<dyn AuditLog>;
+
+/// Record `event`, logging failures instead of returning them: the action
+/// being audited has already happened by the time it is recorded.
+pub async fn record(audit_log: &dyn AuditLog, event: NewAuditEvent) {
+    if let Err(e) = audit_log.record(event.clone()).await {
+        error!(?event, "Failed to record audit event {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn actions_round_trip_through_their_string_form() {
+        for action in AuditAction::ALL {
+            assert_ok_eq!(AuditAction::try_from(action.as_str().to_owned()), action);
+        }
+        assert_err!(AuditAction::try_from("subscriber_hacked".to_owned()));
+    }
+
+    #[test]
+    fn actors_are_displayed_with_their_kind() {
+        assert_eq!(Actor::Admin("root".into()).to_string(), "admin:root");
+        assert_eq!(Actor::Subscriber.to_string(), "subscriber");
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use super::{AuditEvent, AuditFilter, AuditLog, NewAuditEvent};
+
+pub struct PgAuditLog {
+    db_connection: PgPool,
+}
+
+impl PgAuditLog {
+    pub fn new(db_connection: PgPool) -> Self {
+        Self { db_connection }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct AuditEventRow {
+    id: i64,
+    occurred_at: DateTime<Utc>,
+    actor: String,
+    action: String,
+    target: String,
+    request_id: Option<String>,
+    details: Option<serde_json::Value>,
+}
+
+impl TryFrom<AuditEventRow> for AuditEvent {
+    type Error = anyhow::Error;
+
+    fn try_from(row: AuditEventRow) -> Result<Self, Self::Error> {
+        Ok(AuditEvent {
+            id: row.id,
+            occurred_at: row.occurred_at,
+            actor: row.actor,
+            action: row.action.try_into()?,
+            target: row.target,
+            request_id: row.request_id,
+            details: row.details,
+        })
+    }
+}
+
+#[async_trait]
+impl AuditLog for PgAuditLog {
+    #[tracing::instrument(name = "Record audit event", skip(self))]
+    async fn record(&self, event: NewAuditEvent) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_events (occurred_at, actor, action, target, request_id, details)
+            VALUES ($1, $2, $3, $4, $5, $6)"#,
+            Utc::now(),
+            event.actor.to_string(),
+            event.action.as_str(),
+            event.target.as_ref(),
+            event.request_id,
+            event.details
+        )
+        .execute(&self.db_connection)
+        .await
+        .context("Failed to insert audit event")?;
+        Ok(())
+    }
+
+    async fn query(&self, filter: &AuditFilter) -> anyhow::Result<Vec<AuditEvent>> {
+        let mut query: QueryBuilder<Postgres> = QueryBuilder::new(
+            "SELECT id, occurred_at, actor, action, target, request_id, details \
+            FROM audit_events WHERE TRUE",
+        );
+        if let Some(target) = &filter.target {
+            query.push(" AND target = ").push_bind(target);
+        }
+        if let Some(actor) = &filter.actor {
+            query.push(" AND actor = ").push_bind(actor);
+        }
+        if let Some(action) = filter.action {
+            query.push(" AND action = ").push_bind(action.as_str());
+        }
+        if let Some(since) = filter.since {
+            query.push(" AND occurred_at >= ").push_bind(since);
+        }
+        if let Some(until) = filter.until {
+            query.push(" AND occurred_at < ").push_bind(until);
+        }
+        if let Some(before) = filter.before {
+            query.push(" AND id < ").push_bind(before);
+        }
+        query.push(" ORDER BY id DESC");
+        if let Some(limit) = filter.limit {
+            query.push(" LIMIT ").push_bind(limit);
+        }
+
+        query
+            .build_query_as::<AuditEventRow>()
+            .fetch_all(&self.db_connection)
+            .await
+            .context("Failed to query audit events")?
+            .into_iter()
+            .map(AuditEvent::try_from)
+            .collect()
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::{anyhow, bail, Context};
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use async_trait::async_trait;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::http::{header, request::Parts, HeaderMap};
+use base64::Engine;
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::audit::Actor;
+use crate::error::AppError;
+
+const MIN_PASSWORD_LENGTH: usize = 12;
+
+#[derive(Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("Invalid credentials.")]
+    InvalidCredentials(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+pub fn compute_password_hash(password: &Secret<String>) -> anyhow::Result<Secret<String>> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None).unwrap(),
+    )
+    .hash_password(password.expose_secret().as_bytes(), &salt)
+    .context("Failed to hash password")?
+    .to_string();
+    Ok(Secret::new(password_hash))
+}
+
+#[tracing::instrument(name = "Create admin user", skip(db_connection, password))]
+pub async fn create_admin(
+    db_connection: &PgPool,
+    username: &str,
+    password: Secret<String>,
+) -> anyhow::Result<Uuid> {
+    if username.trim().is_empty() {
+        bail!("The username cannot be empty.");
+    }
+    if password.expose_secret().chars().count() < MIN_PASSWORD_LENGTH {
+        bail!(
+            "The password must be at least {} characters long.",
+            MIN_PASSWORD_LENGTH
+        );
+    }
+
+    let password_hash = compute_password_hash(&password)?;
+    let user_id = Uuid::new_v4();
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (username) DO NOTHING"#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        Utc::now()
+    )
+    .execute(db_connection)
+    .await
+    .context("Failed to store the admin user")?;
+    if inserted.rows_affected() == 0 {
+        bail!("A user named {} already exists.", username);
+    }
+    Ok(user_id)
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(credentials, db_connection))]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    db_connection: &PgPool,
+) -> Result<Uuid, AuthError> {
+    // Verify against a dummy hash when the user does not exist, so that
+    // response times do not reveal which usernames are valid.
+    let mut user_id = None;
+    let mut expected_password_hash = Secret::new(
+        "$argon2id$v=19$m=15000,t=2,p=1$\
+        gZiV/M1gPc22ElAH/Jh1Hw$\
+        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
+            .to_string(),
+    );
+
+    let row = sqlx::query!(
+        "SELECT user_id, password_hash FROM users WHERE username = $1",
+        credentials.username,
+    )
+    .fetch_optional(db_connection)
+    .await
+    .context("Failed to perform a query to retrieve stored credentials.")?;
+    if let Some(row) = row {
+        user_id = Some(row.user_id);
+        expected_password_hash = Secret::new(row.password_hash);
+    }
+
+    // Hashing is CPU-bound: keep it off the async executor.
+    tokio::task::spawn_blocking(move || {
+        verify_password_hash(expected_password_hash, credentials.password)
+    })
+    .await
+    .context("Failed to spawn blocking task.")??;
+
+    user_id
+        .ok_or_else(|| anyhow!("Unknown username."))
+        .map_err(AuthError::InvalidCredentials)
+}
+
+fn verify_password_hash(
+    expected_password_hash: Secret<String>,
+    password_candidate: Secret<String>,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .context("Failed to parse hash in PHC string format.")?;
+    Argon2::default()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .context("Invalid password.")
+        .map_err(AuthError::InvalidCredentials)
+}
+
+/// Parse `Authorization: Basic <base64(username:password)>`.
+fn basic_authentication(headers: &HeaderMap) -> anyhow::Result<Credentials> {
+    let header_value = headers
+        .get(header::AUTHORIZATION)
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .context("A password must be provided in 'Basic' auth.")?;
+    Ok(Credentials {
+        username: username.to_owned(),
+        password: Secret::new(password.to_owned()),
+    })
+}
+
+/// Check the password of the Basic credentials a webhook is configured with against
+/// `secret`, whatever the username.
+pub fn verify_webhook_secret(headers: &HeaderMap, secret: &Secret<String>) -> Result<(), AppError> {
+    let credentials =
+        basic_authentication(headers).map_err(|e| AppError::Unauthorized(e.to_string()))?;
+    let candidate = credentials.password.expose_secret().as_bytes();
+    let expected = secret.expose_secret().as_bytes();
+    // Compare every byte, so that the time taken does not tell how much matched.
+    let matches = candidate.len() == expected.len()
+        && candidate
+            .iter()
+            .zip(expected)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0;
+    if matches {
+        Ok(())
+    } else {
+        Err(AppError::Unauthorized("Invalid webhook secret.".to_owned()))
+    }
+}
+
+/// An admin authenticated with HTTP Basic credentials.
+/// Handlers taking it as an argument reject every other request with `401`.
+#[derive(Debug, Clone)]
+pub struct AdminUser {
+    pub user_id: Uuid,
+    pub username: String,
+}
+
+impl AdminUser {
+    pub fn actor(&self) -> Actor {
+        Actor::Admin(self.username.clone())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminUser
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let credentials = basic_authentication(&parts.headers)
+            .map_err(|e| AppError::Unauthorized(e.to_string()))?;
+        let username = credentials.username.clone();
+        let db_connection = PgPool::from_ref(state);
+        match validate_credentials(credentials, &db_connection).await {
+            Ok(user_id) => Ok(AdminUser { user_id, username }),
+            Err(AuthError::InvalidCredentials(_)) => Err(AppError::Unauthorized(
+                "Invalid username or password.".to_owned(),
+            )),
+            Err(AuthError::UnexpectedError(e)) => Err(AppError::Other(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use claims::assert_err;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(authorization).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn basic_credentials_are_decoded() {
+        // "admin:pass:word", colons are allowed in the password.
+        let credentials = basic_authentication(&headers("Basic YWRtaW46cGFzczp3b3Jk")).unwrap();
+        assert_eq!(credentials.username, "admin");
+        assert_eq!(credentials.password.expose_secret(), "pass:word");
+    }
+
+    #[test]
+    fn other_schemes_and_malformed_values_are_rejected() {
+        assert_err!(basic_authentication(&HeaderMap::new()));
+        assert_err!(basic_authentication(&headers("Bearer YWRtaW46cGFzcw==")));
+        assert_err!(basic_authentication(&headers("Basic not-base64!")));
+        // "admin", without a password.
+        assert_err!(basic_authentication(&headers("Basic YWRtaW4=")));
+    }
+}
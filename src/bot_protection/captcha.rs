@@ -0,0 +1,178 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::configuration::CaptchaSettings;
+
+#[async_trait]
+pub trait CaptchaVerifier: Send + Sync {
+    /// Whether `response`, the token posted by the captcha widget, solves a challenge.
+    async fn verify(&self, response: &str, remote_ip: Option<IpAddr>) -> anyhow::Result<bool>;
+}
+
+/// Verifies responses against a `siteverify` endpoint, the protocol shared by
+/// hCaptcha, Cloudflare Turnstile and reCAPTCHA.
+pub struct HttpCaptchaVerifier {
+    http_client: Client,
+    verify_url: String,
+    secret_key: Secret<String>,
+}
+
+#[derive(Serialize)]
+struct VerifyRequest<'a> {
+    secret: &'a str,
+    response: &'a str,
+    #[serde(rename = "remoteip", skip_serializing_if = "Option::is_none")]
+    remote_ip: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VerifyResponse {
+    success: bool,
+    #[serde(rename = "error-codes", default)]
+    error_codes: Vec<String>,
+}
+
+impl HttpCaptchaVerifier {
+    pub fn new(
+        verify_url: String,
+        secret_key: Secret<String>,
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let http_client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .context("Failed to build the captcha HTTP client")?;
+        Ok(Self {
+            http_client,
+            verify_url,
+            secret_key,
+        })
+    }
+
+    pub fn from_settings(settings: &CaptchaSettings) -> anyhow::Result<Self> {
+        Self::new(
+            settings.verify_url.clone(),
+            settings.secret_key.clone(),
+            settings.timeout(),
+        )
+    }
+}
+
+#[async_trait]
+impl CaptchaVerifier for HttpCaptchaVerifier {
+    #[tracing::instrument(name = "Verify a captcha", skip(self, response))]
+    async fn verify(&self, response: &str, remote_ip: Option<IpAddr>) -> anyhow::Result<bool> {
+        let request = VerifyRequest {
+            secret: self.secret_key.expose_secret(),
+            response,
+            remote_ip: remote_ip.map(|ip| ip.to_string()),
+        };
+        let verification: VerifyResponse = self
+            .http_client
+            .post(&self.verify_url)
+            .form(&request)
+            .send()
+            .await
+            .context("Failed to reach the captcha provider")?
+            .error_for_status()
+            .context("The captcha provider returned an error")?
+            .json()
+            .await
+            .context("Failed to read the captcha provider's answer")?;
+        if !verification.success {
+            info!(error_codes = ?verification.error_codes, "Captcha rejected");
+        }
+        Ok(verification.success)
+    }
+}
+
+/// Accepts a single response, for tests.
+pub struct MockCaptchaVerifier {
+    valid_response: String,
+}
+
+impl MockCaptchaVerifier {
+    pub fn accepting(valid_response: &str) -> Self {
+        Self {
+            valid_response: valid_response.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl CaptchaVerifier for MockCaptchaVerifier {
+    async fn verify(&self, response: &str, _remote_ip: Option<IpAddr>) -> anyhow::Result<bool> {
+        Ok(response == self.valid_response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_ok_eq};
+    use wiremock::matchers::{body_string_contains, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn verifier(server: &MockServer) -> HttpCaptchaVerifier {
+        HttpCaptchaVerifier::new(
+            format!("{}/siteverify", server.uri()),
+            Secret::new("captcha-secret".into()),
+            Duration::from_millis(200),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_response_secret_and_client_ip_are_posted_as_a_form() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("secret=captcha-secret"))
+            .and(body_string_contains("response=solved"))
+            .and(body_string_contains("remoteip=203.0.113.7"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_ok_eq!(
+            verifier(&server)
+                .verify("solved", Some("203.0.113.7".parse().unwrap()))
+                .await,
+            true
+        );
+    }
+
+    #[tokio::test]
+    async fn unsuccessful_verifications_are_rejections() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": false,
+                "error-codes": ["invalid-input-response"]
+            })))
+            .mount(&server)
+            .await;
+
+        assert_ok_eq!(verifier(&server).verify("guessed", None).await, false);
+    }
+
+    #[tokio::test]
+    async fn provider_failures_are_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        assert_err!(verifier(&server).verify("solved", None).await);
+    }
+}
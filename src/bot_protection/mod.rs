@@ -0,0 +1,372 @@
+mod captcha;
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use thiserror::Error;
+
+use crate::configuration::BotProtectionSettings;
+
+pub use captcha::{CaptchaVerifier, HttpCaptchaVerifier, MockCaptchaVerifier};
+
+/// Form tokens older than this are refused, so that a harvested one cannot be replayed forever.
+const FORM_TOKEN_LIFETIME_HOURS: i64 = 24;
+
+pub type SharedCaptchaVerifier = Arc<dyn CaptchaVerifier>;
+
+/// The anti-bot fields of a submitted form.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FormSubmission<'a> {
+    pub honeypot: Option<&'a str>,
+    pub form_token: Option<&'a str>,
+    pub captcha_response: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Human,
+    /// To be dropped without telling the sender, who would otherwise learn to avoid the trap.
+    Bot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum BotRejection {
+    #[error("The form token is missing, reload the form and try again.")]
+    MissingFormToken,
+    #[error("The form token is invalid, reload the form and try again.")]
+    InvalidFormToken,
+    #[error("The form has expired, reload it and try again.")]
+    ExpiredFormToken,
+    #[error("The form has already been submitted, reload it and try again.")]
+    ReusedFormToken,
+    #[error("The form was submitted too quickly, wait a few seconds and try again.")]
+    TooFast,
+    #[error("Complete the captcha to subscribe.")]
+    MissingCaptcha,
+    #[error("The captcha could not be verified, try again.")]
+    FailedCaptcha,
+}
+
+#[derive(Debug, Error)]
+pub enum BotProtectionError {
+    #[error(transparent)]
+    Rejected(#[from] BotRejection),
+    /// The captcha provider could not be reached or did not answer properly.
+    #[error(transparent)]
+    CaptchaUnavailable(anyhow::Error),
+}
+
+/// The nonces of accepted form tokens, until the tokens expire. They are kept per
+/// instance: behind a load balancer, a token may be accepted once by each instance.
+#[derive(Default)]
+struct UsedNonces(Mutex<HashMap<String, DateTime<Utc>>>);
+
+impl UsedNonces {
+    /// Returns `false` if `nonce` has already been used.
+    fn insert(&self, nonce: &str, expires_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        let mut nonces = self.0.lock().expect("The used nonces lock is poisoned");
+        nonces.retain(|_, expires_at| *expires_at > now);
+        nonces.insert(nonce.to_owned(), expires_at).is_none()
+    }
+}
+
+/// Tells people from scripts on the public signup form.
+#[derive(Clone)]
+pub struct BotProtection {
+    form_token_key: Secret<String>,
+    captcha: Option<SharedCaptchaVerifier>,
+    used_nonces: Arc<UsedNonces>,
+}
+
+impl BotProtection {
+    /// Without a captcha verifier, captchas are not required.
+    pub fn new(form_token_key: Secret<String>, captcha: Option<SharedCaptchaVerifier>) -> Self {
+        Self {
+            form_token_key,
+            captcha,
+            used_nonces: Arc::default(),
+        }
+    }
+
+    pub fn with_captcha_verifier(mut self, captcha: SharedCaptchaVerifier) -> Self {
+        self.captcha = Some(captcha);
+        self
+    }
+
+    /// A single-use token recording when a form was handed out, as
+    /// `<unix seconds>.<nonce>.<signature>`.
+    pub fn issue_form_token(&self, now: DateTime<Utc>) -> String {
+        let issued_at = now.timestamp();
+        let mut nonce = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let nonce = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(nonce);
+        let signature = self.sign(issued_at, &nonce);
+        format!("{}.{}.{}", issued_at, nonce, signature)
+    }
+
+    /// The honeypot first, then the form token, then the captcha, which costs a request
+    /// to its provider. Form tokens are only used up once every check has passed.
+    #[tracing::instrument(name = "Check for bots", skip(self, submission, settings))]
+    pub async fn check(
+        &self,
+        submission: FormSubmission<'_>,
+        settings: &BotProtectionSettings,
+        remote_ip: Option<IpAddr>,
+    ) -> Result<Verdict, BotProtectionError> {
+        if settings.honeypot && submission.honeypot.is_some_and(|value| !value.is_empty()) {
+            return Ok(Verdict::Bot);
+        }
+
+        let now = Utc::now();
+        let mut form_token = None;
+        if let Some(min_fill_seconds) = settings.min_fill_seconds {
+            let token = submission
+                .form_token
+                .ok_or(BotRejection::MissingFormToken)?;
+            let (issued_at, nonce) = self.verify_form_token(token)?;
+            let age = now - issued_at;
+            if age < Duration::seconds(min_fill_seconds as i64) {
+                return Err(BotRejection::TooFast.into());
+            }
+            if age > Duration::hours(FORM_TOKEN_LIFETIME_HOURS) {
+                return Err(BotRejection::ExpiredFormToken.into());
+            }
+            form_token = Some((issued_at, nonce));
+        }
+
+        if let Some(captcha) = &self.captcha {
+            let response = submission
+                .captcha_response
+                .filter(|response| !response.is_empty())
+                .ok_or(BotRejection::MissingCaptcha)?;
+            let solved = captcha
+                .verify(response, remote_ip)
+                .await
+                .map_err(BotProtectionError::CaptchaUnavailable)?;
+            if !solved {
+                return Err(BotRejection::FailedCaptcha.into());
+            }
+        }
+
+        if let Some((issued_at, nonce)) = form_token {
+            let expires_at = issued_at + Duration::hours(FORM_TOKEN_LIFETIME_HOURS);
+            if !self.used_nonces.insert(nonce, expires_at, now) {
+                return Err(BotRejection::ReusedFormToken.into());
+            }
+        }
+        Ok(Verdict::Human)
+    }
+
+    /// When the form was handed out and the token's nonce, if `token` was issued by us.
+    fn verify_form_token<'a>(
+        &self,
+        token: &'a str,
+    ) -> Result<(DateTime<Utc>, &'a str), BotRejection> {
+        let mut parts = token.splitn(3, '.');
+        let (Some(issued_at), Some(nonce), Some(signature)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(BotRejection::InvalidFormToken);
+        };
+        let issued_at: i64 = issued_at
+            .parse()
+            .map_err(|_| BotRejection::InvalidFormToken)?;
+        let signature = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| BotRejection::InvalidFormToken)?;
+        self.mac(issued_at, nonce)
+            .verify_slice(&signature)
+            .map_err(|_| BotRejection::InvalidFormToken)?;
+        let issued_at = Utc
+            .timestamp_opt(issued_at, 0)
+            .single()
+            .ok_or(BotRejection::InvalidFormToken)?;
+        Ok((issued_at, nonce))
+    }
+
+    fn sign(&self, issued_at: i64, nonce: &str) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(self.mac(issued_at, nonce).finalize().into_bytes())
+    }
+
+    fn mac(&self, issued_at: i64, nonce: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(self.form_token_key.expose_secret().as_bytes())
+                .expect("HMAC accepts keys of any length");
+        // Prefixed so that no other value signed with the same key passes for a form token.
+        mac.update(format!("subscription-form:{}:{}", issued_at, nonce).as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_matches, assert_ok_eq};
+
+    fn protection() -> BotProtection {
+        BotProtection::new(Secret::new("form-token-key".into()), None)
+    }
+
+    fn settings(min_fill_seconds: Option<u64>) -> BotProtectionSettings {
+        BotProtectionSettings {
+            min_fill_seconds,
+            ..Default::default()
+        }
+    }
+
+    fn with_token(token: &str) -> FormSubmission<'_> {
+        FormSubmission {
+            form_token: Some(token),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn a_filled_honeypot_marks_a_bot() {
+        let submission = FormSubmission {
+            honeypot: Some("https://spam.example"),
+            ..Default::default()
+        };
+        assert_ok_eq!(
+            protection().check(submission, &settings(None), None).await,
+            Verdict::Bot
+        );
+
+        let disabled = BotProtectionSettings {
+            honeypot: false,
+            ..Default::default()
+        };
+        assert_ok_eq!(
+            protection().check(submission, &disabled, None).await,
+            Verdict::Human
+        );
+    }
+
+    #[tokio::test]
+    async fn form_tokens_must_be_old_enough_but_not_too_old() {
+        let protection = protection();
+        let settings = settings(Some(3));
+
+        let token = protection.issue_form_token(Utc::now() - Duration::seconds(5));
+        assert_ok_eq!(
+            protection.check(with_token(&token), &settings, None).await,
+            Verdict::Human
+        );
+
+        let token = protection.issue_form_token(Utc::now());
+        assert_matches!(
+            protection.check(with_token(&token), &settings, None).await,
+            Err(BotProtectionError::Rejected(BotRejection::TooFast))
+        );
+
+        let token = protection.issue_form_token(Utc::now() - Duration::days(2));
+        assert_matches!(
+            protection.check(with_token(&token), &settings, None).await,
+            Err(BotProtectionError::Rejected(BotRejection::ExpiredFormToken))
+        );
+
+        assert_matches!(
+            protection
+                .check(FormSubmission::default(), &settings, None)
+                .await,
+            Err(BotProtectionError::Rejected(BotRejection::MissingFormToken))
+        );
+    }
+
+    #[tokio::test]
+    async fn form_tokens_can_only_be_used_once() {
+        let protection = protection();
+        let settings = settings(Some(3));
+        let token = protection.issue_form_token(Utc::now() - Duration::seconds(5));
+
+        assert_ok_eq!(
+            protection.check(with_token(&token), &settings, None).await,
+            Verdict::Human
+        );
+        // Clones share the nonces they have seen, like the copies in each request's state.
+        assert_matches!(
+            protection
+                .clone()
+                .check(with_token(&token), &settings, None)
+                .await,
+            Err(BotProtectionError::Rejected(BotRejection::ReusedFormToken))
+        );
+
+        let another = protection.issue_form_token(Utc::now() - Duration::seconds(5));
+        assert_ok_eq!(
+            protection
+                .check(with_token(&another), &settings, None)
+                .await,
+            Verdict::Human
+        );
+    }
+
+    #[tokio::test]
+    async fn forged_form_tokens_are_rejected() {
+        let protection = protection();
+        let settings = settings(Some(3));
+        let token = protection.issue_form_token(Utc::now());
+        let (_, nonce_and_signature) = token.split_once('.').unwrap();
+        let backdated = format!("{}.{}", Utc::now().timestamp() - 60, nonce_and_signature);
+        let other_key = BotProtection::new(Secret::new("another-key".into()), None)
+            .issue_form_token(Utc::now() - Duration::seconds(60));
+
+        for token in [
+            backdated.as_str(),
+            &other_key,
+            "",
+            "not-a-token",
+            "1.2",
+            "1.2.3",
+        ] {
+            assert_matches!(
+                protection.check(with_token(token), &settings, None).await,
+                Err(BotProtectionError::Rejected(BotRejection::InvalidFormToken))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn captchas_are_only_required_with_a_verifier() {
+        let submission = FormSubmission {
+            captcha_response: Some("solved"),
+            ..Default::default()
+        };
+        let settings = settings(None);
+        assert_ok_eq!(
+            protection()
+                .check(FormSubmission::default(), &settings, None)
+                .await,
+            Verdict::Human
+        );
+
+        let protection =
+            protection().with_captcha_verifier(Arc::new(MockCaptchaVerifier::accepting("solved")));
+        assert_ok_eq!(
+            protection.check(submission, &settings, None).await,
+            Verdict::Human
+        );
+        assert_matches!(
+            protection
+                .check(FormSubmission::default(), &settings, None)
+                .await,
+            Err(BotProtectionError::Rejected(BotRejection::MissingCaptcha))
+        );
+        let wrong = FormSubmission {
+            captcha_response: Some("guessed"),
+            ..Default::default()
+        };
+        assert_matches!(
+            protection.check(wrong, &settings, None).await,
+            Err(BotProtectionError::Rejected(BotRejection::FailedCaptcha))
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::import::DEFAULT_BATCH_SIZE;
+
+#[derive(Parser, Debug)]
+#[command(name = "zero2prod", version, about = "Email newsletter service")]
+pub struct Cli {
+    /// Defaults to `serve`.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Run the HTTP server.
+    Serve,
+    /// Apply the database migrations embedded in this binary.
+    Migrate {
+        /// List the pending migrations without applying them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Load and validate the configuration, then print it with secrets redacted.
+    CheckConfig {
+        #[arg(long, env = "APP_ENVIRONMENT", default_value = "local")]
+        environment: String,
+    },
+    /// Create an admin user. The password is read from the first line of stdin.
+    CreateAdmin {
+        #[arg(long)]
+        username: String,
+    },
+    /// Import subscribers from a CSV file with `email` and `name` columns.
+    ImportSubscribers {
+        /// Path of the CSV file, `-` to read it from stdin.
+        file: PathBuf,
+        /// Store the subscribers as confirmed rather than pending confirmation.
+        #[arg(long)]
+        confirmed: bool,
+        #[arg(long, default_value_t = DEFAULT_BATCH_SIZE)]
+        batch_size: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_subcommand_means_serve() {
+        let cli = Cli::try_parse_from(["zero2prod"]).unwrap();
+        assert_eq!(cli.command, None);
+    }
+
+    #[test]
+    fn migrate_accepts_dry_run() {
+        let cli = Cli::try_parse_from(["zero2prod", "migrate", "--dry-run"]).unwrap();
+        assert_eq!(cli.command, Some(Command::Migrate { dry_run: true }));
+    }
+
+    #[test]
+    fn create_admin_requires_a_username() {
+        assert!(Cli::try_parse_from(["zero2prod", "create-admin"]).is_err());
+    }
+
+    #[test]
+    fn import_subscribers_defaults_to_pending_confirmation() {
+        let cli = Cli::try_parse_from(["zero2prod", "import-subscribers", "list.csv"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Command::ImportSubscribers {
+                file: "list.csv".into(),
+                confirmed: false,
+                batch_size: DEFAULT_BATCH_SIZE,
+            })
+        );
+    }
+
+    #[test]
+    fn the_command_line_definition_is_consistent() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+}
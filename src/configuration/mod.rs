@@ -0,0 +1,471 @@
+mod reload;
+mod secrets;
+mod validation;
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::domain::{NameRules, SubscriberEmail};
+use crate::error::AppError;
+use crate::rate_limit::{RateLimitStoreKind, TokenBucket};
+use anyhow::{anyhow, Context};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use serde_aux::prelude::deserialize_number_from_string;
+use sqlx::postgres::{PgConnectOptions, PgSslMode};
+
+pub use reload::{ConfigWatcher, SettingsHandle};
+pub use secrets::{
+    default_secret_providers, DirectorySecretProvider, EnvFileSecretProvider, SecretProvider,
+    SECRET_KEYS,
+};
+pub use validation::{ValidationErrors, ValidationIssue};
+
+#[derive(Deserialize, Debug)]
+pub struct Settings {
+    pub database: DatabaseSettings,
+    pub application: ApplicationSettings,
+    pub email_client: EmailClientSettings,
+    /// Reloadable at runtime.
+    #[serde(default)]
+    pub subscriber_names: NameRules,
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
+    #[serde(default)]
+    pub bot_protection: BotProtectionSettings,
+    /// Named on/off switches, reloadable at runtime.
+    #[serde(default)]
+    pub features: HashMap<String, bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ApplicationSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    /// Overrides the `APP_LOG_LEVEL` used at startup, reloadable at runtime.
+    #[serde(default)]
+    pub log_level: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct DatabaseSettings {
+    pub username: String,
+    pub password: Secret<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub host: String,
+    pub database_name: String,
+    pub require_ssl: bool,
+    /// Apply the embedded migrations when the server starts.
+    #[serde(default)]
+    pub migrate_on_startup: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EmailClientSettings {
+    pub base_url: String,
+    pub sender_email: String,
+    /// Shown next to the sender address.
+    #[serde(default)]
+    pub sender_name: Option<String>,
+    /// Postmark's server token, unused when sending through `smtp`.
+    #[serde(default)]
+    pub authorization_token: Option<Secret<String>>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    /// The password of the Basic credentials in the URL of Postmark's bounce webhook,
+    /// which is refused while this is unset.
+    #[serde(default)]
+    pub webhook_secret: Option<Secret<String>>,
+    /// When set, emails go to this SMTP server instead of Postmark's API.
+    #[serde(default)]
+    pub smtp: Option<SmtpSettings>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    /// Upgrade a plain connection with `STARTTLS`, refusing servers that do not offer it.
+    #[default]
+    Starttls,
+    /// Connect over TLS from the start, usually on port 465.
+    Implicit,
+    /// Plain text, for local relays only.
+    None,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpAuthMechanism {
+    Plain,
+    Login,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    /// Authenticate when set, together with `password`.
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<Secret<String>>,
+    /// In order of preference, among those the server offers.
+    #[serde(default = "default_smtp_authentication")]
+    pub authentication: Vec<SmtpAuthMechanism>,
+    /// Connections kept open and reused between emails.
+    #[serde(
+        default = "default_smtp_pool_size",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub pool_size: usize,
+    /// Connections are closed after sending this many emails, as many servers limit it.
+    #[serde(
+        default = "default_smtp_max_messages_per_connection",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub max_messages_per_connection: u32,
+    /// Idle connections older than this are closed rather than reused.
+    #[serde(
+        default = "default_smtp_idle_timeout_seconds",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub idle_timeout_seconds: u64,
+}
+
+fn default_smtp_authentication() -> Vec<SmtpAuthMechanism> {
+    vec![SmtpAuthMechanism::Plain, SmtpAuthMechanism::Login]
+}
+
+fn default_smtp_pool_size() -> usize {
+    4
+}
+
+fn default_smtp_max_messages_per_connection() -> u32 {
+    100
+}
+
+fn default_smtp_idle_timeout_seconds() -> u64 {
+    60
+}
+
+impl SmtpSettings {
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_seconds)
+    }
+}
+
+/// Limits on `POST /subscriptions`. The buckets and proxies are reloadable at runtime,
+/// the store is not.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    pub store: RateLimitStoreKind,
+    /// Proxies whose `X-Forwarded-For` header is trusted to carry the client IP.
+    pub trusted_proxies: Vec<IpAddr>,
+    pub per_ip: TokenBucket,
+    /// Protects the owners of the addresses, whoever sends the requests.
+    pub per_email: TokenBucket,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            store: RateLimitStoreKind::default(),
+            trusted_proxies: vec![],
+            per_ip: TokenBucket {
+                capacity: 10,
+                refill_per_hour: 30,
+            },
+            per_email: TokenBucket {
+                capacity: 3,
+                refill_per_hour: 3,
+            },
+        }
+    }
+}
+
+/// Anti-bot checks on `POST /subscriptions`, reloadable at runtime except for `captcha`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BotProtectionSettings {
+    /// Silently drop submissions that fill in the hidden `website` field.
+    pub honeypot: bool,
+    /// When set, require a token from `GET /subscriptions/form-token` issued at least
+    /// this many seconds before the form is submitted.
+    pub min_fill_seconds: Option<u64>,
+    /// When set, require a solved captcha.
+    pub captcha: Option<CaptchaSettings>,
+}
+
+impl Default for BotProtectionSettings {
+    fn default() -> Self {
+        Self {
+            honeypot: true,
+            min_fill_seconds: None,
+            captcha: None,
+        }
+    }
+}
+
+/// An hCaptcha or Turnstile-style `siteverify` endpoint.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CaptchaSettings {
+    pub verify_url: String,
+    pub secret_key: Secret<String>,
+    #[serde(
+        default = "default_captcha_timeout_milliseconds",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub timeout_milliseconds: u64,
+    /// Shown on the signup page, reloadable at runtime.
+    #[serde(default)]
+    pub widget: Option<CaptchaWidget>,
+}
+
+/// The provider's script and the element it turns into a captcha.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CaptchaWidget {
+    pub script_url: String,
+    /// `h-captcha` for hCaptcha, `cf-turnstile` for Turnstile.
+    pub class: String,
+    pub site_key: String,
+}
+
+fn default_captcha_timeout_milliseconds() -> u64 {
+    5000
+}
+
+impl CaptchaSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
+impl EmailClientSettings {
+    pub fn sender(&self) -> anyhow::Result<SubscriberEmail> {
+        SubscriberEmail::parse(self.sender_email.clone())
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
+pub fn get_configuration() -> Result<Settings, AppError> {
+    load_configuration(
+        &configuration_directory()?,
+        &current_environment()?,
+        &default_secret_providers(),
+    )
+}
+
+/// The `configuration` directory next to the current working directory.
+pub fn configuration_directory() -> Result<PathBuf, AppError> {
+    let base_path = std::env::current_dir().context("Failed to determine the current directory")?;
+    Ok(base_path.join("configuration"))
+}
+
+/// Detect the running environment.
+/// Default to `local` if unspecified.
+pub fn current_environment() -> Result<Environment, AppError> {
+    let environment = std::env::var("APP_ENVIRONMENT")
+        .unwrap_or_else(|_| "local".into())
+        .try_into()
+        .context("Failed to parse APP_ENVIRONMENT.")?;
+    Ok(environment)
+}
+
+/// Load the settings for `environment` from `configuration_dir`, layering
+/// `base.yaml`, `<environment>.yaml`, `APP_`-prefixed environment variables
+/// and finally the values returned by `secret_providers`, then validate the result.
+pub fn load_configuration(
+    configuration_dir: &Path,
+    environment: &Environment,
+    secret_providers: &[Box<dyn SecretProvider>],
+) -> Result<Settings, AppError> {
+    let environment_file = configuration_dir.join(format!("{}.yaml", environment.as_str()));
+    if !environment_file.is_file() {
+        return Err(anyhow!(
+            "No configuration file found for the `{}` environment at {:?}.",
+            environment.as_str(),
+            environment_file
+        )
+        .into());
+    }
+
+    // Initialise our configuration reader
+    let builder = config::Config::builder()
+        .add_source(config::File::from(configuration_dir.join("base.yaml")))
+        .add_source(config::File::from(environment_file))
+        .add_source(
+            config::Environment::with_prefix("APP")
+                .prefix_separator("_")
+                .separator("__"),
+        );
+    let settings = secrets::apply_secrets(builder, secret_providers)?.build()?;
+    Ok(validation::parse_settings(&settings)?)
+}
+
+impl Settings {
+    pub fn feature_enabled(&self, name: &str) -> bool {
+        self.features.get(name).copied().unwrap_or(false)
+    }
+}
+
+impl DatabaseSettings {
+    pub fn without_db(&self) -> PgConnectOptions {
+        let ssl_mode = if self.require_ssl {
+            PgSslMode::Require
+        } else {
+            PgSslMode::Prefer
+        };
+        PgConnectOptions::new()
+            .host(&self.host)
+            .username(&self.username)
+            .password(self.password.expose_secret())
+            .port(self.port)
+            .ssl_mode(ssl_mode)
+    }
+
+    pub fn with_db(&self) -> PgConnectOptions {
+        self.without_db().database(&self.database_name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Environment {
+    Local,
+    Test,
+    Staging,
+    Production,
+    /// Any other environment, backed by `configuration/<name>.yaml`.
+    Custom(String),
+}
+
+impl Environment {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Environment::Local => "local",
+            Environment::Test => "test",
+            Environment::Staging => "staging",
+            Environment::Production => "production",
+            Environment::Custom(name) => name,
+        }
+    }
+}
+
+impl TryFrom<String> for Environment {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(Self::Local),
+            "test" => Ok(Self::Test),
+            "staging" => Ok(Self::Staging),
+            "production" => Ok(Self::Production),
+            // `base` holds the shared defaults, it is not an environment on its own.
+            "base" | "" => Err(anyhow!("{:?} is not a supported environment.", s)),
+            other => {
+                // The name ends up in a file path: keep it to a single, plain path segment.
+                let is_valid_name = other
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+                if is_valid_name {
+                    Ok(Self::Custom(other.to_owned()))
+                } else {
+                    Err(anyhow!(
+                        "{} is not a supported environment. Use `local`, `test`, `staging`, `production` \
+                        or the name of a file in the `configuration` directory.",
+                        other
+                    ))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn known_environments_are_parsed_case_insensitively() {
+        assert_ok_eq!(
+            Environment::try_from("Local".to_string()),
+            Environment::Local
+        );
+        assert_ok_eq!(Environment::try_from("TEST".to_string()), Environment::Test);
+        assert_ok_eq!(
+            Environment::try_from("staging".to_string()),
+            Environment::Staging
+        );
+        assert_ok_eq!(
+            Environment::try_from("production".to_string()),
+            Environment::Production
+        );
+    }
+
+    #[test]
+    fn other_names_are_custom_environments() {
+        assert_ok_eq!(
+            Environment::try_from("qa-eu_1".to_string()),
+            Environment::Custom("qa-eu_1".into())
+        );
+    }
+
+    #[test]
+    fn environment_names_that_are_not_a_plain_file_name_are_rejected() {
+        for name in ["", "base", "../secrets", "prod/eu", "prod.eu"] {
+            assert_err!(Environment::try_from(name.to_string()));
+        }
+    }
+
+    struct StaticSecretProvider;
+
+    impl SecretProvider for StaticSecretProvider {
+        fn get_secret(&self, _key: &str) -> anyhow::Result<Option<Secret<String>>> {
+            Ok(Some(Secret::new("s3cr3t".into())))
+        }
+    }
+
+    #[test]
+    fn every_bundled_environment_loads_and_validates() {
+        let configuration_dir = std::env::current_dir().unwrap().join("configuration");
+        let secret_providers: Vec<Box<dyn SecretProvider>> = vec![Box::new(StaticSecretProvider)];
+        for environment in [
+            Environment::Local,
+            Environment::Test,
+            Environment::Staging,
+            Environment::Production,
+        ] {
+            if let Err(e) = load_configuration(&configuration_dir, &environment, &secret_providers)
+            {
+                panic!("{} failed to load: {}", environment.as_str(), e);
+            }
+        }
+    }
+
+    #[test]
+    fn production_requires_the_database_password_from_a_secret_provider() {
+        let configuration_dir = std::env::current_dir().unwrap().join("configuration");
+        let error =
+            load_configuration(&configuration_dir, &Environment::Production, &[]).unwrap_err();
+        assert!(error.to_string().contains("database.password: is missing"));
+    }
+
+    #[test]
+    fn an_environment_without_a_configuration_file_is_rejected() {
+        let configuration_dir = std::env::current_dir().unwrap().join("configuration");
+        let environment = Environment::Custom("does-not-exist".into());
+        assert_err!(load_configuration(&configuration_dir, &environment, &[]));
+    }
+}
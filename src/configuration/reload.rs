@@ -0,0 +1,372 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context};
+use secrecy::ExposeSecret;
+use tokio::sync::watch;
+use tracing::{error, info};
+
+use super::{load_configuration, Environment, SecretProvider, Settings};
+
+/// A cheap, cloneable view over the latest valid [`Settings`].
+#[derive(Clone)]
+pub struct SettingsHandle(watch::Receiver<Arc<Settings>>);
+
+impl SettingsHandle {
+    /// A handle that never changes, for when no [`ConfigWatcher`] is running.
+    pub fn fixed(settings: Settings) -> Self {
+        let (_, receiver) = watch::channel(Arc::new(settings));
+        Self(receiver)
+    }
+
+    pub fn current(&self) -> Arc<Settings> {
+        self.0.borrow().clone()
+    }
+
+    /// Resolves once a new configuration has been published.
+    /// Fails if the watcher has been dropped.
+    pub async fn changed(&mut self) -> anyhow::Result<Arc<Settings>> {
+        self.0
+            .changed()
+            .await
+            .context("The configuration watcher has stopped")?;
+        Ok(self.current())
+    }
+}
+
+/// Re-reads the configuration directory when it or a secret file changes on disk, or
+/// when the process receives `SIGHUP`, publishing the result to every [`SettingsHandle`].
+pub struct ConfigWatcher {
+    configuration_dir: PathBuf,
+    environment: Environment,
+    secret_providers: Vec<Box<dyn SecretProvider>>,
+    sender: watch::Sender<Arc<Settings>>,
+    fingerprint: Vec<(PathBuf, SystemTime, u64)>,
+}
+
+impl ConfigWatcher {
+    pub fn new(
+        configuration_dir: PathBuf,
+        environment: Environment,
+        secret_providers: Vec<Box<dyn SecretProvider>>,
+        initial: Settings,
+    ) -> Self {
+        let (sender, _) = watch::channel(Arc::new(initial));
+        let mut watcher = Self {
+            configuration_dir,
+            environment,
+            secret_providers,
+            sender,
+            fingerprint: vec![],
+        };
+        watcher.fingerprint = watcher.fingerprint();
+        watcher
+    }
+
+    /// The configuration directory, then the secret files and directories.
+    fn watched_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.configuration_dir.clone()];
+        for provider in &self.secret_providers {
+            paths.extend(provider.watched_paths());
+        }
+        paths
+    }
+
+    fn fingerprint(&self) -> Vec<(PathBuf, SystemTime, u64)> {
+        fingerprint(&self.watched_paths())
+    }
+
+    pub fn handle(&self) -> SettingsHandle {
+        SettingsHandle(self.sender.subscribe())
+    }
+
+    /// Load and validate the configuration again, publishing it if only reloadable
+    /// settings changed. The current settings stay in place on failure.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let new = load_configuration(
+            &self.configuration_dir,
+            &self.environment,
+            &self.secret_providers,
+        )?;
+        let changed = non_reloadable_changes(&self.sender.borrow(), &new);
+        if !changed.is_empty() {
+            bail!(
+                "Refusing to reload: {} cannot change without a restart",
+                changed.join(", ")
+            );
+        }
+        self.sender.send_replace(Arc::new(new));
+        Ok(())
+    }
+
+    /// Watch for changes until the process shuts down.
+    pub async fn run(mut self, poll_interval: Duration) {
+        #[cfg(unix)]
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+            .expect("Failed to install SIGHUP handler");
+        let mut interval = tokio::time::interval(poll_interval);
+        loop {
+            #[cfg(unix)]
+            let hangup_received = hangup.recv();
+            #[cfg(not(unix))]
+            let hangup_received = std::future::pending::<Option<()>>();
+
+            tokio::select! {
+                _ = hangup_received => info!("SIGHUP received, reloading configuration"),
+                _ = interval.tick() => {
+                    let current = self.fingerprint();
+                    if current == self.fingerprint {
+                        continue;
+                    }
+                    self.fingerprint = current;
+                    info!("Configuration or secret files changed, reloading configuration");
+                }
+            }
+            match self.reload() {
+                Ok(_) => info!("Configuration reloaded"),
+                Err(e) => error!("Failed to reload configuration: {:?}", e),
+            }
+        }
+    }
+}
+
+/// Settings that are only read at startup: the listening port, the values copied
+/// into the application state and the database connection the pool was built from.
+fn non_reloadable_changes(old: &Settings, new: &Settings) -> Vec<&'static str> {
+    let mut changed = vec![];
+    let mut check = |is_same: bool, key| {
+        if !is_same {
+            changed.push(key);
+        }
+    };
+    check(
+        old.application.port == new.application.port,
+        "application.port",
+    );
+    check(
+        old.application.base_url == new.application.base_url,
+        "application.base_url",
+    );
+    check(
+        old.application.hmac_secret.expose_secret() == new.application.hmac_secret.expose_secret(),
+        "application.hmac_secret",
+    );
+    check(old.database.host == new.database.host, "database.host");
+    check(old.database.port == new.database.port, "database.port");
+    check(
+        old.database.username == new.database.username,
+        "database.username",
+    );
+    check(
+        old.database.password.expose_secret() == new.database.password.expose_secret(),
+        "database.password",
+    );
+    check(
+        old.database.database_name == new.database.database_name,
+        "database.database_name",
+    );
+    check(
+        old.database.require_ssl == new.database.require_ssl,
+        "database.require_ssl",
+    );
+    // The email client is built once at startup.
+    check(
+        old.email_client.base_url == new.email_client.base_url,
+        "email_client.base_url",
+    );
+    check(
+        old.email_client.sender_email == new.email_client.sender_email,
+        "email_client.sender_email",
+    );
+    let token = |settings: &Settings| {
+        settings
+            .email_client
+            .authorization_token
+            .as_ref()
+            .map(|token| token.expose_secret().clone())
+    };
+    check(token(old) == token(new), "email_client.authorization_token");
+    check(
+        old.email_client.timeout_milliseconds == new.email_client.timeout_milliseconds,
+        "email_client.timeout_milliseconds",
+    );
+    let smtp = |settings: &Settings| {
+        settings.email_client.smtp.as_ref().map(|smtp| {
+            (
+                smtp.host.clone(),
+                smtp.port,
+                smtp.tls,
+                smtp.username.clone(),
+                smtp.password.as_ref().map(|p| p.expose_secret().clone()),
+                smtp.authentication.clone(),
+                smtp.pool_size,
+                smtp.max_messages_per_connection,
+                smtp.idle_timeout_seconds,
+            )
+        })
+    };
+    check(smtp(old) == smtp(new), "email_client.smtp");
+    check(
+        old.rate_limit.store == new.rate_limit.store,
+        "rate_limit.store",
+    );
+    // So is the captcha verifier.
+    let captcha = |settings: &Settings| {
+        settings.bot_protection.captcha.as_ref().map(|captcha| {
+            (
+                captcha.verify_url.clone(),
+                captcha.secret_key.expose_secret().clone(),
+                captcha.timeout_milliseconds,
+            )
+        })
+    };
+    check(captcha(old) == captcha(new), "bot_protection.captcha");
+    changed
+}
+
+/// The modification time and size of each file in `paths`, or in the directories
+/// among them. Missing files are left out, so that their appearance is a change too.
+fn fingerprint(paths: &[PathBuf]) -> Vec<(PathBuf, SystemTime, u64)> {
+    let mut entries = vec![];
+    for path in paths {
+        match std::fs::read_dir(path) {
+            Ok(directory) => {
+                for entry in directory.flatten() {
+                    entries.extend(file_fingerprint(&entry.path()));
+                }
+            }
+            Err(_) => entries.extend(file_fingerprint(path)),
+        }
+    }
+    entries.sort();
+    entries
+}
+
+fn file_fingerprint(path: &Path) -> Option<(PathBuf, SystemTime, u64)> {
+    // Follow symlinks: Kubernetes swaps ConfigMap and Secret contents by re-pointing them.
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((path.to_owned(), metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::{DirectorySecretProvider, EnvFileSecretProvider};
+    use crate::email_client::EmailClient;
+    use claims::{assert_err, assert_ok};
+    use uuid::Uuid;
+
+    const BASE: &str = "application:\n  port: 8000\n  base_url: \"http://127.0.0.1:8000\"\n  \
+        hmac_secret: secret\nemail_client:\n  base_url: \"http://127.0.0.1:8025\"\n  \
+        sender_email: newsletter@example.com\n  authorization_token: token\n  timeout_milliseconds: 1000\n";
+
+    fn configuration_dir(local: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("base.yaml"), BASE).unwrap();
+        std::fs::write(directory.join("local.yaml"), local).unwrap();
+        directory
+    }
+
+    fn local(host: &str, log_level: &str) -> String {
+        format!(
+            "application:\n  log_level: {}\ndatabase:\n  host: {}\n  port: 5432\n  username: postgres\n  \
+            password: password\n  database_name: newsletter\n  require_ssl: false\n",
+            log_level, host
+        )
+    }
+
+    fn watcher(directory: &Path) -> ConfigWatcher {
+        let initial = load_configuration(directory, &Environment::Local, &[]).unwrap();
+        ConfigWatcher::new(directory.to_owned(), Environment::Local, vec![], initial)
+    }
+
+    #[test]
+    fn reloadable_changes_are_published() {
+        let directory = configuration_dir(&local("localhost", "info"));
+        let watcher = watcher(&directory);
+        let handle = watcher.handle();
+
+        std::fs::write(directory.join("local.yaml"), local("localhost", "debug")).unwrap();
+        assert_ok!(watcher.reload());
+        assert_eq!(
+            handle.current().application.log_level.as_deref(),
+            Some("debug")
+        );
+    }
+
+    #[test]
+    fn the_email_client_follows_reloads_of_the_sender_name() {
+        let directory = configuration_dir(&local("localhost", "info"));
+        let watcher = watcher(&directory);
+        let email_client = EmailClient::from_settings(&watcher.handle()).unwrap();
+
+        let renamed = format!(
+            "{}email_client:\n  sender_name: Renamed\n",
+            local("localhost", "info")
+        );
+        std::fs::write(directory.join("local.yaml"), renamed).unwrap();
+        assert_ok!(watcher.reload());
+        assert_eq!(email_client.sender().name.as_deref(), Some("Renamed"));
+    }
+
+    #[test]
+    fn secret_files_are_watched_along_with_the_configuration_directory() {
+        let directory = configuration_dir(&local("localhost", "info"));
+        let secrets = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&secrets).unwrap();
+        let password_file = secrets.join("db_password");
+        std::fs::write(&password_file, "password").unwrap();
+        let providers: Vec<Box<dyn SecretProvider>> = vec![
+            Box::new(EnvFileSecretProvider::new(
+                [(
+                    "APP_DATABASE__PASSWORD_FILE".to_string(),
+                    password_file.display().to_string(),
+                )]
+                .into(),
+            )),
+            Box::new(DirectorySecretProvider::new(&secrets)),
+        ];
+        let initial = load_configuration(&directory, &Environment::Local, &providers).unwrap();
+        let watcher = ConfigWatcher::new(directory, Environment::Local, providers, initial);
+        let before = watcher.fingerprint();
+
+        std::fs::write(secrets.join("application.hmac_secret"), "rotated").unwrap();
+        let after_new_secret = watcher.fingerprint();
+        assert_ne!(before, after_new_secret);
+
+        std::fs::write(&password_file, "a longer password").unwrap();
+        assert_ne!(after_new_secret, watcher.fingerprint());
+    }
+
+    #[test]
+    fn reloads_changing_the_database_host_are_rejected() {
+        let directory = configuration_dir(&local("localhost", "info"));
+        let watcher = watcher(&directory);
+        let handle = watcher.handle();
+
+        std::fs::write(directory.join("local.yaml"), local("elsewhere", "debug")).unwrap();
+        let error = assert_err!(watcher.reload());
+        assert!(error.to_string().contains("database.host"));
+        assert_eq!(handle.current().database.host, "localhost");
+        assert_eq!(
+            handle.current().application.log_level.as_deref(),
+            Some("info")
+        );
+    }
+
+    #[test]
+    fn invalid_configurations_are_not_published() {
+        let directory = configuration_dir(&local("localhost", "info"));
+        let watcher = watcher(&directory);
+        let handle = watcher.handle();
+
+        std::fs::write(directory.join("local.yaml"), local("localhost", "loud")).unwrap();
+        assert_err!(watcher.reload());
+        assert_eq!(
+            handle.current().application.log_level.as_deref(),
+            Some("info")
+        );
+    }
+}
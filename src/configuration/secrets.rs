@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+
+/// Settings that hold credentials and can be resolved through a [`SecretProvider`]
+/// instead of being written in the YAML files.
+pub const SECRET_KEYS: &[&str] = &[
+    "database.password",
+    "application.hmac_secret",
+    "email_client.authorization_token",
+    "email_client.webhook_secret",
+    "email_client.smtp.password",
+    "bot_protection.captcha.secret_key",
+];
+
+/// A source of secret values, looked up by their settings key (e.g. `database.password`).
+pub trait SecretProvider: Send + Sync {
+    /// Returns `Ok(None)` when this provider does not know about `key`.
+    fn get_secret(&self, key: &str) -> anyhow::Result<Option<Secret<String>>>;
+
+    /// The files and directories the secrets are read from, watched for changes
+    /// along with the configuration directory.
+    fn watched_paths(&self) -> Vec<PathBuf> {
+        vec![]
+    }
+}
+
+/// Resolves each key from the file named by its `APP_<KEY>_FILE` variable (e.g. `APP_DATABASE__PASSWORD_FILE`),
+/// the convention used by Docker and Kubernetes secret mounts.
+pub struct EnvFileSecretProvider {
+    vars: HashMap<String, String>,
+}
+
+impl EnvFileSecretProvider {
+    pub fn new(vars: HashMap<String, String>) -> Self {
+        Self { vars }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(std::env::vars().collect())
+    }
+
+    fn variable_name(key: &str) -> String {
+        format!("APP_{}_FILE", key.replace('.', "__").to_uppercase())
+    }
+}
+
+impl SecretProvider for EnvFileSecretProvider {
+    fn get_secret(&self, key: &str) -> anyhow::Result<Option<Secret<String>>> {
+        let variable = Self::variable_name(key);
+        match self.vars.get(&variable) {
+            Some(path) => read_secret_file(Path::new(path))
+                .with_context(|| format!("Failed to resolve {} from {}", key, variable))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn watched_paths(&self) -> Vec<PathBuf> {
+        SECRET_KEYS
+            .iter()
+            .filter_map(|key| self.vars.get(&Self::variable_name(key)))
+            .map(PathBuf::from)
+            .collect()
+    }
+}
+
+/// Resolves secrets from a directory holding one file per key, e.g. `/run/secrets/database.password`.
+/// Works offline, without any secret manager.
+pub struct DirectorySecretProvider {
+    directory: PathBuf,
+}
+
+impl DirectorySecretProvider {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+impl SecretProvider for DirectorySecretProvider {
+    fn get_secret(&self, key: &str) -> anyhow::Result<Option<Secret<String>>> {
+        let path = self.directory.join(key);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        read_secret_file(&path)
+            .with_context(|| format!("Failed to resolve {}", key))
+            .map(Some)
+    }
+
+    fn watched_paths(&self) -> Vec<PathBuf> {
+        vec![self.directory.clone()]
+    }
+}
+
+/// The providers used by `get_configuration`: `*_FILE` variables first, then the
+/// directory named by `APP_SECRETS_DIR`, if any.
+pub fn default_secret_providers() -> Vec<Box<dyn SecretProvider>> {
+    let mut providers: Vec<Box<dyn SecretProvider>> =
+        vec![Box::new(EnvFileSecretProvider::from_env())];
+    if let Ok(directory) = std::env::var("APP_SECRETS_DIR") {
+        providers.push(Box::new(DirectorySecretProvider::new(directory)));
+    }
+    providers
+}
+
+/// Ask each provider in turn for every known secret, applying the first value found
+/// on top of the other configuration sources. Secrets of optional sub-sections, such
+/// as `email_client.smtp`, are only applied when the sub-section is configured, so
+/// that a secret alone does not turn it on.
+pub(super) fn apply_secrets(
+    mut builder: config::ConfigBuilder<config::builder::DefaultState>,
+    providers: &[Box<dyn SecretProvider>],
+) -> anyhow::Result<config::ConfigBuilder<config::builder::DefaultState>> {
+    let configured = builder.build_cloned()?;
+    for key in SECRET_KEYS {
+        if let Some((section, _)) = key.rsplit_once('.').filter(|(s, _)| s.contains('.')) {
+            if configured.get_table(section).is_err() {
+                continue;
+            }
+        }
+        for provider in providers {
+            if let Some(secret) = provider.get_secret(key)? {
+                builder = builder.set_override(*key, secret.expose_secret().as_str())?;
+                break;
+            }
+        }
+    }
+    Ok(builder)
+}
+
+fn read_secret_file(path: &Path) -> anyhow::Result<Secret<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read secret file {:?}", path))?;
+    // Secret files are usually written with a trailing newline.
+    Ok(Secret::new(
+        content.trim_end_matches(['\r', '\n']).to_owned(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::SmtpSettings;
+    use claims::{assert_err, assert_none, assert_ok};
+    use uuid::Uuid;
+
+    fn secrets_directory() -> PathBuf {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn env_file_provider_reads_the_file_and_strips_the_trailing_newline() {
+        let directory = secrets_directory();
+        let path = directory.join("db_password");
+        std::fs::write(&path, "s3cr3t\n").unwrap();
+        let provider = EnvFileSecretProvider::new(HashMap::from([(
+            "APP_DATABASE__PASSWORD_FILE".to_string(),
+            path.to_string_lossy().into_owned(),
+        )]));
+
+        let secret = provider.get_secret("database.password").unwrap().unwrap();
+        assert_eq!(secret.expose_secret(), "s3cr3t");
+    }
+
+    #[test]
+    fn env_file_provider_fails_when_the_file_is_missing() {
+        let provider = EnvFileSecretProvider::new(HashMap::from([(
+            "APP_DATABASE__PASSWORD_FILE".to_string(),
+            "/definitely/not/here".to_string(),
+        )]));
+        assert_err!(provider.get_secret("database.password"));
+    }
+
+    #[test]
+    fn env_file_provider_ignores_unset_keys() {
+        let provider = EnvFileSecretProvider::new(HashMap::new());
+        assert_none!(provider.get_secret("database.password").unwrap());
+    }
+
+    #[test]
+    fn directory_provider_reads_one_file_per_key() {
+        let directory = secrets_directory();
+        std::fs::write(directory.join("database.password"), "s3cr3t").unwrap();
+        let provider = DirectorySecretProvider::new(&directory);
+
+        let secret = provider.get_secret("database.password").unwrap().unwrap();
+        assert_eq!(secret.expose_secret(), "s3cr3t");
+        assert_none!(provider.get_secret("unknown.key").unwrap());
+    }
+
+    #[test]
+    fn the_smtp_password_can_be_read_from_a_file() {
+        let directory = secrets_directory();
+        let path = directory.join("smtp_password");
+        std::fs::write(&path, "smtp-password\n").unwrap();
+        let providers: Vec<Box<dyn SecretProvider>> =
+            vec![Box::new(EnvFileSecretProvider::new(HashMap::from([(
+                "APP_EMAIL_CLIENT__SMTP__PASSWORD_FILE".to_string(),
+                path.to_string_lossy().into_owned(),
+            )])))];
+        let builder = config::Config::builder()
+            .set_default("email_client.smtp.host", "localhost")
+            .unwrap()
+            .set_default("email_client.smtp.port", 1025)
+            .unwrap();
+
+        let config = assert_ok!(apply_secrets(builder, &providers))
+            .build()
+            .unwrap();
+        let smtp: SmtpSettings = config.get("email_client.smtp").unwrap();
+        assert_eq!(smtp.password.unwrap().expose_secret(), "smtp-password");
+    }
+
+    #[test]
+    fn the_captcha_secret_key_is_only_applied_when_a_captcha_is_configured() {
+        let directory = secrets_directory();
+        std::fs::write(
+            directory.join("bot_protection.captcha.secret_key"),
+            "s3cr3t",
+        )
+        .unwrap();
+        let providers: Vec<Box<dyn SecretProvider>> =
+            vec![Box::new(DirectorySecretProvider::new(&directory))];
+
+        let config = assert_ok!(apply_secrets(config::Config::builder(), &providers))
+            .build()
+            .unwrap();
+        assert_err!(config.get_table("bot_protection.captcha"));
+
+        let builder = config::Config::builder()
+            .set_default("bot_protection.captcha.verify_url", "https://example.com")
+            .unwrap();
+        let config = assert_ok!(apply_secrets(builder, &providers))
+            .build()
+            .unwrap();
+        assert_eq!(
+            config
+                .get_string("bot_protection.captcha.secret_key")
+                .unwrap(),
+            "s3cr3t"
+        );
+    }
+
+    #[test]
+    fn the_first_provider_with_a_value_wins() {
+        let first = secrets_directory();
+        let second = secrets_directory();
+        std::fs::write(first.join("database.password"), "first").unwrap();
+        std::fs::write(second.join("database.password"), "second").unwrap();
+        // Only known to the second provider.
+        std::fs::write(second.join("application.hmac_secret"), "second").unwrap();
+        let providers: Vec<Box<dyn SecretProvider>> = vec![
+            Box::new(DirectorySecretProvider::new(&first)),
+            Box::new(DirectorySecretProvider::new(&second)),
+        ];
+
+        let config = assert_ok!(apply_secrets(config::Config::builder(), &providers))
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.get_string("database.password").unwrap(),
+            "first".to_string()
+        );
+        assert_eq!(
+            config.get_string("application.hmac_secret").unwrap(),
+            "second".to_string()
+        );
+    }
+}
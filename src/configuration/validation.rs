@@ -0,0 +1,524 @@
+use std::fmt;
+
+use secrecy::ExposeSecret;
+use tracing::log::LevelFilter;
+use url::Url;
+
+use config::{ConfigError, ValueKind};
+use serde::de::DeserializeOwned;
+use serde_path_to_error::Segment;
+
+use super::{
+    ApplicationSettings, BotProtectionSettings, DatabaseSettings, EmailClientSettings,
+    RateLimitSettings, Settings,
+};
+use crate::domain::NameRules;
+
+/// Keys that must be provided by at least one configuration source.
+const REQUIRED_KEYS: &[&str] = &[
+    "application.port",
+    "application.base_url",
+    "application.hmac_secret",
+    "database.host",
+    "database.port",
+    "database.username",
+    "database.password",
+    "database.database_name",
+    "database.require_ssl",
+    "email_client.base_url",
+    "email_client.sender_email",
+    "email_client.timeout_milliseconds",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub key: String,
+    pub message: String,
+}
+
+/// Every problem found in a configuration, reported together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationErrors(pub Vec<ValidationIssue>);
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for issue in &self.0 {
+            write!(f, "\n  - {}: {}", issue.key, issue.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+#[derive(Default)]
+struct IssueCollector(Vec<ValidationIssue>);
+
+impl IssueCollector {
+    fn check(&mut self, is_valid: bool, key: &str, message: &str) {
+        if !is_valid {
+            self.0.push(ValidationIssue {
+                key: key.to_owned(),
+                message: message.to_owned(),
+            });
+        }
+    }
+
+    fn non_empty(&mut self, value: &str, key: &str) {
+        self.check(!value.trim().is_empty(), key, "must not be empty");
+    }
+
+    fn port(&mut self, value: u16, key: &str) {
+        self.check(value != 0, key, "must be between 1 and 65535");
+    }
+
+    fn http_url(&mut self, value: &str, key: &str) {
+        match Url::parse(value) {
+            Ok(url) => self.check(
+                matches!(url.scheme(), "http" | "https") && url.has_host(),
+                key,
+                "must be an absolute http(s) URL",
+            ),
+            Err(e) => self.check(false, key, &format!("is not a valid URL ({})", e)),
+        }
+    }
+
+    fn finish(self) -> Result<(), ValidationErrors> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(self.0))
+        }
+    }
+}
+
+impl IssueCollector {
+    fn missing_keys(&mut self, config: &config::Config) {
+        for key in REQUIRED_KEYS {
+            let is_present = config.get::<config::Value>(key).is_ok();
+            self.check(is_present, key, "is missing");
+        }
+    }
+
+    /// Deserialize the `key` section on its own, reporting every value of the wrong
+    /// type: each one is left out in turn until the section deserializes, or until a
+    /// required field is missing.
+    fn section<T: DeserializeOwned>(&mut self, config: &config::Config, key: &str) -> Option<T> {
+        let mut value = match config.get::<config::Value>(key) {
+            Ok(value) => value,
+            // Reported by `missing_keys`, or left to the section's defaults.
+            Err(ConfigError::NotFound(_)) => return None,
+            Err(e) => {
+                self.check(false, key, &e.to_string());
+                return None;
+            }
+        };
+        let mut left_out = vec![];
+        loop {
+            let error = match serde_path_to_error::deserialize::<_, T>(value.clone()) {
+                Ok(section) => return left_out.is_empty().then_some(section),
+                Err(error) => error,
+            };
+            let path = match error.path().to_string().as_str() {
+                "." => key.to_owned(),
+                path => format!("{}.{}", key, path),
+            };
+            let message = match error.inner() {
+                // Without the `for key` suffix, relative to the section.
+                ConfigError::Type {
+                    unexpected,
+                    expected,
+                    ..
+                } => format!("invalid type: {}, expected {}", unexpected, expected),
+                e => e.to_string(),
+            };
+            if let Some(field) = missing_field(&message) {
+                let field = format!("{}.{}", path, field);
+                let reported = self.0.iter().any(|issue| issue.key == field);
+                if !reported && !left_out.contains(&field) {
+                    self.check(false, &field, "is missing");
+                }
+                return None;
+            }
+            self.check(false, &path, &message);
+            if !leave_out(&mut value, error.path()) {
+                return None;
+            }
+            left_out.push(path);
+        }
+    }
+}
+
+/// The field named by serde's `missing field `name`` error.
+fn missing_field(message: &str) -> Option<&str> {
+    message.strip_prefix("missing field `")?.split('`').next()
+}
+
+/// Remove the entry at `path` from `value`, returning `false` if there is none.
+fn leave_out(value: &mut config::Value, path: &serde_path_to_error::Path) -> bool {
+    let segments: Vec<_> = path.iter().collect();
+    let Some((last, parents)) = segments.split_last() else {
+        return false;
+    };
+    let mut current = value;
+    for segment in parents {
+        current = match (&mut current.kind, segment) {
+            (ValueKind::Table(table), Segment::Map { key }) => match table.get_mut(key) {
+                Some(value) => value,
+                None => return false,
+            },
+            (ValueKind::Array(array), Segment::Seq { index }) => match array.get_mut(*index) {
+                Some(value) => value,
+                None => return false,
+            },
+            _ => return false,
+        };
+    }
+    match (&mut current.kind, last) {
+        (ValueKind::Table(table), Segment::Map { key }) => table.remove(key).is_some(),
+        (ValueKind::Array(array), Segment::Seq { index }) if *index < array.len() => {
+            array.remove(*index);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Deserialize and validate the settings, reporting missing keys, values of the
+/// wrong type and invalid values together, where serde stops at the first error.
+pub(super) fn parse_settings(config: &config::Config) -> Result<Settings, ValidationErrors> {
+    let mut issues = IssueCollector::default();
+    issues.missing_keys(config);
+
+    let application = issues.section(config, "application");
+    let database = issues.section(config, "database");
+    let email_client = issues.section(config, "email_client");
+    let subscriber_names = issues.section(config, "subscriber_names");
+    let rate_limit = issues.section(config, "rate_limit");
+    let bot_protection = issues.section(config, "bot_protection");
+    let features = issues.section(config, "features");
+    if let Some(application) = &application {
+        check_application(application, &mut issues);
+    }
+    if let Some(database) = &database {
+        check_database(database, &mut issues);
+    }
+    if let Some(email_client) = &email_client {
+        check_email_client(email_client, &mut issues);
+    }
+    if let Some(subscriber_names) = &subscriber_names {
+        check_subscriber_names(subscriber_names, &mut issues);
+    }
+    if let Some(rate_limit) = &rate_limit {
+        check_rate_limit(rate_limit, &mut issues);
+    }
+    if let Some(bot_protection) = &bot_protection {
+        check_bot_protection(bot_protection, &mut issues);
+    }
+    match (application, database, email_client) {
+        (Some(application), Some(database), Some(email_client)) if issues.0.is_empty() => {
+            Ok(Settings {
+                application,
+                database,
+                email_client,
+                subscriber_names: subscriber_names.unwrap_or_default(),
+                rate_limit: rate_limit.unwrap_or_default(),
+                bot_protection: bot_protection.unwrap_or_default(),
+                features: features.unwrap_or_default(),
+            })
+        }
+        // A required section that is missing altogether has its keys reported as missing.
+        _ => Err(ValidationErrors(issues.0)),
+    }
+}
+
+impl Settings {
+    /// Check the values that deserialized fine but are still unusable,
+    /// collecting every issue instead of stopping at the first one.
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut issues = IssueCollector::default();
+        check_application(&self.application, &mut issues);
+        check_database(&self.database, &mut issues);
+        check_email_client(&self.email_client, &mut issues);
+        check_subscriber_names(&self.subscriber_names, &mut issues);
+        check_rate_limit(&self.rate_limit, &mut issues);
+        check_bot_protection(&self.bot_protection, &mut issues);
+        issues.finish()
+    }
+}
+
+fn check_application(application: &ApplicationSettings, issues: &mut IssueCollector) {
+    issues.port(application.port, "application.port");
+    issues.http_url(&application.base_url, "application.base_url");
+    issues.non_empty(
+        application.hmac_secret.expose_secret(),
+        "application.hmac_secret",
+    );
+    if let Some(log_level) = &application.log_level {
+        issues.check(
+            log_level.parse::<LevelFilter>().is_ok(),
+            "application.log_level",
+            "must be one of off, error, warn, info, debug, trace",
+        );
+    }
+}
+
+fn check_database(database: &DatabaseSettings, issues: &mut IssueCollector) {
+    issues.non_empty(&database.host, "database.host");
+    issues.port(database.port, "database.port");
+    issues.non_empty(&database.username, "database.username");
+    issues.non_empty(database.password.expose_secret(), "database.password");
+    issues.non_empty(&database.database_name, "database.database_name");
+}
+
+fn check_email_client(email_client: &EmailClientSettings, issues: &mut IssueCollector) {
+    issues.http_url(&email_client.base_url, "email_client.base_url");
+    issues.check(
+        email_client.sender().is_ok(),
+        "email_client.sender_email",
+        "must be a valid email address",
+    );
+    match (&email_client.smtp, &email_client.authorization_token) {
+        (None, None) => issues.check(
+            false,
+            "email_client.authorization_token",
+            "is missing, and needed unless email_client.smtp is set",
+        ),
+        (None, Some(token)) => {
+            issues.non_empty(token.expose_secret(), "email_client.authorization_token")
+        }
+        (Some(_), _) => {}
+    }
+    issues.check(
+        email_client.timeout_milliseconds > 0,
+        "email_client.timeout_milliseconds",
+        "must be greater than 0",
+    );
+    if let Some(secret) = &email_client.webhook_secret {
+        issues.non_empty(secret.expose_secret(), "email_client.webhook_secret");
+    }
+
+    if let Some(smtp) = &email_client.smtp {
+        issues.non_empty(&smtp.host, "email_client.smtp.host");
+        issues.port(smtp.port, "email_client.smtp.port");
+        issues.check(
+            smtp.username.is_some() == smtp.password.is_some(),
+            "email_client.smtp.password",
+            "must be set together with email_client.smtp.username",
+        );
+        issues.check(
+            !smtp.authentication.is_empty(),
+            "email_client.smtp.authentication",
+            "must list at least one mechanism",
+        );
+        issues.check(
+            smtp.pool_size > 0,
+            "email_client.smtp.pool_size",
+            "must be greater than 0",
+        );
+        issues.check(
+            smtp.max_messages_per_connection > 0,
+            "email_client.smtp.max_messages_per_connection",
+            "must be greater than 0",
+        );
+    }
+}
+
+fn check_subscriber_names(subscriber_names: &NameRules, issues: &mut IssueCollector) {
+    issues.check(
+        subscriber_names.max_length > 0,
+        "subscriber_names.max_length",
+        "must be greater than 0",
+    );
+}
+
+fn check_rate_limit(rate_limit: &RateLimitSettings, issues: &mut IssueCollector) {
+    for (bucket, key) in [
+        (&rate_limit.per_ip, "rate_limit.per_ip"),
+        (&rate_limit.per_email, "rate_limit.per_email"),
+    ] {
+        issues.check(
+            bucket.capacity > 0,
+            &format!("{}.capacity", key),
+            "must be greater than 0",
+        );
+        issues.check(
+            bucket.refill_per_hour > 0,
+            &format!("{}.refill_per_hour", key),
+            "must be greater than 0",
+        );
+    }
+}
+
+fn check_bot_protection(bot_protection: &BotProtectionSettings, issues: &mut IssueCollector) {
+    if let Some(captcha) = &bot_protection.captcha {
+        issues.http_url(&captcha.verify_url, "bot_protection.captcha.verify_url");
+        issues.non_empty(
+            captcha.secret_key.expose_secret(),
+            "bot_protection.captcha.secret_key",
+        );
+        issues.check(
+            captcha.timeout_milliseconds > 0,
+            "bot_protection.captcha.timeout_milliseconds",
+            "must be greater than 0",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::{
+        ApplicationSettings, DatabaseSettings, EmailClientSettings, SmtpSettings,
+    };
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    fn valid_settings() -> Settings {
+        Settings {
+            application: ApplicationSettings {
+                port: 8000,
+                base_url: "http://127.0.0.1:8000".into(),
+                hmac_secret: Secret::new("long-and-very-secret-random-key".into()),
+                log_level: None,
+            },
+            database: DatabaseSettings {
+                username: "postgres".into(),
+                password: Secret::new("password".into()),
+                port: 5432,
+                host: "localhost".into(),
+                database_name: "newsletter".into(),
+                require_ssl: false,
+                migrate_on_startup: false,
+            },
+            email_client: EmailClientSettings {
+                base_url: "https://api.postmarkapp.com".into(),
+                sender_email: "newsletter@example.com".into(),
+                sender_name: None,
+                authorization_token: Some(Secret::new("token".into())),
+                timeout_milliseconds: 10000,
+                webhook_secret: None,
+                smtp: None,
+            },
+            subscriber_names: Default::default(),
+            rate_limit: Default::default(),
+            bot_protection: Default::default(),
+            features: Default::default(),
+        }
+    }
+
+    #[test]
+    fn valid_settings_pass_validation() {
+        assert_ok!(valid_settings().validate());
+    }
+
+    #[test]
+    fn every_invalid_setting_is_reported_together() {
+        let mut settings = valid_settings();
+        settings.application.port = 0;
+        settings.application.base_url = "not a url".into();
+        settings.database.password = Secret::new("  ".into());
+        settings.application.log_level = Some("loud".into());
+        settings.database.host = "".into();
+        settings.email_client.sender_email = "newsletter".into();
+
+        let errors = settings.validate().unwrap_err();
+        let keys: Vec<_> = errors.0.iter().map(|issue| issue.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "application.port",
+                "application.base_url",
+                "application.log_level",
+                "database.host",
+                "database.password",
+                "email_client.sender_email"
+            ]
+        );
+    }
+
+    #[test]
+    fn base_url_must_be_http() {
+        let mut settings = valid_settings();
+        settings.application.base_url = "ftp://example.com".into();
+        assert_err!(settings.validate());
+    }
+
+    #[test]
+    fn the_postmark_token_is_only_needed_without_smtp() {
+        let mut settings = valid_settings();
+        settings.email_client.authorization_token = None;
+        assert_err!(settings.validate());
+
+        settings.email_client.smtp = Some(SmtpSettings {
+            host: "smtp.example.com".into(),
+            port: 587,
+            tls: Default::default(),
+            username: Some("newsletter".into()),
+            password: None,
+            authentication: vec![],
+            pool_size: 4,
+            max_messages_per_connection: 100,
+            idle_timeout_seconds: 60,
+        });
+        let errors = settings.validate().unwrap_err();
+        let keys: Vec<_> = errors.0.iter().map(|issue| issue.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "email_client.smtp.password",
+                "email_client.smtp.authentication"
+            ]
+        );
+    }
+
+    #[test]
+    fn every_missing_key_is_reported_together() {
+        let config = config::Config::builder()
+            .set_override("application.port", 8000)
+            .unwrap()
+            .set_override("database.host", "localhost")
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let errors = parse_settings(&config).unwrap_err();
+        assert_eq!(errors.0.len(), REQUIRED_KEYS.len() - 2);
+        assert!(errors.0.iter().all(|issue| issue.message == "is missing"));
+    }
+
+    #[test]
+    fn type_errors_are_reported_with_the_validation_issues() {
+        let config = config::Config::builder()
+            .add_source(config::File::from_str(
+                "application:\n  port: eighty\n  base_url: \"http://127.0.0.1:8000\"\n  \
+                hmac_secret: secret\ndatabase:\n  host: localhost\n  port: 5432\n  \
+                username: postgres\n  password: password\n  database_name: newsletter\n  \
+                require_ssl: maybe\nemail_client:\n  base_url: \"http://127.0.0.1:8025\"\n  \
+                sender_email: newsletter@example.com\n  authorization_token: token\n  \
+                timeout_milliseconds: 0\nrate_limit:\n  per_ip:\n    capacity: many\n\
+                bot_protection:\n  honeypot: sure\n  min_fill_seconds: soon\n",
+                config::FileFormat::Yaml,
+            ))
+            .build()
+            .unwrap();
+
+        let errors = parse_settings(&config).unwrap_err();
+        let mut keys: Vec<_> = errors.0.iter().map(|issue| issue.key.as_str()).collect();
+        // Fields within a section come in no particular order.
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            vec![
+                "application.port",
+                // Both reported, as neither is required.
+                "bot_protection.honeypot",
+                "bot_protection.min_fill_seconds",
+                "database.require_ssl",
+                "email_client.timeout_milliseconds",
+                "rate_limit.per_ip.capacity",
+            ]
+        );
+    }
+}
@@ -1,7 +1,11 @@
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_timezone;
+mod subscription_status;
 
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
-pub use subscriber_name::SubscriberName;
+pub use subscriber_name::{NameRules, SubscriberName};
+pub use subscriber_timezone::SubscriberTimezone;
+pub use subscription_status::{InvalidStatusTransition, SubscriptionStatus};
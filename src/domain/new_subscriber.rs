@@ -3,4 +3,5 @@ use super::*;
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub timezone: Option<SubscriberTimezone>,
 }
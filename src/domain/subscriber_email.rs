@@ -1,29 +1,68 @@
 use anyhow::bail;
 use validator::validate_email;
 
-#[derive(Debug)]
-pub struct SubscriberEmail(String);
+/// An email address with its domain normalised: lowercase, and punycode for
+/// internationalised domains. This is the form emails are sent to.
+///
+/// The local part is kept as entered, since some mail servers treat it as case-sensitive,
+/// but [`SubscriberEmail::canonical`] ignores its case so that `Foo@Example.com` and
+/// `foo@example.com` are the same subscriber. [`SubscriberEmail::original`] keeps the
+/// address as the subscriber typed it, for display.
+#[derive(Debug, Clone)]
+pub struct SubscriberEmail {
+    original: String,
+    address: String,
+    canonical: String,
+}
 
 impl SubscriberEmail {
     pub fn parse(s: String) -> anyhow::Result<Self> {
-        if validate_email(&s) {
-            Ok(Self(s))
-        } else {
+        let trimmed = s.trim();
+        let Some((local, domain)) = trimmed.rsplit_once('@') else {
+            bail!("{} is invalid email.", s)
+        };
+        let domain = match idna::domain_to_ascii(domain) {
+            Ok(domain) => domain,
+            Err(_) => bail!("{} is invalid email.", s),
+        };
+        let address = format!("{}@{}", local, domain);
+        if !validate_email(&address) {
             bail!("{} is invalid email.", s)
         }
+        Ok(Self {
+            original: trimmed.to_owned(),
+            canonical: format!("{}@{}", local.to_lowercase(), domain),
+            address,
+        })
+    }
+
+    /// The address as entered, without surrounding whitespace, which is what gets stored.
+    pub fn original(&self) -> &str {
+        &self.original
+    }
+
+    /// The form used to tell whether two addresses belong to the same subscriber.
+    pub fn canonical(&self) -> &str {
+        &self.canonical
+    }
+
+    /// The normalised domain, after the last `@`.
+    pub fn domain(&self) -> &str {
+        let at = self.address.rfind('@').expect("Parsed emails contain an @");
+        &self.address[at + 1..]
     }
 }
 
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
-        &self.0
+        &self.address
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use claims::assert_err;
+    use claims::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
     use rand::rngs::StdRng;
@@ -61,4 +100,20 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn domains_are_normalised_and_local_parts_kept() {
+        let email = assert_ok!(SubscriberEmail::parse(" Ursula@Example.COM\n".to_string()));
+        assert_eq!(email.as_ref(), "Ursula@example.com");
+        assert_eq!(email.original(), "Ursula@Example.COM");
+        assert_eq!(email.canonical(), "ursula@example.com");
+        assert_eq!(email.domain(), "example.com");
+    }
+
+    #[test]
+    fn internationalised_domains_are_converted_to_punycode() {
+        let email = assert_ok!(SubscriberEmail::parse("ursula@Bücher.example".to_string()));
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.example");
+        assert_eq!(email.original(), "ursula@Bücher.example");
+    }
 }
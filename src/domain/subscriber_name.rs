@@ -1,33 +1,86 @@
 use anyhow::bail;
+use serde::Deserialize;
+use unicode_normalization::UnicodeNormalization;
 use unicode_segmentation::UnicodeSegmentation;
 
+/// Deployment-specific limits on subscriber names, from the `subscriber_names` settings.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct NameRules {
+    pub forbidden_characters: Vec<char>,
+    /// In graphemes, after normalisation.
+    pub max_length: usize,
+}
+
+impl Default for NameRules {
+    fn default() -> Self {
+        Self {
+            forbidden_characters: vec!['/', '(', ')', '"', '<', '>', '\\', '{', '}'],
+            max_length: 256,
+        }
+    }
+}
+
+/// Characters that are invisible or reorder the text around them, so that a name
+/// would not read the way it is stored.
+///
+/// Zero-width joiners and non-joiners are allowed: some scripts and emoji need them.
+fn is_invisible_or_bidi_control(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}' // zero-width space
+            | '\u{2060}' // word joiner
+            | '\u{FEFF}' // zero-width no-break space
+            | '\u{180E}' // Mongolian vowel separator
+            | '\u{200E}'..='\u{200F}' // left-to-right and right-to-left marks
+            | '\u{202A}'..='\u{202E}' // bidi embeddings and overrides
+            | '\u{2066}'..='\u{2069}' // bidi isolates
+    )
+}
+
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
 impl SubscriberName {
+    /// Parse with the default [`NameRules`].
     pub fn parse(s: String) -> anyhow::Result<Self> {
-        // `.trim()` returns a view over the input `s` without trailing
-        // whitespace-like characters.
-        // `.is_empty` checks if the view contains any character.
-        let is_empty_or_whitespace = s.trim().is_empty();
+        Self::parse_with(s, &NameRules::default())
+    }
+
+    /// Normalise `s` to NFC with single spaces between words, then check it against `rules`.
+    pub fn parse_with(s: String, rules: &NameRules) -> anyhow::Result<Self> {
+        // NFC first: `e` followed by a combining acute accent becomes a single `é`,
+        // so that the same name is always stored the same way.
+        let normalised: String = s.nfc().collect();
+        // Tabs, newlines and runs of spaces all become a single space,
+        // and leading or trailing whitespace goes away.
+        let normalised = normalised.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if normalised.is_empty() {
+            bail!("A subscriber name cannot be empty.")
+        }
         // A grapheme is defined by the Unicode standard as a "user-perceived"
         // character: `å` is a single grapheme, but it is composed of two characters
         // (`a` and `̊`).
-        //
-        // `graphemes` returns an iterator over the graphemes in the input `s`.
-        // `true` specifies that we want to use the extended grapheme definition set,
-        // the recommended one.
-        let is_too_long = s.graphemes(true).count() > 256;
-
-        // Iterate over all characters in the input `s` to check if any of them
-        // matches one of the characters in the forbidden array.
-        let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            bail!("{} is not a valid subscriber name.", s)
-        } else {
-            Ok(Self(s))
+        if normalised.graphemes(true).count() > rules.max_length {
+            bail!(
+                "A subscriber name cannot be longer than {} characters.",
+                rules.max_length
+            )
         }
+        if normalised
+            .chars()
+            .any(|c| c.is_control() || is_invisible_or_bidi_control(c))
+        {
+            bail!("A subscriber name cannot contain control or invisible characters.")
+        }
+        if normalised
+            .chars()
+            .any(|c| rules.forbidden_characters.contains(&c))
+        {
+            bail!("{} is not a valid subscriber name.", normalised)
+        }
+        Ok(Self(normalised))
     }
 }
 
@@ -76,4 +129,37 @@ mod tests {
         let name = "Ursula Le Guin".to_string();
         assert_ok!(SubscriberName::parse(name));
     }
+
+    #[test]
+    fn names_are_normalised_to_nfc_with_single_spaces() {
+        let name = assert_ok!(SubscriberName::parse(
+            "  Rene\u{301}e \t Le\n\nGuin ".to_string()
+        ));
+        assert_eq!(name.as_ref(), "Ren\u{e9}e Le Guin");
+    }
+
+    #[test]
+    fn names_with_control_invisible_or_bidi_characters_are_rejected() {
+        for name in [
+            "Ursula\u{0}",
+            "Urs\u{200B}ula",
+            "Ursula \u{202E}niuG eL",
+            "\u{2066}Ursula\u{2069}",
+        ] {
+            assert_err!(SubscriberName::parse(name.to_string()));
+        }
+        // Needed by Persian, among others.
+        assert_ok!(SubscriberName::parse("می\u{200C}خواهم".to_string()));
+    }
+
+    #[test]
+    fn rules_can_be_tuned() {
+        let rules = NameRules {
+            forbidden_characters: vec!['!'],
+            max_length: 5,
+        };
+        assert_err!(SubscriberName::parse_with("Ursula".to_string(), &rules));
+        assert_err!(SubscriberName::parse_with("Le!".to_string(), &rules));
+        assert_ok!(SubscriberName::parse_with("(Le)".to_string(), &rules));
+    }
 }
@@ -0,0 +1,52 @@
+use anyhow::anyhow;
+use chrono_tz::Tz;
+
+/// An IANA time zone, such as `Europe/Rome`, used to send newsletters at a local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriberTimezone(Tz);
+
+impl SubscriberTimezone {
+    pub fn parse(s: String) -> anyhow::Result<Self> {
+        s.trim()
+            .parse::<Tz>()
+            .map(Self)
+            .map_err(|_| anyhow!("{} is not a valid time zone.", s))
+    }
+
+    pub fn tz(&self) -> Tz {
+        self.0
+    }
+}
+
+impl AsRef<str> for SubscriberTimezone {
+    fn as_ref(&self) -> &str {
+        self.0.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn iana_names_are_valid() {
+        for name in ["Europe/Rome", "America/Argentina/Buenos_Aires", "UTC"] {
+            let timezone = assert_ok!(SubscriberTimezone::parse(name.to_string()));
+            assert_eq!(timezone.as_ref(), name);
+        }
+    }
+
+    #[test]
+    fn surrounding_whitespace_is_ignored() {
+        let timezone = assert_ok!(SubscriberTimezone::parse(" Asia/Tokyo\n".to_string()));
+        assert_eq!(timezone.as_ref(), "Asia/Tokyo");
+    }
+
+    #[test]
+    fn unknown_names_and_offsets_are_rejected() {
+        for name in ["", "Europe/Atlantis", "+02:00", "CEST"] {
+            assert_err!(SubscriberTimezone::parse(name.to_string()));
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl SubscriptionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::PendingConfirmation => "pending_confirmation",
+            SubscriptionStatus::Confirmed => "confirmed",
+            SubscriptionStatus::Unsubscribed => "unsubscribed",
+        }
+    }
+
+    /// Subscribers confirm once, may unsubscribe at any time, and have to
+    /// confirm again to come back after unsubscribing.
+    pub fn can_transition_to(self, next: SubscriptionStatus) -> bool {
+        use SubscriptionStatus::*;
+        matches!(
+            (self, next),
+            (PendingConfirmation, Confirmed)
+                | (PendingConfirmation, Unsubscribed)
+                | (Confirmed, Unsubscribed)
+                | (Unsubscribed, PendingConfirmation)
+        )
+    }
+
+    pub fn transition_to(
+        self,
+        next: SubscriptionStatus,
+    ) -> Result<SubscriptionStatus, InvalidStatusTransition> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(InvalidStatusTransition {
+                from: self,
+                to: next,
+            })
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("A subscription cannot go from {} to {}.", from.as_str(), to.as_str())]
+pub struct InvalidStatusTransition {
+    pub from: SubscriptionStatus,
+    pub to: SubscriptionStatus,
+}
+
+impl TryFrom<String> for SubscriptionStatus {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "pending_confirmation" => Ok(Self::PendingConfirmation),
+            "confirmed" => Ok(Self::Confirmed),
+            "unsubscribed" => Ok(Self::Unsubscribed),
+            other => Err(anyhow!("{} is not a valid subscription status.", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_ok_eq};
+
+    const ALL: [SubscriptionStatus; 3] = [
+        SubscriptionStatus::PendingConfirmation,
+        SubscriptionStatus::Confirmed,
+        SubscriptionStatus::Unsubscribed,
+    ];
+
+    #[test]
+    fn statuses_round_trip_through_their_string_form() {
+        for status in ALL {
+            assert_ok_eq!(
+                SubscriptionStatus::try_from(status.as_str().to_owned()),
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_statuses_are_rejected() {
+        assert_err!(SubscriptionStatus::try_from("active".to_string()));
+    }
+
+    #[test]
+    fn unsubscribed_subscribers_have_to_confirm_again() {
+        use SubscriptionStatus::*;
+        assert_ok_eq!(
+            Unsubscribed.transition_to(PendingConfirmation),
+            PendingConfirmation
+        );
+        assert_eq!(
+            Unsubscribed.transition_to(Confirmed),
+            Err(InvalidStatusTransition {
+                from: Unsubscribed,
+                to: Confirmed
+            })
+        );
+        assert_err!(Confirmed.transition_to(PendingConfirmation));
+    }
+
+    #[test]
+    fn statuses_cannot_transition_to_themselves() {
+        for status in ALL {
+            assert!(!status.can_transition_to(status));
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::mime::{self, Part};
+use crate::domain::SubscriberEmail;
+
+/// An address, with the name to show next to it.
+#[derive(Debug, Clone)]
+pub struct Mailbox {
+    pub name: Option<String>,
+    pub email: SubscriberEmail,
+}
+
+impl Mailbox {
+    pub fn new(email: SubscriberEmail) -> Self {
+        Self { name: None, email }
+    }
+
+    pub fn named(name: impl Into<String>, email: SubscriberEmail) -> Self {
+        Self {
+            name: Some(name.into()),
+            email,
+        }
+    }
+
+    /// `"Name" <address>`, or just the address, as a header value.
+    pub fn header_value(&self) -> String {
+        match self.name.as_deref().filter(|name| !name.trim().is_empty()) {
+            Some(name) => format!("{} <{}>", mime::display_name(name), self.email.as_ref()),
+            None => self.email.as_ref().to_owned(),
+        }
+    }
+}
+
+impl From<SubscriberEmail> for Mailbox {
+    fn from(email: SubscriberEmail) -> Self {
+        Self::new(email)
+    }
+}
+
+/// A file sent along with an email. Inline attachments are referenced from the HTML
+/// body as `cid:<content_id>`, and usually are images.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+    pub content_id: Option<String>,
+}
+
+impl Attachment {
+    pub fn new(
+        filename: impl Into<String>,
+        content_type: impl Into<String>,
+        content: Vec<u8>,
+    ) -> Self {
+        Self {
+            filename: filename.into(),
+            content_type: content_type.into(),
+            content,
+            content_id: None,
+        }
+    }
+
+    pub fn inline(mut self, content_id: impl Into<String>) -> Self {
+        self.content_id = Some(content_id.into());
+        self
+    }
+
+    fn to_part(&self) -> Part {
+        let disposition = if self.content_id.is_some() {
+            "inline"
+        } else {
+            "attachment"
+        };
+        let mut headers = vec![
+            (
+                "Content-Type",
+                format!(
+                    "{}; {}",
+                    self.content_type,
+                    mime::parameter("name", &self.filename)
+                ),
+            ),
+            ("Content-Transfer-Encoding", "base64".to_owned()),
+            (
+                "Content-Disposition",
+                format!(
+                    "{}; {}",
+                    disposition,
+                    mime::parameter("filename", &self.filename)
+                ),
+            ),
+        ];
+        if let Some(content_id) = &self.content_id {
+            headers.push(("Content-ID", format!("<{}>", content_id)));
+        }
+        Part::Single {
+            headers,
+            body: mime::base64_lines(&self.content),
+        }
+    }
+}
+
+/// What to send to a recipient. The sender is added by [`EmailClient`](super::EmailClient).
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub to: Mailbox,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    pub attachments: Vec<Attachment>,
+}
+
+impl EmailMessage {
+    pub fn new(
+        to: impl Into<Mailbox>,
+        subject: impl Into<String>,
+        html_body: impl Into<String>,
+        text_body: impl Into<String>,
+    ) -> Self {
+        Self {
+            to: to.into(),
+            subject: subject.into(),
+            html_body: html_body.into(),
+            text_body: text_body.into(),
+            attachments: vec![],
+        }
+    }
+
+    pub fn attachment(mut self, attachment: Attachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+}
+
+/// A message ready for a transport, with the headers every transport must agree on.
+#[derive(Debug, Clone)]
+pub struct OutgoingEmail {
+    pub from: Mailbox,
+    /// Including the angle brackets.
+    pub message_id: String,
+    pub date: DateTime<Utc>,
+    pub message: EmailMessage,
+}
+
+impl OutgoingEmail {
+    pub fn new(from: Mailbox, message: EmailMessage) -> Self {
+        let message_id = format!("<{}@{}>", Uuid::new_v4(), from.email.domain());
+        Self {
+            from,
+            message_id,
+            date: Utc::now(),
+            message,
+        }
+    }
+
+    pub fn inline_attachments(&self) -> impl Iterator<Item = &Attachment> {
+        self.message
+            .attachments
+            .iter()
+            .filter(|a| a.content_id.is_some())
+    }
+
+    pub fn regular_attachments(&self) -> impl Iterator<Item = &Attachment> {
+        self.message
+            .attachments
+            .iter()
+            .filter(|a| a.content_id.is_none())
+    }
+
+    /// The whole message in MIME format, with CRLF line endings and nothing but ASCII:
+    ///
+    /// ```text
+    /// multipart/mixed             (only with regular attachments)
+    ///   multipart/alternative
+    ///     text/plain
+    ///     multipart/related       (only with inline attachments)
+    ///       text/html
+    ///       inline attachments
+    ///   regular attachments
+    /// ```
+    pub fn to_mime(&self) -> String {
+        let text = Part::Single {
+            headers: vec![
+                ("Content-Type", "text/plain; charset=utf-8".to_owned()),
+                ("Content-Transfer-Encoding", "quoted-printable".to_owned()),
+            ],
+            body: mime::quoted_printable(&self.message.text_body),
+        };
+        let mut html = Part::Single {
+            headers: vec![
+                ("Content-Type", "text/html; charset=utf-8".to_owned()),
+                ("Content-Transfer-Encoding", "quoted-printable".to_owned()),
+            ],
+            body: mime::quoted_printable(&self.message.html_body),
+        };
+        let inline: Vec<Part> = self.inline_attachments().map(Attachment::to_part).collect();
+        if !inline.is_empty() {
+            html = Part::Multipart {
+                subtype: "related",
+                parts: std::iter::once(html).chain(inline).collect(),
+            };
+        }
+        let mut root = Part::Multipart {
+            subtype: "alternative",
+            parts: vec![text, html],
+        };
+        let attachments: Vec<Part> = self
+            .regular_attachments()
+            .map(Attachment::to_part)
+            .collect();
+        if !attachments.is_empty() {
+            root = Part::Multipart {
+                subtype: "mixed",
+                parts: std::iter::once(root).chain(attachments).collect(),
+            };
+        }
+
+        let mut out = String::new();
+        mime::write_header(&mut out, "Date", &self.date.to_rfc2822());
+        mime::write_header(&mut out, "From", &self.from.header_value());
+        mime::write_header(&mut out, "To", &self.message.to.header_value());
+        mime::write_header(
+            &mut out,
+            "Subject",
+            &mime::header_text(&self.message.subject),
+        );
+        mime::write_header(&mut out, "Message-ID", &self.message_id);
+        mime::write_header(&mut out, "MIME-Version", "1.0");
+        root.write(&mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.into()).unwrap()
+    }
+
+    fn outgoing(message: EmailMessage) -> OutgoingEmail {
+        OutgoingEmail::new(
+            Mailbox::named("Newsletter", email("newsletter@example.com")),
+            message,
+        )
+    }
+
+    /// The `Content-Type` lines of the message, in order, without parameters.
+    fn content_types(mime: &str) -> Vec<&str> {
+        mime.lines()
+            .filter_map(|line| line.strip_prefix("Content-Type: "))
+            .map(|value| value.split(';').next().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn html_and_text_are_alternatives() {
+        let message = EmailMessage::new(
+            Mailbox::named("Zoë", email("zoe@example.com")),
+            "Ça va ?",
+            "<p>Hi</p>",
+            "Hi",
+        );
+
+        let email = outgoing(message);
+        let mime = email.to_mime();
+
+        assert!(mime.is_ascii());
+        assert!(mime.contains("\r\nTo: =?UTF-8?B?Wm/Dqw==?= <zoe@example.com>\r\n"));
+        assert!(mime.contains("\r\nFrom: \"Newsletter\" <newsletter@example.com>\r\n"));
+        assert!(mime.contains("\r\nSubject: =?UTF-8?B?w4dhIHZhID8=?=\r\n"));
+        assert!(mime.contains(&format!("\r\nMessage-ID: {}\r\n", email.message_id)));
+        assert!(email.message_id.ends_with("@example.com>"));
+        assert_eq!(
+            content_types(&mime),
+            ["multipart/alternative", "text/plain", "text/html"]
+        );
+    }
+
+    #[test]
+    fn inline_images_are_related_to_the_html_and_files_are_mixed_in() {
+        let message = EmailMessage::new(
+            email("zoe@example.com"),
+            "Hello",
+            "<img src=\"cid:logo\">",
+            "Hello",
+        )
+        .attachment(Attachment::new("logo.png", "image/png", vec![0x89, 0x50]).inline("logo"))
+        .attachment(Attachment::new(
+            "données.json",
+            "application/json",
+            b"{}".to_vec(),
+        ));
+
+        let mime = outgoing(message).to_mime();
+
+        assert_eq!(
+            content_types(&mime),
+            [
+                "multipart/mixed",
+                "multipart/alternative",
+                "text/plain",
+                "multipart/related",
+                "text/html",
+                "image/png",
+                "application/json",
+            ]
+        );
+        assert!(mime.contains("Content-ID: <logo>\r\n"));
+        assert!(mime.contains("Content-Disposition: inline; filename=\"logo.png\"\r\n"));
+        assert!(mime
+            .contains("Content-Disposition: attachment; filename*=UTF-8''donn%C3%A9es.json\r\n"));
+    }
+
+    #[test]
+    fn every_boundary_is_closed() {
+        let message = EmailMessage::new(email("zoe@example.com"), "Hello", "<p>Hi</p>", "Hi")
+            .attachment(Attachment::new("a.txt", "text/plain", b"a".to_vec()));
+
+        let mime = outgoing(message).to_mime();
+
+        for line in mime
+            .lines()
+            .filter(|l| l.starts_with("Content-Type: multipart/"))
+        {
+            let boundary = line
+                .split("boundary=\"")
+                .nth(1)
+                .unwrap()
+                .trim_end_matches('"');
+            assert!(mime.contains(&format!("\r\n--{}--\r\n", boundary)));
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! The encodings of RFC 2045, 2047 and 2231, keeping every line of a message short
+//! and ASCII-only.
+
+use base64::Engine;
+use uuid::Uuid;
+
+const LINE_ENDING: &str = "\r\n";
+/// Leaves room for the `=` of soft line breaks within the 78 characters lines should stay under.
+const MAX_LINE_LENGTH: usize = 76;
+/// Bytes of UTF-8 per encoded word, which stays under 75 characters once encoded.
+const ENCODED_WORD_BYTES: usize = 45;
+
+/// A part boundary. `=_` cannot appear in quoted-printable or base64 content.
+pub(super) fn boundary() -> String {
+    format!("=_{}", Uuid::new_v4().simple())
+}
+
+fn needs_encoding(text: &str) -> bool {
+    text.chars().any(|c| !(c.is_ascii_graphic() || c == ' '))
+}
+
+/// Unstructured header text such as a subject, as encoded words if it is not plain ASCII.
+/// Line breaks never survive, so that values cannot add headers.
+pub(super) fn header_text(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !needs_encoding(&text) {
+        return text;
+    }
+    let mut words = vec![];
+    let mut chunk = String::new();
+    for c in text.chars() {
+        if chunk.len() + c.len_utf8() > ENCODED_WORD_BYTES {
+            words.push(encoded_word(&chunk));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    words.push(encoded_word(&chunk));
+    // Whitespace between encoded words is dropped by readers.
+    words.join("\r\n ")
+}
+
+fn encoded_word(text: &str) -> String {
+    format!(
+        "=?UTF-8?B?{}?=",
+        base64::engine::general_purpose::STANDARD.encode(text)
+    )
+}
+
+/// A display name, quoted when plain ASCII and encoded otherwise.
+pub(super) fn display_name(name: &str) -> String {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if needs_encoding(&name) {
+        header_text(&name)
+    } else {
+        format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// `name=value` for a `Content-Type` or `Content-Disposition` header, using the
+/// extended syntax of RFC 2231 for values that are not plain ASCII.
+pub(super) fn parameter(name: &str, value: &str) -> String {
+    if !needs_encoding(value) {
+        return format!(
+            "{}=\"{}\"",
+            name,
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+    }
+    let encoded: String = value
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+    format!("{}*=UTF-8''{}", name, encoded)
+}
+
+/// Quoted-printable text with CRLF line endings, whatever `text` used.
+pub(super) fn quoted_printable(text: &str) -> String {
+    let text = text.replace("\r\n", "\n");
+    let mut out = String::with_capacity(text.len());
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push_str(LINE_ENDING);
+        }
+        let mut length = 0;
+        let bytes = line.as_bytes();
+        for (j, &b) in bytes.iter().enumerate() {
+            let is_last = j == bytes.len() - 1;
+            let literal =
+                (b.is_ascii_graphic() && b != b'=') || ((b == b' ' || b == b'\t') && !is_last);
+            let token = if literal {
+                (b as char).to_string()
+            } else {
+                format!("={:02X}", b)
+            };
+            // A soft line break, unless the token ends the line anyway.
+            let limit = if is_last {
+                MAX_LINE_LENGTH
+            } else {
+                MAX_LINE_LENGTH - 1
+            };
+            if length + token.len() > limit {
+                out.push('=');
+                out.push_str(LINE_ENDING);
+                length = 0;
+            }
+            length += token.len();
+            out.push_str(&token);
+        }
+    }
+    out
+}
+
+/// Base64 in lines of 76 characters.
+pub(super) fn base64_lines(content: &[u8]) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(content);
+    encoded
+        .as_bytes()
+        .chunks(MAX_LINE_LENGTH)
+        .map(|line| std::str::from_utf8(line).expect("Base64 is ASCII"))
+        .collect::<Vec<_>>()
+        .join(LINE_ENDING)
+}
+
+/// A node of the MIME tree: either content, or parts separated by a boundary.
+pub(super) enum Part {
+    Single {
+        headers: Vec<(&'static str, String)>,
+        body: String,
+    },
+    Multipart {
+        subtype: &'static str,
+        parts: Vec<Part>,
+    },
+}
+
+impl Part {
+    /// The part's headers, a blank line and its body.
+    pub(super) fn write(&self, out: &mut String) {
+        match self {
+            Part::Single { headers, body } => {
+                for (name, value) in headers {
+                    write_header(out, name, value);
+                }
+                out.push_str(LINE_ENDING);
+                out.push_str(body);
+            }
+            Part::Multipart { subtype, parts } => {
+                let boundary = boundary();
+                write_header(
+                    out,
+                    "Content-Type",
+                    &format!("multipart/{}; boundary=\"{}\"", subtype, boundary),
+                );
+                out.push_str(LINE_ENDING);
+                for part in parts {
+                    out.push_str(&format!("--{}{}", boundary, LINE_ENDING));
+                    part.write(out);
+                    out.push_str(LINE_ENDING);
+                }
+                out.push_str(&format!("--{}--{}", boundary, LINE_ENDING));
+            }
+        }
+    }
+}
+
+pub(super) fn write_header(out: &mut String, name: &str, value: &str) {
+    out.push_str(name);
+    out.push_str(": ");
+    out.push_str(value);
+    out.push_str(LINE_ENDING);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_quoted_printable(encoded: &str) -> Vec<u8> {
+        let joined = encoded.replace("=\r\n", "");
+        let mut out = vec![];
+        let mut bytes = joined.bytes();
+        while let Some(b) = bytes.next() {
+            if b == b'=' {
+                let hex: String = [bytes.next().unwrap(), bytes.next().unwrap()]
+                    .iter()
+                    .map(|&b| b as char)
+                    .collect();
+                out.push(u8::from_str_radix(&hex, 16).unwrap());
+            } else {
+                out.push(b);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn ascii_header_text_is_kept_on_one_line() {
+        assert_eq!(
+            header_text("Hello\r\nBcc: x@example.com"),
+            "Hello Bcc: x@example.com"
+        );
+    }
+
+    #[test]
+    fn non_ascii_header_text_is_split_into_short_encoded_words() {
+        let subject = "Grüße aus Köln – 日本語のニュースレター, ".repeat(3);
+
+        let encoded = header_text(&subject);
+
+        let mut decoded = String::new();
+        for word in encoded.split("\r\n ") {
+            assert!(word.len() <= 75, "{} is too long", word);
+            let payload = word
+                .strip_prefix("=?UTF-8?B?")
+                .and_then(|w| w.strip_suffix("?="))
+                .unwrap();
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(payload)
+                .unwrap();
+            decoded.push_str(std::str::from_utf8(&bytes).unwrap());
+        }
+        assert_eq!(decoded, subject.trim_end());
+    }
+
+    #[test]
+    fn display_names_are_quoted_or_encoded() {
+        assert_eq!(
+            display_name(r#"Ursula "Le" Guin"#),
+            r#""Ursula \"Le\" Guin""#
+        );
+        assert_eq!(display_name("Zoë"), "=?UTF-8?B?Wm/Dqw==?=");
+    }
+
+    #[test]
+    fn parameters_use_the_extended_syntax_when_needed() {
+        assert_eq!(parameter("filename", "data.json"), "filename=\"data.json\"");
+        assert_eq!(
+            parameter("filename", "résumé 1.pdf"),
+            "filename*=UTF-8''r%C3%A9sum%C3%A9%201.pdf"
+        );
+    }
+
+    #[test]
+    fn quoted_printable_round_trips_with_short_lines() {
+        let text = format!(
+            "Héllo = world \n{}\nTrailing space \nend",
+            "a long line of text ".repeat(10)
+        );
+
+        let encoded = quoted_printable(&text);
+
+        assert!(encoded.is_ascii());
+        assert!(encoded
+            .split("\r\n")
+            .all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert!(encoded.contains("space=20\r\n"));
+        assert_eq!(
+            decode_quoted_printable(&encoded),
+            text.replace('\n', "\r\n").into_bytes()
+        );
+    }
+
+    #[test]
+    fn base64_lines_are_short() {
+        let encoded = base64_lines(&[0xAB; 200]);
+
+        assert!(encoded
+            .split("\r\n")
+            .all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert_eq!(
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded.replace("\r\n", ""))
+                .unwrap(),
+            vec![0xAB; 200]
+        );
+    }
+}
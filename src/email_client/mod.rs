@@ -0,0 +1,101 @@
+mod message;
+mod mime;
+mod postmark;
+mod smtp;
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use async_trait::async_trait;
+
+use crate::configuration::SettingsHandle;
+use crate::domain::SubscriberEmail;
+
+pub use message::{Attachment, EmailMessage, Mailbox, OutgoingEmail};
+pub use postmark::PostmarkTransport;
+pub use smtp::SmtpTransport;
+
+/// Delivers messages built by [`EmailClient`], so that every backend sends the same
+/// headers and parts.
+#[async_trait]
+pub trait EmailTransport: Send + Sync {
+    /// Returns the id the provider knows the email by, which bounce reports refer to.
+    async fn send(&self, email: &OutgoingEmail) -> anyhow::Result<String>;
+}
+
+/// Sends emails from the configured sender.
+pub struct EmailClient {
+    sender: Mailbox,
+    transport: Arc<dyn EmailTransport>,
+    /// When set, the sender name is read from the latest settings for each email.
+    settings: Option<SettingsHandle>,
+}
+
+impl EmailClient {
+    pub fn new(sender: Mailbox, transport: Arc<dyn EmailTransport>) -> Self {
+        Self {
+            sender,
+            transport,
+            settings: None,
+        }
+    }
+
+    /// The sender address and transport are fixed once built, the sender name follows
+    /// reloads of `settings`.
+    pub fn from_settings(handle: &SettingsHandle) -> anyhow::Result<Self> {
+        let current = handle.current();
+        let settings = &current.email_client;
+        let sender = Mailbox::new(settings.sender().context("Invalid sender email address")?);
+        let transport: Arc<dyn EmailTransport> = match &settings.smtp {
+            Some(smtp) => Arc::new(SmtpTransport::new(smtp, settings.timeout())),
+            None => Arc::new(PostmarkTransport::new(
+                settings.base_url.clone(),
+                settings
+                    .authorization_token
+                    .clone()
+                    .context("Missing the email provider's authorization token")?,
+                settings.timeout(),
+            )?),
+        };
+        Ok(Self {
+            sender,
+            transport,
+            settings: Some(handle.clone()),
+        })
+    }
+
+    /// Who the next email will be sent from.
+    pub fn sender(&self) -> Mailbox {
+        let mut sender = self.sender.clone();
+        if let Some(settings) = &self.settings {
+            sender.name = settings.current().email_client.sender_name.clone();
+        }
+        sender
+    }
+
+    #[tracing::instrument(name = "Send an email", skip(self, html_content, text_content))]
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> anyhow::Result<()> {
+        self.send(EmailMessage::new(
+            recipient.clone(),
+            subject,
+            html_content,
+            text_content,
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Like [`EmailClient::send_email`], for messages with a recipient name or attachments.
+    /// Returns the id the email provider gave the email.
+    #[tracing::instrument(name = "Send an email", skip_all, fields(recipient = %message.to.email.as_ref(), subject = %message.subject))]
+    pub async fn send(&self, message: EmailMessage) -> anyhow::Result<String> {
+        let email = OutgoingEmail::new(self.sender(), message);
+        self.transport.send(&email).await
+    }
+}
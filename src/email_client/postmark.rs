@@ -0,0 +1,264 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use base64::Engine;
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use super::{EmailTransport, OutgoingEmail};
+
+/// Sends emails through Postmark's HTTP API.
+///
+/// Postmark composes the MIME message itself, from the same headers and parts as
+/// [`OutgoingEmail::to_mime`].
+pub struct PostmarkTransport {
+    http_client: Client,
+    base_url: String,
+    authorization_token: Secret<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: String,
+    to: String,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    headers: Vec<Header<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<PostmarkAttachment<'a>>,
+}
+
+#[derive(Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Header<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkAttachment<'a> {
+    name: &'a str,
+    content: String,
+    content_type: &'a str,
+    #[serde(rename = "ContentID", skip_serializing_if = "Option::is_none")]
+    content_id: Option<String>,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: String,
+        authorization_token: Secret<String>,
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let http_client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .context("Failed to build the Postmark HTTP client")?;
+        Ok(Self {
+            http_client,
+            base_url,
+            authorization_token,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, email: &OutgoingEmail) -> anyhow::Result<String> {
+        let url = format!("{}/email", self.base_url);
+        let message = &email.message;
+        let request_body = SendEmailRequest {
+            from: email.from.header_value(),
+            to: message.to.header_value(),
+            subject: &message.subject,
+            html_body: &message.html_body,
+            text_body: &message.text_body,
+            headers: vec![Header {
+                name: "Message-ID",
+                value: &email.message_id,
+            }],
+            attachments: message
+                .attachments
+                .iter()
+                .map(|attachment| PostmarkAttachment {
+                    name: &attachment.filename,
+                    content: base64::engine::general_purpose::STANDARD.encode(&attachment.content),
+                    content_type: &attachment.content_type,
+                    content_id: attachment
+                        .content_id
+                        .as_ref()
+                        .map(|id| format!("cid:{}", id)),
+                })
+                .collect(),
+        };
+        let response = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await
+            .context("Failed to reach the email provider")?
+            .error_for_status()
+            .context("The email provider rejected the email")?
+            .bytes()
+            .await
+            .context("Failed to read the email provider's response")?;
+        // Postmark also keeps our `Message-ID` header, should its response lack an id.
+        let message_id = serde_json::from_slice::<SendEmailResponse>(&response)
+            .map_or_else(|_| email.message_id.clone(), |response| response.message_id);
+        Ok(message_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{Attachment, EmailClient, EmailMessage, Mailbox};
+    use claims::{assert_err, assert_ok};
+    use std::sync::Arc;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse("ursula@example.com".into()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        EmailClient::new(
+            Mailbox::new(SubscriberEmail::parse("newsletter@example.com".into()).unwrap()),
+            Arc::new(
+                PostmarkTransport::new(
+                    base_url,
+                    Secret::new("token".into()),
+                    Duration::from_millis(200),
+                )
+                .unwrap(),
+            ),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client(mock_server.uri())
+            .send_email(&email(), "Subject", "<p>Content</p>", "Content")
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn attachments_and_the_message_id_are_sent_along() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+        let message = EmailMessage::new(email(), "Subject", "<img src=\"cid:logo\">", "Logo")
+            .attachment(Attachment::new("logo.png", "image/png", vec![1, 2, 3]).inline("logo"));
+
+        assert_ok!(email_client(mock_server.uri()).send(message).await);
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["Headers"][0]["Name"], "Message-ID");
+        assert_eq!(body["Attachments"][0]["ContentID"], "cid:logo");
+        assert_eq!(body["Attachments"][0]["Content"], "AQID");
+    }
+
+    #[tokio::test]
+    async fn send_returns_the_id_postmark_gave_the_email() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ErrorCode": 0,
+                "Message": "OK",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817"
+            })))
+            .mount(&mock_server)
+            .await;
+        let message = EmailMessage::new(email(), "Subject", "<p>Content</p>", "Content");
+
+        let message_id = email_client(mock_server.uri()).send(message).await;
+
+        assert_eq!(
+            assert_ok!(message_id),
+            "b7bc2f4a-e38e-4336-af7d-e6c392c2f817"
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client(mock_server.uri())
+            .send_email(&email(), "Subject", "<p>Content</p>", "Content")
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(180)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client(mock_server.uri())
+            .send_email(&email(), "Subject", "<p>Content</p>", "Content")
+            .await;
+
+        assert_err!(outcome);
+    }
+}
@@ -0,0 +1,428 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use lettre::address::Envelope;
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::transport::smtp::client::{AsyncSmtpConnection, TlsParameters};
+use lettre::transport::smtp::extension::ClientId;
+use lettre::Address;
+use secrecy::ExposeSecret;
+use tokio::sync::Semaphore;
+
+use super::{EmailTransport, Mailbox, OutgoingEmail};
+use crate::configuration::{SmtpAuthMechanism, SmtpSettings, SmtpTls};
+
+/// Sends emails to an SMTP server, as composed by [`OutgoingEmail::to_mime`].
+///
+/// Up to `pool_size` connections are open at once. They are kept for the next email
+/// until they have been idle for too long or have sent `max_messages_per_connection`.
+pub struct SmtpTransport {
+    host: String,
+    port: u16,
+    tls: SmtpTls,
+    credentials: Option<Credentials>,
+    mechanisms: Vec<Mechanism>,
+    max_messages_per_connection: u32,
+    idle_timeout: Duration,
+    timeout: Duration,
+    hello_name: ClientId,
+    permits: Semaphore,
+    idle: Mutex<Vec<PooledConnection>>,
+}
+
+struct PooledConnection {
+    connection: AsyncSmtpConnection,
+    sent: u32,
+    idle_since: Instant,
+}
+
+impl From<SmtpAuthMechanism> for Mechanism {
+    fn from(mechanism: SmtpAuthMechanism) -> Self {
+        match mechanism {
+            SmtpAuthMechanism::Plain => Mechanism::Plain,
+            SmtpAuthMechanism::Login => Mechanism::Login,
+        }
+    }
+}
+
+impl SmtpTransport {
+    pub fn new(settings: &SmtpSettings, timeout: Duration) -> Self {
+        let credentials = settings
+            .username
+            .as_ref()
+            .zip(settings.password.as_ref())
+            .map(|(username, password)| {
+                Credentials::new(username.clone(), password.expose_secret().clone())
+            });
+        Self {
+            host: settings.host.clone(),
+            port: settings.port,
+            tls: settings.tls,
+            credentials,
+            mechanisms: settings
+                .authentication
+                .iter()
+                .copied()
+                .map(Mechanism::from)
+                .collect(),
+            max_messages_per_connection: settings.max_messages_per_connection,
+            idle_timeout: settings.idle_timeout(),
+            timeout,
+            hello_name: ClientId::default(),
+            permits: Semaphore::new(settings.pool_size),
+            idle: Mutex::new(vec![]),
+        }
+    }
+
+    async fn connect(&self) -> anyhow::Result<PooledConnection> {
+        let tls_parameters = || {
+            TlsParameters::new(self.host.clone())
+                .context("Failed to set up TLS for the SMTP server")
+        };
+        let implicit_tls = match self.tls {
+            SmtpTls::Implicit => Some(tls_parameters()?),
+            SmtpTls::Starttls | SmtpTls::None => None,
+        };
+        let mut connection = AsyncSmtpConnection::connect_tokio1(
+            (self.host.as_str(), self.port),
+            Some(self.timeout),
+            &self.hello_name,
+            implicit_tls,
+            None,
+        )
+        .await
+        .context("Failed to connect to the SMTP server")?;
+        if self.tls == SmtpTls::Starttls {
+            if !connection.can_starttls() {
+                connection.abort().await;
+                return Err(anyhow!("The SMTP server does not offer STARTTLS"));
+            }
+            connection
+                .starttls(tls_parameters()?, &self.hello_name)
+                .await
+                .context("Failed to upgrade the SMTP connection with STARTTLS")?;
+        }
+        if let Some(credentials) = &self.credentials {
+            if let Err(e) = connection.auth(&self.mechanisms, credentials).await {
+                connection.abort().await;
+                return Err(e).context("The SMTP server rejected the credentials");
+            }
+        }
+        Ok(PooledConnection {
+            connection,
+            sent: 0,
+            idle_since: Instant::now(),
+        })
+    }
+
+    /// An idle connection that still answers, or a new one.
+    async fn checkout(&self) -> anyhow::Result<PooledConnection> {
+        loop {
+            let next = self.idle.lock().unwrap().pop();
+            let Some(mut pooled) = next else {
+                return self.connect().await;
+            };
+            if pooled.idle_since.elapsed() < self.idle_timeout
+                && pooled.connection.test_connected().await
+            {
+                return Ok(pooled);
+            }
+            pooled.connection.abort().await;
+        }
+    }
+
+    async fn checkin(&self, mut pooled: PooledConnection) {
+        if pooled.sent >= self.max_messages_per_connection {
+            let _ = pooled.connection.quit().await;
+            return;
+        }
+        pooled.idle_since = Instant::now();
+        self.idle.lock().unwrap().push(pooled);
+    }
+}
+
+fn address(mailbox: &Mailbox) -> anyhow::Result<Address> {
+    mailbox
+        .email
+        .as_ref()
+        .parse()
+        .context("Invalid address for the SMTP envelope")
+}
+
+#[async_trait]
+impl EmailTransport for SmtpTransport {
+    /// SMTP servers report bounces against the `Message-ID` header, which is returned.
+    async fn send(&self, email: &OutgoingEmail) -> anyhow::Result<String> {
+        let envelope = Envelope::new(
+            Some(address(&email.from)?),
+            vec![address(&email.message.to)?],
+        )
+        .context("Invalid SMTP envelope")?;
+        let mime = email.to_mime();
+
+        let _permit = self.permits.acquire().await?;
+        let mut pooled = self.checkout().await?;
+        match pooled.connection.send(&envelope, mime.as_bytes()).await {
+            Ok(_) => {
+                pooled.sent += 1;
+                self.checkin(pooled).await;
+                Ok(email.message_id.clone())
+            }
+            Err(e) => {
+                pooled.connection.abort().await;
+                Err(e).context("The SMTP server rejected the email")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailClient, EmailMessage};
+    use base64::Engine;
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[derive(Debug, Clone)]
+    struct ReceivedMessage {
+        connection: usize,
+        mail_from: String,
+        rcpt_to: Vec<String>,
+        data: String,
+    }
+
+    /// A plain text SMTP server recording what it receives, and on which connection.
+    struct SmtpSink {
+        port: u16,
+        messages: Arc<Mutex<Vec<ReceivedMessage>>>,
+        logins: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    impl SmtpSink {
+        async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let messages = Arc::new(Mutex::new(vec![]));
+            let logins = Arc::new(Mutex::new(vec![]));
+            let (m, l) = (messages.clone(), logins.clone());
+            tokio::spawn(async move {
+                let mut connection = 0;
+                while let Ok((stream, _)) = listener.accept().await {
+                    connection += 1;
+                    tokio::spawn(serve(stream, connection, m.clone(), l.clone()));
+                }
+            });
+            Self {
+                port,
+                messages,
+                logins,
+            }
+        }
+
+        fn messages(&self) -> Vec<ReceivedMessage> {
+            self.messages.lock().unwrap().clone()
+        }
+
+        fn logins(&self) -> Vec<(String, String)> {
+            self.logins.lock().unwrap().clone()
+        }
+    }
+
+    fn decode(encoded: &str) -> String {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    async fn serve(
+        stream: TcpStream,
+        connection: usize,
+        messages: Arc<Mutex<Vec<ReceivedMessage>>>,
+        logins: Arc<Mutex<Vec<(String, String)>>>,
+    ) {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"220 sink ready\r\n").await.unwrap();
+        let mut mail_from = String::new();
+        let mut rcpt_to = vec![];
+        while let Ok(Some(line)) = lines.next_line().await {
+            let command = line.to_ascii_uppercase();
+            let response = if command.starts_with("EHLO") {
+                "250-sink\r\n250-8BITMIME\r\n250 AUTH PLAIN LOGIN".to_owned()
+            } else if let Some(initial) = line.strip_prefix("AUTH PLAIN ") {
+                let credentials = decode(initial);
+                let mut parts = credentials.split('\0').skip(1);
+                let username = parts.next().unwrap_or_default().to_owned();
+                let password = parts.next().unwrap_or_default().to_owned();
+                logins.lock().unwrap().push((username, password));
+                "235 authenticated".to_owned()
+            } else if command.starts_with("AUTH LOGIN") {
+                writer.write_all(b"334 VXNlcm5hbWU6\r\n").await.unwrap();
+                let username = decode(&lines.next_line().await.unwrap().unwrap());
+                writer.write_all(b"334 UGFzc3dvcmQ6\r\n").await.unwrap();
+                let password = decode(&lines.next_line().await.unwrap().unwrap());
+                logins.lock().unwrap().push((username, password));
+                "235 authenticated".to_owned()
+            } else if command.starts_with("MAIL FROM:") {
+                mail_from = line[10..].trim().to_owned();
+                rcpt_to.clear();
+                "250 ok".to_owned()
+            } else if command.starts_with("RCPT TO:") {
+                rcpt_to.push(line[8..].trim().to_owned());
+                "250 ok".to_owned()
+            } else if command == "DATA" {
+                writer.write_all(b"354 go ahead\r\n").await.unwrap();
+                let mut data = String::new();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line == "." {
+                        break;
+                    }
+                    data.push_str(line.strip_prefix('.').unwrap_or(&line));
+                    data.push_str("\r\n");
+                }
+                messages.lock().unwrap().push(ReceivedMessage {
+                    connection,
+                    mail_from: mail_from.clone(),
+                    rcpt_to: rcpt_to.clone(),
+                    data,
+                });
+                "250 queued".to_owned()
+            } else if command == "QUIT" {
+                writer.write_all(b"221 bye\r\n").await.unwrap();
+                return;
+            } else {
+                "250 ok".to_owned()
+            };
+            let response = format!("{}\r\n", response);
+            if writer.write_all(response.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    fn settings(port: u16) -> SmtpSettings {
+        SmtpSettings {
+            host: "127.0.0.1".into(),
+            port,
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+            authentication: vec![SmtpAuthMechanism::Plain, SmtpAuthMechanism::Login],
+            pool_size: 1,
+            max_messages_per_connection: 100,
+            idle_timeout_seconds: 60,
+        }
+    }
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.into()).unwrap()
+    }
+
+    fn email_client(settings: &SmtpSettings) -> EmailClient {
+        EmailClient::new(
+            Mailbox::named("Newsletter", email("newsletter@example.com")),
+            Arc::new(SmtpTransport::new(settings, Duration::from_secs(2))),
+        )
+    }
+
+    #[tokio::test]
+    async fn the_mime_message_is_delivered_to_the_recipient() {
+        let sink = SmtpSink::start().await;
+        let client = email_client(&settings(sink.port));
+
+        let outcome = client
+            .send(EmailMessage::new(
+                email("ursula@example.com"),
+                "Subject",
+                "<p>Content</p>",
+                "Content",
+            ))
+            .await;
+
+        assert_ok!(outcome);
+        let messages = sink.messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].mail_from, "<newsletter@example.com>");
+        assert_eq!(messages[0].rcpt_to, vec!["<ursula@example.com>"]);
+        assert!(messages[0]
+            .data
+            .contains("From: \"Newsletter\" <newsletter@example.com>\r\n"));
+        assert!(messages[0].data.contains("Subject: Subject\r\n"));
+        assert!(messages[0]
+            .data
+            .contains("Content-Type: multipart/alternative"));
+        assert!(sink.logins().is_empty());
+    }
+
+    #[tokio::test]
+    async fn credentials_are_sent_with_the_preferred_mechanism() {
+        for mechanism in [SmtpAuthMechanism::Login, SmtpAuthMechanism::Plain] {
+            let sink = SmtpSink::start().await;
+            let mut settings = settings(sink.port);
+            settings.username = Some("user".into());
+            settings.password = Some(Secret::new("p4ss".into()));
+            settings.authentication = vec![mechanism];
+
+            let outcome = email_client(&settings)
+                .send_email(&email("ursula@example.com"), "Hi", "<p>Hi</p>", "Hi")
+                .await;
+
+            assert_ok!(outcome);
+            assert_eq!(sink.logins(), vec![("user".to_owned(), "p4ss".to_owned())]);
+        }
+    }
+
+    #[tokio::test]
+    async fn connections_are_reused_up_to_the_message_limit() {
+        let sink = SmtpSink::start().await;
+        let mut settings = settings(sink.port);
+        settings.max_messages_per_connection = 2;
+        let client = email_client(&settings);
+
+        for _ in 0..5 {
+            assert_ok!(
+                client
+                    .send_email(&email("ursula@example.com"), "Hi", "<p>Hi</p>", "Hi")
+                    .await
+            );
+        }
+
+        let connections: Vec<usize> = sink.messages().iter().map(|m| m.connection).collect();
+        assert_eq!(connections, vec![1, 1, 2, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn starttls_is_required_unless_disabled() {
+        let sink = SmtpSink::start().await;
+        let mut settings = settings(sink.port);
+        settings.tls = SmtpTls::Starttls;
+
+        let outcome = email_client(&settings)
+            .send_email(&email("ursula@example.com"), "Hi", "<p>Hi</p>", "Hi")
+            .await;
+
+        assert_err!(outcome);
+        assert!(sink.messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sending_fails_if_the_server_is_unreachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let outcome = email_client(&settings(port))
+            .send_email(&email("ursula@example.com"), "Hi", "<p>Hi</p>", "Hi")
+            .await;
+
+        assert_err!(outcome);
+    }
+}
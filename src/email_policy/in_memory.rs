@@ -0,0 +1,74 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use super::{DomainRule, DomainRuleEntry, DomainRules};
+
+/// Keeps domain rules in a `Vec`, for tests.
+#[derive(Default)]
+pub struct InMemoryDomainRules {
+    entries: Mutex<Vec<DomainRuleEntry>>,
+}
+
+impl InMemoryDomainRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_entries<T>(&self, f: impl FnOnce(&mut Vec<DomainRuleEntry>) -> T) -> T {
+        let mut entries = self
+            .entries
+            .lock()
+            .expect("The domain rules lock is poisoned");
+        f(&mut entries)
+    }
+}
+
+#[async_trait]
+impl DomainRules for InMemoryDomainRules {
+    async fn find(&self, domains: &[String]) -> anyhow::Result<Vec<DomainRuleEntry>> {
+        Ok(self.with_entries(|entries| {
+            entries
+                .iter()
+                .filter(|entry| domains.contains(&entry.domain))
+                .cloned()
+                .collect()
+        }))
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<DomainRuleEntry>> {
+        Ok(self.with_entries(|entries| {
+            let mut entries = entries.clone();
+            entries.sort_by(|a, b| a.domain.cmp(&b.domain));
+            entries
+        }))
+    }
+
+    async fn set(
+        &self,
+        domain: &str,
+        rule: DomainRule,
+        created_by: &str,
+    ) -> anyhow::Result<DomainRuleEntry> {
+        let entry = DomainRuleEntry {
+            domain: domain.to_owned(),
+            rule,
+            created_at: Utc::now(),
+            created_by: created_by.to_owned(),
+        };
+        self.with_entries(|entries| {
+            entries.retain(|e| e.domain != domain);
+            entries.push(entry.clone());
+        });
+        Ok(entry)
+    }
+
+    async fn remove(&self, domain: &str) -> anyhow::Result<bool> {
+        Ok(self.with_entries(|entries| {
+            let before = entries.len();
+            entries.retain(|e| e.domain != domain);
+            entries.len() < before
+        }))
+    }
+}
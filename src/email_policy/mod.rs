@@ -0,0 +1,305 @@
+mod in_memory;
+mod postgres;
+mod resolver;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{error, warn};
+
+use crate::domain::SubscriberEmail;
+
+pub use in_memory::InMemoryDomainRules;
+pub use postgres::PgDomainRules;
+pub use resolver::{DnsMxResolver, FakeMxResolver};
+
+/// The feature switch turning on MX lookups for new signups.
+pub const MX_CHECK_FEATURE: &str = "email_mx_check";
+
+static DISPOSABLE_DOMAINS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    include_str!("disposable_domains.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+});
+
+/// An admin's decision about a domain, overriding the bundled disposable list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainRule {
+    Block,
+    /// Skips every other check, for false positives.
+    Allow,
+}
+
+impl DomainRule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DomainRule::Block => "block",
+            DomainRule::Allow => "allow",
+        }
+    }
+}
+
+impl TryFrom<String> for DomainRule {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "block" => Ok(Self::Block),
+            "allow" => Ok(Self::Allow),
+            other => Err(anyhow!("{} is not a valid domain rule.", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DomainRuleEntry {
+    pub domain: String,
+    pub rule: DomainRule,
+    pub created_at: DateTime<Utc>,
+    pub created_by: String,
+}
+
+/// Storage for the admin-managed blocklist and allowlist.
+#[async_trait]
+pub trait DomainRules: Send + Sync {
+    /// The entries for any of `domains`, in no particular order.
+    async fn find(&self, domains: &[String]) -> anyhow::Result<Vec<DomainRuleEntry>>;
+
+    /// Every entry, by domain.
+    async fn list(&self) -> anyhow::Result<Vec<DomainRuleEntry>>;
+
+    /// Replaces any existing entry for `domain`.
+    async fn set(
+        &self,
+        domain: &str,
+        rule: DomainRule,
+        created_by: &str,
+    ) -> anyhow::Result<DomainRuleEntry>;
+
+    /// Returns `false` if there was no entry for `domain`.
+    async fn remove(&self, domain: &str) -> anyhow::Result<bool>;
+}
+
+pub type SharedDomainRules = Arc<dyn DomainRules>;
+
+#[async_trait]
+pub trait MxResolver: Send + Sync {
+    /// Whether mail can be delivered to `domain`.
+    async fn accepts_mail(&self, domain: &str) -> anyhow::Result<bool>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum EmailRejection {
+    #[error("Addresses from disposable email providers are not accepted.")]
+    DisposableDomain,
+    #[error("Addresses from this domain are not accepted.")]
+    BlockedDomain,
+    #[error("This domain does not accept email.")]
+    NoMailServer,
+}
+
+impl EmailRejection {
+    /// A stable code for API clients, unlike the message.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            EmailRejection::DisposableDomain => "disposable_domain",
+            EmailRejection::BlockedDomain => "blocked_domain",
+            EmailRejection::NoMailServer => "no_mail_server",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum EmailPolicyError {
+    #[error(transparent)]
+    Rejected(#[from] EmailRejection),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+/// Decides whether addresses at a domain may subscribe, on top of [`SubscriberEmail::parse`].
+#[derive(Clone)]
+pub struct EmailPolicy {
+    rules: SharedDomainRules,
+    /// `None` when no resolver could be built, skipping MX checks.
+    resolver: Option<Arc<dyn MxResolver>>,
+}
+
+impl EmailPolicy {
+    pub fn new(rules: SharedDomainRules, resolver: Arc<dyn MxResolver>) -> Self {
+        Self {
+            rules,
+            resolver: Some(resolver),
+        }
+    }
+
+    /// With the system's DNS resolver, or without MX checks if it cannot be built:
+    /// the other checks do not depend on it.
+    pub fn with_dns_resolver(rules: SharedDomainRules) -> Self {
+        let resolver = match DnsMxResolver::new() {
+            Ok(resolver) => Some(Arc::new(resolver) as Arc<dyn MxResolver>),
+            Err(e) => {
+                error!(
+                    "Failed to build the DNS resolver, MX checks are skipped {:?}",
+                    e
+                );
+                None
+            }
+        };
+        Self { rules, resolver }
+    }
+
+    pub fn rules(&self) -> &dyn DomainRules {
+        self.rules.as_ref()
+    }
+
+    /// Admin rules come first, then the disposable list, then MX records if `check_mx`.
+    /// Rules and list entries also apply to subdomains; the most specific rule wins.
+    #[tracing::instrument(name = "Check email domain", skip(self, email), fields(domain = %email.domain()))]
+    pub async fn check(
+        &self,
+        email: &SubscriberEmail,
+        check_mx: bool,
+    ) -> Result<(), EmailPolicyError> {
+        let domains = parent_domains(email.domain());
+        let entries = self.rules.find(&domains).await?;
+        let rule = domains
+            .iter()
+            .find_map(|domain| entries.iter().find(|entry| &entry.domain == domain))
+            .map(|entry| entry.rule);
+        match rule {
+            Some(DomainRule::Allow) => return Ok(()),
+            Some(DomainRule::Block) => return Err(EmailRejection::BlockedDomain.into()),
+            None => {}
+        }
+
+        if domains
+            .iter()
+            .any(|domain| DISPOSABLE_DOMAINS.contains(domain.as_str()))
+        {
+            return Err(EmailRejection::DisposableDomain.into());
+        }
+
+        if let Some(resolver) = self.resolver.as_ref().filter(|_| check_mx) {
+            match resolver.accepts_mail(email.domain()).await {
+                Ok(true) => {}
+                Ok(false) => return Err(EmailRejection::NoMailServer.into()),
+                // A flaky resolver must not turn subscribers away.
+                Err(e) => warn!("Failed to look up the mail servers {:?}", e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `a.b.example.com`, `b.example.com`, `example.com` and `com`, most specific first.
+fn parent_domains(domain: &str) -> Vec<String> {
+    let mut domains = vec![domain.to_owned()];
+    let mut rest = domain;
+    while let Some((_, parent)) = rest.split_once('.') {
+        domains.push(parent.to_owned());
+        rest = parent;
+    }
+    domains
+}
+
+/// Normalise a domain entered by an admin the way [`SubscriberEmail`] normalises domains.
+pub fn parse_domain(s: &str) -> anyhow::Result<String> {
+    let domain = match idna::domain_to_ascii(s.trim()) {
+        Ok(domain) => domain,
+        Err(_) => bail!("{} is not a valid domain.", s),
+    };
+    if domain.is_empty()
+        || domain.starts_with('.')
+        || domain.ends_with('.')
+        || domain.contains(|c: char| c == '@' || c == '/' || c.is_whitespace())
+    {
+        bail!("{} is not a valid domain.", s);
+    }
+    Ok(domain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_matches, assert_ok, assert_ok_eq};
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_owned()).unwrap()
+    }
+
+    fn policy(rules: InMemoryDomainRules, resolver: FakeMxResolver) -> EmailPolicy {
+        EmailPolicy::new(Arc::new(rules), Arc::new(resolver))
+    }
+
+    #[tokio::test]
+    async fn disposable_domains_and_their_subdomains_are_rejected() {
+        let policy = policy(InMemoryDomainRules::new(), FakeMxResolver::new());
+
+        for address in ["ursula@mailinator.com", "ursula@eu.Mailinator.com"] {
+            assert_matches!(
+                policy.check(&email(address), false).await,
+                Err(EmailPolicyError::Rejected(EmailRejection::DisposableDomain))
+            );
+        }
+        assert_ok!(policy.check(&email("ursula@example.com"), false).await);
+    }
+
+    #[tokio::test]
+    async fn the_most_specific_admin_rule_wins() {
+        let rules = InMemoryDomainRules::new();
+        rules
+            .set("example.com", DomainRule::Block, "admin:root")
+            .await
+            .unwrap();
+        rules
+            .set("staff.example.com", DomainRule::Allow, "admin:root")
+            .await
+            .unwrap();
+        rules
+            .set("mailinator.com", DomainRule::Allow, "admin:root")
+            .await
+            .unwrap();
+        let policy = policy(rules, FakeMxResolver::new());
+
+        assert_matches!(
+            policy.check(&email("ursula@example.com"), false).await,
+            Err(EmailPolicyError::Rejected(EmailRejection::BlockedDomain))
+        );
+        assert_ok!(policy.check(&email("ursula@staff.example.com"), true).await);
+        assert_ok!(policy.check(&email("ursula@mailinator.com"), false).await);
+    }
+
+    #[tokio::test]
+    async fn mail_servers_are_only_checked_when_asked() {
+        let resolver = FakeMxResolver::new().with_mail_server("example.com");
+        let policy = policy(InMemoryDomainRules::new(), resolver);
+
+        assert_ok!(policy.check(&email("ursula@example.com"), true).await);
+        assert_ok!(policy.check(&email("ursula@nowhere.example"), false).await);
+        assert_matches!(
+            policy.check(&email("ursula@nowhere.example"), true).await,
+            Err(EmailPolicyError::Rejected(EmailRejection::NoMailServer))
+        );
+    }
+
+    #[test]
+    fn admin_domains_are_normalised() {
+        assert_ok_eq!(
+            parse_domain(" Bücher.Example "),
+            "xn--bcher-kva.example".to_owned()
+        );
+        for invalid in ["", "ursula@example.com", ".example.com", "exa mple.com"] {
+            assert_err!(parse_domain(invalid));
+        }
+    }
+}
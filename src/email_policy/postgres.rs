@@ -0,0 +1,103 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use super::{DomainRule, DomainRuleEntry, DomainRules};
+
+pub struct PgDomainRules {
+    db_connection: PgPool,
+}
+
+impl PgDomainRules {
+    pub fn new(db_connection: PgPool) -> Self {
+        Self { db_connection }
+    }
+}
+
+struct DomainRuleRow {
+    domain: String,
+    rule: String,
+    created_at: DateTime<Utc>,
+    created_by: String,
+}
+
+impl TryFrom<DomainRuleRow> for DomainRuleEntry {
+    type Error = anyhow::Error;
+
+    fn try_from(row: DomainRuleRow) -> Result<Self, Self::Error> {
+        Ok(DomainRuleEntry {
+            domain: row.domain,
+            rule: row.rule.try_into()?,
+            created_at: row.created_at,
+            created_by: row.created_by,
+        })
+    }
+}
+
+#[async_trait]
+impl DomainRules for PgDomainRules {
+    async fn find(&self, domains: &[String]) -> anyhow::Result<Vec<DomainRuleEntry>> {
+        sqlx::query_as!(
+            DomainRuleRow,
+            r#"
+            SELECT domain, rule, created_at, created_by FROM email_domain_rules
+            WHERE domain = ANY($1)"#,
+            domains
+        )
+        .fetch_all(&self.db_connection)
+        .await
+        .context("Failed to fetch email domain rules")?
+        .into_iter()
+        .map(DomainRuleEntry::try_from)
+        .collect()
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<DomainRuleEntry>> {
+        sqlx::query_as!(
+            DomainRuleRow,
+            "SELECT domain, rule, created_at, created_by FROM email_domain_rules ORDER BY domain"
+        )
+        .fetch_all(&self.db_connection)
+        .await
+        .context("Failed to list email domain rules")?
+        .into_iter()
+        .map(DomainRuleEntry::try_from)
+        .collect()
+    }
+
+    #[tracing::instrument(name = "Set email domain rule", skip(self))]
+    async fn set(
+        &self,
+        domain: &str,
+        rule: DomainRule,
+        created_by: &str,
+    ) -> anyhow::Result<DomainRuleEntry> {
+        sqlx::query_as!(
+            DomainRuleRow,
+            r#"
+            INSERT INTO email_domain_rules (domain, rule, created_at, created_by)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (domain) DO UPDATE
+            SET rule = EXCLUDED.rule, created_at = EXCLUDED.created_at, created_by = EXCLUDED.created_by
+            RETURNING domain, rule, created_at, created_by"#,
+            domain,
+            rule.as_str(),
+            Utc::now(),
+            created_by
+        )
+        .fetch_one(&self.db_connection)
+        .await
+        .context("Failed to store email domain rule")?
+        .try_into()
+    }
+
+    #[tracing::instrument(name = "Remove email domain rule", skip(self))]
+    async fn remove(&self, domain: &str) -> anyhow::Result<bool> {
+        let removed = sqlx::query!("DELETE FROM email_domain_rules WHERE domain = $1", domain)
+            .execute(&self.db_connection)
+            .await
+            .context("Failed to remove email domain rule")?;
+        Ok(removed.rows_affected() > 0)
+    }
+}
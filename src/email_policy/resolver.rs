@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::TokioAsyncResolver;
+
+use super::MxResolver;
+
+/// Looks up MX records with the system's DNS configuration.
+pub struct DnsMxResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsMxResolver {
+    /// Falls back to public resolvers when the system configuration cannot be read.
+    pub fn new() -> anyhow::Result<Self> {
+        let resolver = match TokioAsyncResolver::tokio_from_system_conf() {
+            Ok(resolver) => resolver,
+            Err(_) => TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+                .context("Failed to build a DNS resolver")?,
+        };
+        Ok(Self { resolver })
+    }
+}
+
+#[async_trait]
+impl MxResolver for DnsMxResolver {
+    #[tracing::instrument(name = "Look up mail servers", skip(self))]
+    async fn accepts_mail(&self, domain: &str) -> anyhow::Result<bool> {
+        // Trailing dot: never append the search domains of the host.
+        let fqdn = format!("{}.", domain);
+        match self.resolver.mx_lookup(fqdn.as_str()).await {
+            // A single `.` exchange is a null MX: the domain explicitly accepts no mail.
+            Ok(mx) => Ok(mx.iter().any(|record| !record.exchange().is_root())),
+            Err(e) => match e.kind() {
+                // Without MX records, mail goes to the domain's own address.
+                ResolveErrorKind::NoRecordsFound { .. } => {
+                    match self.resolver.lookup_ip(fqdn.as_str()).await {
+                        Ok(_) => Ok(true),
+                        Err(e) => match e.kind() {
+                            ResolveErrorKind::NoRecordsFound { .. } => Ok(false),
+                            _ => Err(e).context("Failed to look up the domain's address"),
+                        },
+                    }
+                }
+                _ => Err(e).context("Failed to look up MX records"),
+            },
+        }
+    }
+}
+
+/// Answers from a fixed set of domains, for tests.
+#[derive(Default)]
+pub struct FakeMxResolver {
+    domains: HashSet<String>,
+}
+
+impl FakeMxResolver {
+    /// A resolver for which no domain accepts mail.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_mail_server(mut self, domain: &str) -> Self {
+        self.domains.insert(domain.to_owned());
+        self
+    }
+}
+
+#[async_trait]
+impl MxResolver for FakeMxResolver {
+    async fn accepts_mail(&self, domain: &str) -> anyhow::Result<bool> {
+        Ok(self.domains.contains(domain))
+    }
+}
@@ -1,21 +1,33 @@
-use axum::http::StatusCode;
+use axum::http::{header, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use serde_json::json;
 use thiserror::Error;
 
-#[derive(Debug)]
-struct AnyError(anyhow::Error);
-
 #[derive(Debug, Error)]
 pub enum AppError {
     #[error("Bad request: {0}")]
     BadRequest(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
     #[error("Internal server error: {0}")]
     InternalServerError(String),
+    /// A service we depend on failed; the same request may work a little later.
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
+    #[error(transparent)]
+    RateLimited(#[from] crate::rate_limit::RateLimited),
+    #[error(transparent)]
+    EmailRejected(#[from] crate::email_policy::EmailRejection),
     #[error(transparent)]
     ConfigError(#[from] config::ConfigError),
     #[error(transparent)]
+    InvalidConfiguration(#[from] crate::configuration::ValidationErrors),
+    #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
@@ -23,11 +35,49 @@ impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, error_message) = match self {
             AppError::BadRequest(e) => (StatusCode::BAD_REQUEST, e),
+            AppError::Unauthorized(e) => {
+                let body = Json(json!({
+                    "error": e,
+                }));
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    [(header::WWW_AUTHENTICATE, r#"Basic realm="admin""#)],
+                    body,
+                )
+                    .into_response();
+            }
+            AppError::RateLimited(limited) => {
+                let body = Json(json!({
+                    "error": limited.to_string(),
+                }));
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(
+                        header::RETRY_AFTER,
+                        limited.retry_after_seconds().to_string(),
+                    )],
+                    body,
+                )
+                    .into_response();
+            }
+            AppError::EmailRejected(rejection) => {
+                let body = Json(json!({
+                    "error": rejection.to_string(),
+                    "reason": rejection.reason(),
+                }));
+                return (StatusCode::BAD_REQUEST, body).into_response();
+            }
+            AppError::NotFound(e) => (StatusCode::NOT_FOUND, e),
+            AppError::Conflict(e) => (StatusCode::CONFLICT, e),
             AppError::InternalServerError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
-            _ => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Unknown error".to_owned(),
-            ),
+            AppError::ServiceUnavailable(e) => (StatusCode::SERVICE_UNAVAILABLE, e),
+            other => {
+                tracing::error!("{:?}", other);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Unknown error".to_owned(),
+                )
+            }
         };
 
         let body = Json(json!({
@@ -38,11 +88,79 @@ impl IntoResponse for AppError {
     }
 }
 
-impl<E> From<E> for AnyError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(err: E) -> Self {
-        Self(err.into())
+impl From<crate::repository::RepositoryError> for AppError {
+    fn from(e: crate::repository::RepositoryError) -> Self {
+        match e {
+            crate::repository::RepositoryError::DuplicateEmail
+            | crate::repository::RepositoryError::InvalidTransition(_) => {
+                AppError::Conflict(e.to_string())
+            }
+            crate::repository::RepositoryError::Unexpected(e) => AppError::Other(e),
+        }
+    }
+}
+
+impl From<crate::import::ImportError> for AppError {
+    fn from(e: crate::import::ImportError) -> Self {
+        match e {
+            crate::import::ImportError::Repository(e) => e.into(),
+            crate::import::ImportError::Unexpected(e) => AppError::Other(e),
+            e => AppError::BadRequest(e.to_string()),
+        }
+    }
+}
+
+impl From<crate::email_policy::EmailPolicyError> for AppError {
+    fn from(e: crate::email_policy::EmailPolicyError) -> Self {
+        match e {
+            crate::email_policy::EmailPolicyError::Rejected(rejection) => rejection.into(),
+            crate::email_policy::EmailPolicyError::Unexpected(e) => AppError::Other(e),
+        }
+    }
+}
+
+impl From<crate::bot_protection::BotProtectionError> for AppError {
+    fn from(e: crate::bot_protection::BotProtectionError) -> Self {
+        match e {
+            crate::bot_protection::BotProtectionError::Rejected(rejection) => {
+                AppError::BadRequest(rejection.to_string())
+            }
+            crate::bot_protection::BotProtectionError::CaptchaUnavailable(e) => {
+                tracing::error!(error = ?e, "Failed to verify a captcha");
+                AppError::ServiceUnavailable(
+                    "The captcha could not be checked, try again in a moment.".to_owned(),
+                )
+            }
+        }
+    }
+}
+
+impl From<crate::newsletter_templates::NewsletterTemplateError> for AppError {
+    fn from(e: crate::newsletter_templates::NewsletterTemplateError) -> Self {
+        match e {
+            crate::newsletter_templates::NewsletterTemplateError::DuplicateName => {
+                AppError::Conflict(e.to_string())
+            }
+            crate::newsletter_templates::NewsletterTemplateError::Unexpected(e) => {
+                AppError::Other(e)
+            }
+        }
+    }
+}
+
+impl From<crate::newsletter_templates::TemplateError> for AppError {
+    fn from(e: crate::newsletter_templates::TemplateError) -> Self {
+        AppError::BadRequest(e.to_string())
+    }
+}
+
+impl From<crate::newsletter_issues::NewsletterIssueError> for AppError {
+    fn from(e: crate::newsletter_issues::NewsletterIssueError) -> Self {
+        match e {
+            crate::newsletter_issues::NewsletterIssueError::InvalidTransition(_) => {
+                AppError::Conflict(e.to_string())
+            }
+            crate::newsletter_issues::NewsletterIssueError::Unexpected(e) => AppError::Other(e),
+        }
     }
 }
@@ -0,0 +1,196 @@
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::repository::Subscriber;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportColumn {
+    Id,
+    Email,
+    Name,
+    Status,
+    SubscribedAt,
+}
+
+impl ExportColumn {
+    pub const ALL: [ExportColumn; 5] = [
+        ExportColumn::Id,
+        ExportColumn::Email,
+        ExportColumn::Name,
+        ExportColumn::Status,
+        ExportColumn::SubscribedAt,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportColumn::Id => "id",
+            ExportColumn::Email => "email",
+            ExportColumn::Name => "name",
+            ExportColumn::Status => "status",
+            ExportColumn::SubscribedAt => "subscribed_at",
+        }
+    }
+
+    fn value(&self, subscriber: &Subscriber) -> String {
+        match self {
+            ExportColumn::Id => subscriber.id.to_string(),
+            ExportColumn::Email => subscriber.email.clone(),
+            ExportColumn::Name => subscriber.name.clone(),
+            ExportColumn::Status => subscriber.status.as_str().to_owned(),
+            ExportColumn::SubscribedAt => subscriber.subscribed_at.to_rfc3339(),
+        }
+    }
+
+    /// Parse a comma-separated list such as `email,name`. Empty means every column.
+    pub fn parse_list(columns: &str) -> anyhow::Result<Vec<ExportColumn>> {
+        if columns.trim().is_empty() {
+            return Ok(Self::ALL.to_vec());
+        }
+        columns
+            .split(',')
+            .map(|column| {
+                let column = column.trim();
+                Self::ALL
+                    .into_iter()
+                    .find(|c| c.as_str() == column)
+                    .ok_or_else(|| anyhow!("Unknown column `{}`.", column))
+            })
+            .collect()
+    }
+}
+
+/// Writes subscribers one line at a time in the requested format.
+pub struct ExportEncoder {
+    format: ExportFormat,
+    columns: Vec<ExportColumn>,
+}
+
+impl ExportEncoder {
+    pub fn new(format: ExportFormat, columns: Vec<ExportColumn>) -> Self {
+        Self { format, columns }
+    }
+
+    /// The first line of the export, if the format has one.
+    pub fn header(&self) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.format {
+            ExportFormat::Csv => csv_line(self.columns.iter().map(|c| c.as_str())).map(Some),
+            ExportFormat::Jsonl => Ok(None),
+        }
+    }
+
+    pub fn encode(&self, subscriber: &Subscriber) -> anyhow::Result<Vec<u8>> {
+        let values = self.columns.iter().map(|c| c.value(subscriber));
+        match self.format {
+            ExportFormat::Csv => csv_line(values),
+            ExportFormat::Jsonl => {
+                let object: Map<String, Value> = self
+                    .columns
+                    .iter()
+                    .map(|c| c.as_str().to_owned())
+                    .zip(values.map(Value::String))
+                    .collect();
+                let mut line = serde_json::to_vec(&object)?;
+                line.push(b'\n');
+                Ok(line)
+            }
+        }
+    }
+}
+
+fn csv_line<I, T>(fields: I) -> anyhow::Result<Vec<u8>>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(fields)?;
+    writer.into_inner().context("Failed to flush the CSV line")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::SubscriptionStatus;
+    use chrono::Utc;
+    use claims::assert_err;
+    use uuid::Uuid;
+
+    fn subscriber() -> Subscriber {
+        Subscriber {
+            id: Uuid::new_v4(),
+            email: "ursula@example.com".into(),
+            name: "Le Guin, Ursula".into(),
+            timezone: None,
+            status: SubscriptionStatus::Confirmed,
+            subscribed_at: Utc::now(),
+            confirmed_at: Some(Utc::now()),
+            unsubscribed_at: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn columns_are_parsed_in_the_requested_order() {
+        assert_eq!(
+            ExportColumn::parse_list("name, email").unwrap(),
+            vec![ExportColumn::Name, ExportColumn::Email]
+        );
+        assert_eq!(ExportColumn::parse_list("").unwrap().len(), 5);
+        assert_err!(ExportColumn::parse_list("email,password"));
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        let encoder = ExportEncoder::new(
+            ExportFormat::Csv,
+            vec![ExportColumn::Email, ExportColumn::Name],
+        );
+        assert_eq!(encoder.header().unwrap().unwrap(), b"email,name\n");
+        assert_eq!(
+            encoder.encode(&subscriber()).unwrap(),
+            b"ursula@example.com,\"Le Guin, Ursula\"\n"
+        );
+    }
+
+    #[test]
+    fn jsonl_lines_only_have_the_selected_columns() {
+        let encoder = ExportEncoder::new(
+            ExportFormat::Jsonl,
+            vec![ExportColumn::Email, ExportColumn::Status],
+        );
+        assert_eq!(encoder.header().unwrap(), None);
+        let line = encoder.encode(&subscriber()).unwrap();
+        assert_eq!(line.last(), Some(&b'\n'));
+        let object: Value = serde_json::from_slice(&line).unwrap();
+        assert_eq!(
+            object,
+            serde_json::json!({ "email": "ursula@example.com", "status": "confirmed" })
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! One-off messages carried across a redirect in a signed cookie, shown by the next page.
+
+use axum_extra::extract::cookie::{Cookie, Key, SameSite, SignedCookieJar};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+
+const FLASH_COOKIE: &str = "flash";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlashLevel {
+    Info,
+    Error,
+}
+
+impl FlashLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlashLevel::Info => "info",
+            FlashLevel::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlashMessage {
+    pub level: FlashLevel,
+    pub text: String,
+}
+
+impl FlashMessage {
+    pub fn error(text: impl Into<String>) -> Self {
+        Self {
+            level: FlashLevel::Error,
+            text: text.into(),
+        }
+    }
+}
+
+/// The key signing cookies, derived from `secret` so that it does not double as an HMAC key.
+pub fn cookie_key(secret: &Secret<String>) -> Key {
+    let mut mac = Hmac::<Sha512>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(b"cookies");
+    // 64 bytes, as many as `Key` needs.
+    Key::from(mac.finalize().into_bytes().as_slice())
+}
+
+/// Replaces any message not shown yet.
+pub fn push(jar: SignedCookieJar, message: &FlashMessage) -> SignedCookieJar {
+    let value = serde_json::to_string(message).expect("Flash messages serialize to JSON");
+    jar.add(flash_cookie(value))
+}
+
+/// The pending message, if any, removed from the returned jar.
+pub fn take(jar: SignedCookieJar) -> (SignedCookieJar, Option<FlashMessage>) {
+    match jar.get(FLASH_COOKIE) {
+        Some(cookie) => {
+            let message = serde_json::from_str(cookie.value()).ok();
+            (jar.remove(flash_cookie(String::new())), message)
+        }
+        None => (jar, None),
+    }
+}
+
+fn flash_cookie(value: String) -> Cookie<'static> {
+    Cookie::build(FLASH_COOKIE, value)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_none, assert_some_eq};
+
+    #[test]
+    fn messages_are_shown_once() {
+        let jar = SignedCookieJar::new(cookie_key(&Secret::new("secret".into())));
+        let message = FlashMessage::error("Try again.");
+
+        let (jar, taken) = take(push(jar, &message));
+        assert_some_eq!(taken, message);
+        assert_none!(take(jar).1);
+    }
+}
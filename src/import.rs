@@ -0,0 +1,386 @@
+use std::collections::{HashMap, HashSet};
+
+use csv_async::{AsyncReaderBuilder, ErrorKind, StringRecord, Trim};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::AsyncRead;
+use tracing::info;
+
+use crate::domain::{
+    NameRules, NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus,
+};
+use crate::email_policy::{EmailPolicy, EmailPolicyError, EmailRejection};
+use crate::repository::{RepositoryError, SubscriberRepository};
+
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+/// Larger batches are split, so that a single insert holds a bounded number of rows.
+pub const MAX_BATCH_SIZE: usize = 10_000;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ImportOptions {
+    /// Store the imported subscribers as confirmed rather than pending confirmation.
+    pub confirmed: bool,
+    /// Clamped between 1 and [`MAX_BATCH_SIZE`].
+    pub batch_size: usize,
+    /// Taken from the settings rather than from the caller.
+    #[serde(skip)]
+    pub name_rules: NameRules,
+    /// Look up the mail servers of each domain, as for signups. Taken from the settings.
+    #[serde(skip)]
+    pub check_mx: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            confirmed: false,
+            batch_size: DEFAULT_BATCH_SIZE,
+            name_rules: NameRules::default(),
+            check_mx: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub rejected: Vec<RejectedRow>,
+}
+
+impl ImportReport {
+    /// The counts alone, without the rejected lines and their personal data.
+    pub fn summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "imported": self.imported,
+            "rejected": self.rejected.len(),
+        })
+    }
+}
+
+/// A CSV line that was not imported. `line` is 1-based and counts the header.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct RejectedRow {
+    pub line: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("The CSV header must have `email` and `name` columns.")]
+    MissingColumns,
+    #[error("Failed to read the CSV input: {0}")]
+    Csv(#[from] csv_async::Error),
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+/// Import subscribers from a CSV with `email` and `name` columns (in any order,
+/// other columns are ignored), inserting them `options.batch_size` at a time.
+/// Invalid rows, emails refused by `policy` and emails that are already subscribed
+/// are reported, not fatal.
+#[tracing::instrument(name = "Import subscribers", skip(subscribers, policy, input))]
+pub async fn import_subscribers<R>(
+    subscribers: &dyn SubscriberRepository,
+    policy: &EmailPolicy,
+    input: R,
+    options: &ImportOptions,
+) -> Result<ImportReport, ImportError>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut reader = AsyncReaderBuilder::new()
+        .trim(Trim::All)
+        .flexible(true)
+        .create_reader(input);
+    let headers = reader.headers().await?;
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let (email_column, name_column) = match (column("email"), column("name")) {
+        (Some(email), Some(name)) => (email, name),
+        _ => return Err(ImportError::MissingColumns),
+    };
+
+    let status = if options.confirmed {
+        SubscriptionStatus::Confirmed
+    } else {
+        SubscriptionStatus::PendingConfirmation
+    };
+    let batch_size = options.batch_size.clamp(1, MAX_BATCH_SIZE);
+    let mut report = ImportReport::default();
+    let mut batch = Batch::default();
+    let mut record = StringRecord::new();
+    // The policy only looks at the domain, so each one is checked once per import.
+    let mut verdicts: HashMap<String, Result<(), EmailRejection>> = HashMap::new();
+    loop {
+        match reader.read_record(&mut record).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) if matches!(e.kind(), ErrorKind::Io(_)) => return Err(e.into()),
+            // Malformed lines, e.g. invalid UTF-8, only cost that line.
+            Err(e) => {
+                report.rejected.push(RejectedRow {
+                    line: e.position().map_or(0, |p| p.line()),
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        }
+        let line = record.position().map_or(0, |p| p.line());
+        let field = |index: usize| record.get(index).unwrap_or_default().to_owned();
+        let subscriber =
+            match parse_row(field(email_column), field(name_column), &options.name_rules) {
+                Ok(subscriber) => subscriber,
+                Err(reason) => {
+                    report.rejected.push(RejectedRow { line, reason });
+                    continue;
+                }
+            };
+        let verdict = match verdicts.get(subscriber.email.domain()) {
+            Some(verdict) => *verdict,
+            None => {
+                let verdict = match policy.check(&subscriber.email, options.check_mx).await {
+                    Ok(()) => Ok(()),
+                    Err(EmailPolicyError::Rejected(rejection)) => Err(rejection),
+                    Err(EmailPolicyError::Unexpected(e)) => return Err(e.into()),
+                };
+                verdicts.insert(subscriber.email.domain().to_owned(), verdict);
+                verdict
+            }
+        };
+        match verdict {
+            Ok(()) => batch.push(line, subscriber),
+            Err(rejection) => report.rejected.push(RejectedRow {
+                line,
+                reason: rejection.to_string(),
+            }),
+        }
+        if batch.len() >= batch_size {
+            batch.flush(subscribers, status, &mut report).await?;
+        }
+    }
+    batch.flush(subscribers, status, &mut report).await?;
+
+    report.rejected.sort_by_key(|row| row.line);
+    info!(
+        imported = report.imported,
+        rejected = report.rejected.len(),
+        "Finished importing subscribers"
+    );
+    Ok(report)
+}
+
+fn parse_row(email: String, name: String, name_rules: &NameRules) -> Result<NewSubscriber, String> {
+    Ok(NewSubscriber {
+        email: SubscriberEmail::parse(email).map_err(|e| e.to_string())?,
+        name: SubscriberName::parse_with(name, name_rules).map_err(|e| e.to_string())?,
+        timezone: None,
+    })
+}
+
+#[derive(Default)]
+struct Batch {
+    lines: Vec<u64>,
+    subscribers: Vec<NewSubscriber>,
+}
+
+impl Batch {
+    fn push(&mut self, line: u64, subscriber: NewSubscriber) {
+        self.lines.push(line);
+        self.subscribers.push(subscriber);
+    }
+
+    fn len(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    async fn flush(
+        &mut self,
+        subscribers: &dyn SubscriberRepository,
+        status: SubscriptionStatus,
+        report: &mut ImportReport,
+    ) -> Result<(), RepositoryError> {
+        if self.subscribers.is_empty() {
+            return Ok(());
+        }
+        let mut inserted: HashSet<String> = subscribers
+            .insert_many(&self.subscribers, status)
+            .await?
+            .into_iter()
+            .map(|s| s.email)
+            .collect();
+        // Only the first occurrence of an email can have been inserted.
+        for (line, subscriber) in self.lines.drain(..).zip(self.subscribers.drain(..)) {
+            if inserted.remove(subscriber.email.original()) {
+                report.imported += 1;
+            } else {
+                report.rejected.push(RejectedRow {
+                    line,
+                    reason: RepositoryError::DuplicateEmail.to_string(),
+                });
+            }
+        }
+        info!(
+            imported = report.imported,
+            "Imported a batch of subscribers"
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email_policy::{FakeMxResolver, InMemoryDomainRules, MxResolver};
+    use crate::repository::{InMemorySubscriberRepository, SubscriberFilter};
+    use async_trait::async_trait;
+    use claims::{assert_err, assert_ok};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Counts lookups, answering that every domain accepts mail.
+    #[derive(Default)]
+    struct CountingMxResolver(AtomicUsize);
+
+    #[async_trait]
+    impl MxResolver for CountingMxResolver {
+        async fn accepts_mail(&self, _domain: &str) -> anyhow::Result<bool> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(true)
+        }
+    }
+
+    async fn import(
+        repository: &InMemorySubscriberRepository,
+        csv: &str,
+        options: ImportOptions,
+    ) -> Result<ImportReport, ImportError> {
+        let policy = EmailPolicy::new(
+            Arc::new(InMemoryDomainRules::new()),
+            Arc::new(FakeMxResolver::new()),
+        );
+        import_subscribers(repository, &policy, csv.as_bytes(), &options).await
+    }
+
+    #[tokio::test]
+    async fn addresses_refused_by_the_email_policy_are_reported() {
+        let repository = InMemorySubscriberRepository::new();
+        let csv = "email,name\n\
+                   ursula@mailinator.com,Ursula\n\
+                   octavia@example.com,Octavia\n";
+
+        let report = assert_ok!(import(&repository, csv, ImportOptions::default()).await);
+
+        assert_eq!(report.imported, 1);
+        assert_eq!(
+            report.rejected,
+            vec![RejectedRow {
+                line: 2,
+                reason: EmailRejection::DisposableDomain.to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn each_domain_is_checked_once_per_import() {
+        let repository = InMemorySubscriberRepository::new();
+        let resolver = Arc::new(CountingMxResolver::default());
+        let policy = EmailPolicy::new(Arc::new(InMemoryDomainRules::new()), resolver.clone());
+        let csv = "email,name\n\
+                   ursula@example.com,Ursula\n\
+                   octavia@example.com,Octavia\n\
+                   ted@example.net,Ted\n\
+                   le.guin@example.com,Le Guin\n";
+        let options = ImportOptions {
+            check_mx: true,
+            ..ImportOptions::default()
+        };
+
+        let report =
+            assert_ok!(import_subscribers(&repository, &policy, csv.as_bytes(), &options).await);
+
+        assert_eq!(report.imported, 4);
+        assert_eq!(resolver.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn valid_rows_are_imported_and_invalid_ones_reported() {
+        let repository = InMemorySubscriberRepository::new();
+        let csv = "name,email,source\n\
+                   Ursula,ursula@example.com,old-tool\n\
+                   ,nameless@example.com,old-tool\n\
+                   Octavia,not-an-email,old-tool\n\
+                   Octavia,octavia@example.com,old-tool\n";
+
+        let report = assert_ok!(import(&repository, csv, ImportOptions::default()).await);
+
+        assert_eq!(report.imported, 2);
+        let lines: Vec<_> = report.rejected.iter().map(|row| row.line).collect();
+        assert_eq!(lines, vec![3, 4]);
+    }
+
+    #[tokio::test]
+    async fn duplicates_are_skipped_within_and_across_batches() {
+        let repository = InMemorySubscriberRepository::new();
+        import(
+            &repository,
+            "email,name\noctavia@example.com,Octavia\n",
+            ImportOptions::default(),
+        )
+        .await
+        .unwrap();
+        let csv = "email,name\n\
+                   ursula@example.com,Ursula\n\
+                   ursula@example.com,Ursula again\n\
+                   octavia@example.com,Octavia\n\
+                   ursula@example.com,Ursula once more\n";
+        let options = ImportOptions {
+            batch_size: 2,
+            ..ImportOptions::default()
+        };
+
+        let report = import(&repository, csv, options).await.unwrap();
+
+        assert_eq!(report.imported, 1);
+        let lines: Vec<_> = report.rejected.iter().map(|row| row.line).collect();
+        assert_eq!(lines, vec![3, 4, 5]);
+        assert!(report
+            .rejected
+            .iter()
+            .all(|row| row.reason == RepositoryError::DuplicateEmail.to_string()));
+    }
+
+    #[tokio::test]
+    async fn imported_subscribers_can_be_pre_confirmed() {
+        let repository = InMemorySubscriberRepository::new();
+        let options = ImportOptions {
+            confirmed: true,
+            ..ImportOptions::default()
+        };
+
+        import(
+            &repository,
+            "email,name\nursula@example.com,Ursula\n",
+            options,
+        )
+        .await
+        .unwrap();
+
+        let stored = repository.list(&SubscriberFilter::default()).await.unwrap();
+        assert_eq!(stored[0].status, SubscriptionStatus::Confirmed);
+    }
+
+    #[tokio::test]
+    async fn a_header_without_the_expected_columns_is_an_error() {
+        let repository = InMemorySubscriberRepository::new();
+        assert_err!(
+            import(
+                &repository,
+                "mail,full_name\na@b.com,A\n",
+                ImportOptions::default()
+            )
+            .await
+        );
+    }
+}
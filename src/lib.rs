@@ -1,42 +1,163 @@
 use anyhow::Context;
-use axum::extract::State;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
-use axum::routing::{get, post};
-use axum::{Extension, Form, Json, Router};
-use sqlx::PgPool;
+use axum::body::Body;
+use axum::middleware;
+use axum::routing::{get, post, put};
+use axum::Router;
+use state::AppState;
 use std::net::SocketAddr;
 use tokio::signal;
-use tower_http::{classify::ServerErrorsFailureClass, trace::TraceLayer};
+use tower_http::compression::CompressionLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
 use tracing::info;
 
+pub mod audit;
+pub mod authentication;
+pub mod bot_protection;
+pub mod cli;
 pub mod configuration;
 pub mod domain;
+pub mod email_client;
+pub mod email_policy;
 pub mod error;
+pub mod export;
+pub mod flash;
+pub mod import;
+pub mod migrations;
+pub mod newsletter_issues;
+pub mod newsletter_templates;
+pub mod privacy;
+pub mod rate_limit;
+pub mod repository;
+pub mod request_id;
 pub mod routes;
+pub mod state;
 pub mod telemetry;
+pub mod templates;
 
-async fn ping() -> impl IntoResponse {
-    "pong"
-}
-
-pub fn new_router(db: PgPool) -> Router {
-    let app = Router::new()
-        .route("/", get(ping))
+pub fn new_router(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/",
+            get(routes::signup_page).merge(post(routes::signup).layer(
+                middleware::from_fn_with_state(state.clone(), rate_limit::limit_by_client_ip),
+            )),
+        )
         .route("/health_check", get(routes::health_check))
-        .route("/subscriptions", post(routes::subscriptions))
-        .layer(TraceLayer::new_for_http())
-        .with_state(db);
-    app
+        .route(
+            "/subscriptions",
+            post(routes::subscriptions).layer(middleware::from_fn_with_state(
+                state.clone(),
+                rate_limit::limit_by_client_ip,
+            )),
+        )
+        .route(
+            "/subscriptions/form-token",
+            get(routes::subscription_form_token),
+        )
+        .route(
+            "/subscriptions/check-your-inbox",
+            get(routes::check_inbox_page),
+        )
+        .route("/subscriptions/confirm", get(routes::confirm_subscription))
+        .route(
+            "/subscriptions/unsubscribe",
+            get(routes::unsubscribe_page).post(routes::unsubscribe),
+        )
+        .route("/newsletters/:id/view", get(routes::view_newsletter_issue))
+        .route(
+            "/data-requests/export",
+            post(routes::request_data_export).layer(middleware::from_fn_with_state(
+                state.clone(),
+                rate_limit::limit_by_client_ip,
+            )),
+        )
+        .route(
+            "/data-requests/erasure",
+            post(routes::request_erasure).layer(middleware::from_fn_with_state(
+                state.clone(),
+                rate_limit::limit_by_client_ip,
+            )),
+        )
+        .route(
+            "/data-requests/erasure/confirm",
+            get(routes::confirm_erasure),
+        )
+        .route("/admin/subscribers", get(routes::admin::list_subscribers))
+        .route(
+            "/admin/subscribers/export",
+            get(routes::admin::export_subscribers).layer(CompressionLayer::new()),
+        )
+        .route(
+            "/admin/subscribers/import",
+            post(routes::admin::import_subscribers),
+        )
+        .route(
+            "/admin/subscribers/:id",
+            get(routes::admin::get_subscriber)
+                .patch(routes::admin::update_subscriber)
+                .delete(routes::admin::delete_subscriber),
+        )
+        .route(
+            "/admin/subscribers/:id/data",
+            get(routes::admin::get_subscriber_data),
+        )
+        .route(
+            "/admin/subscribers/:id/erase",
+            post(routes::admin::erase_subscriber),
+        )
+        .route("/admin/audit-events", get(routes::admin::list_audit_events))
+        .route(
+            "/admin/email-domains",
+            get(routes::admin::list_email_domain_rules),
+        )
+        .route(
+            "/admin/email-domains/:domain",
+            put(routes::admin::set_email_domain_rule)
+                .delete(routes::admin::remove_email_domain_rule),
+        )
+        .route(
+            "/admin/newsletter-templates",
+            get(routes::admin::list_newsletter_templates)
+                .post(routes::admin::create_newsletter_template),
+        )
+        .route(
+            "/admin/newsletter-templates/:id",
+            get(routes::admin::get_newsletter_template)
+                .put(routes::admin::update_newsletter_template)
+                .delete(routes::admin::delete_newsletter_template),
+        )
+        .route(
+            "/admin/newsletter-templates/:id/preview",
+            get(routes::admin::preview_newsletter_template),
+        )
+        .route(
+            "/admin/newsletters",
+            get(routes::admin::list_newsletters).post(routes::admin::create_newsletter),
+        )
+        .route("/admin/newsletters/:id", get(routes::admin::get_newsletter))
+        .route(
+            "/admin/newsletters/:id/schedule",
+            put(routes::admin::reschedule_newsletter).delete(routes::admin::cancel_newsletter),
+        )
+        .route(
+            "/webhooks/postmark/bounces",
+            post(routes::record_postmark_bounce),
+        )
+        .layer(TraceLayer::new_for_http().make_span_with(request_id::make_span::<Body>))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state)
 }
 
-pub async fn run(addr: SocketAddr, db: PgPool) -> anyhow::Result<()> {
+pub async fn run(addr: SocketAddr, state: AppState) -> anyhow::Result<()> {
     // build our application with a single route
-    let app = new_router(db);
+    let app = new_router(state);
 
     info!("Starting HTTP server at {:?}", &addr);
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        // The peer address is the client IP for rate limiting.
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .with_graceful_shutdown(shutdown_signal())
         .await
         .context("Error starting HTTP server")
@@ -1,22 +1,37 @@
 use anyhow::Context;
+use clap::Parser;
 use dotenvy::dotenv;
-use secrecy::ExposeSecret;
+use secrecy::Secret;
 use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
-use tracing::info;
 use tracing::log::LevelFilter;
-use zero2prod::configuration::get_configuration;
-use zero2prod::telemetry::parse_log_level;
-use zero2prod::{run, telemetry};
+use zero2prod::audit::{Actor, AuditAction, AuditTarget, NewAuditEvent, PgAuditLog};
+use zero2prod::cli::{Cli, Command};
+use zero2prod::configuration::{
+    configuration_directory, current_environment, default_secret_providers, get_configuration,
+    load_configuration, ConfigWatcher, DatabaseSettings, Environment,
+};
+use zero2prod::email_policy::{EmailPolicy, PgDomainRules, MX_CHECK_FEATURE};
+use zero2prod::import::{import_subscribers, ImportOptions};
+use zero2prod::newsletter_issues::DeliveryWorker;
+use zero2prod::repository::PgSubscriberRepository;
+use zero2prod::state::AppState;
+use zero2prod::telemetry::{parse_log_level, LogLevelHandle};
+use zero2prod::{audit, authentication, migrations, run, telemetry};
 
 const DB_MAX_CONNECTIONS: u32 = 100;
 const DB_MAX_LIFETIME: Duration = Duration::from_secs(3);
 const DB_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(2);
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv().ok();
+    let cli = Cli::parse();
 
     let crate_log_level = parse_log_level(std::env::var("APP_LOG_LEVEL"), Some(LevelFilter::Info));
     let http_log_level = parse_log_level(std::env::var("HTTP_LOG_LEVEL"), None);
@@ -26,20 +41,179 @@ async fn main() -> anyhow::Result<()> {
         .build()?;
 
     // initialize tracing
-    telemetry::init_tracing("zero2prod".into(), tracing_options);
+    let log_level = telemetry::init_tracing("zero2prod".into(), tracing_options);
 
-    let configuration = get_configuration()
-        .context("Error parsing configuration")
-        .unwrap();
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(log_level).await,
+        Command::Migrate { dry_run } => migrate(dry_run).await,
+        Command::CheckConfig { environment } => check_config(environment),
+        Command::CreateAdmin { username } => create_admin(&username).await,
+        Command::ImportSubscribers {
+            file,
+            confirmed,
+            batch_size,
+        } => {
+            import(
+                &file,
+                ImportOptions {
+                    confirmed,
+                    batch_size,
+                    ..ImportOptions::default()
+                },
+            )
+            .await
+        }
+    }
+}
+
+async fn serve(log_level: LogLevelHandle) -> anyhow::Result<()> {
+    let configuration_dir = configuration_directory()?;
+    let environment = current_environment()?;
+    let configuration = load_configuration(
+        &configuration_dir,
+        &environment,
+        &default_secret_providers(),
+    )
+    .context("Error parsing configuration")?;
+    if let Some(level) = &configuration.application.log_level {
+        log_level.set_crate_level(level.parse()?)?;
+    }
 
     let address = SocketAddr::from(([0, 0, 0, 0], configuration.application.port));
+    let db_connection = connect(&configuration.database).await?;
+    migrations::prepare_database(&db_connection, configuration.database.migrate_on_startup).await?;
+
+    let watcher = ConfigWatcher::new(
+        configuration_dir,
+        environment,
+        default_secret_providers(),
+        configuration,
+    );
+    let settings = watcher.handle();
+    tokio::spawn(watcher.run(CONFIG_POLL_INTERVAL));
+    tokio::spawn(log_level.follow(settings.clone()));
+
+    let state = AppState::new(db_connection, settings)?;
+    // Publishes scheduled issues and sends them, alongside every other replica.
+    tokio::spawn(
+        DeliveryWorker::new(
+            state.db_pool.clone(),
+            state.email_client.clone(),
+            state.base_url.0.clone(),
+            state.audit_log.clone(),
+        )
+        .run_until_stopped(),
+    );
+    run(address, state).await
+}
+
+async fn migrate(dry_run: bool) -> anyhow::Result<()> {
+    let configuration = get_configuration().context("Error parsing configuration")?;
+    let db_connection = connect(&configuration.database).await?;
+
+    let pending = migrations::pending_migrations(&db_connection).await?;
+    if pending.is_empty() {
+        println!("The database is up to date.");
+        return Ok(());
+    }
+    for migration in &pending {
+        println!("{}/{}", migration.version, migration.description);
+    }
+    if dry_run {
+        println!("{} pending migration(s), none applied.", pending.len());
+        return Ok(());
+    }
+    migrations::run_migrations(&db_connection).await?;
+    println!("Applied {} migration(s).", pending.len());
+    Ok(())
+}
+
+fn check_config(environment: String) -> anyhow::Result<()> {
+    let environment = Environment::try_from(environment)?;
+    let configuration = load_configuration(
+        &configuration_directory()?,
+        &environment,
+        &default_secret_providers(),
+    )?;
+    // `Secret` fields are printed as `[REDACTED]`.
+    println!(
+        "Configuration for `{}` is valid:\n{:#?}",
+        environment.as_str(),
+        configuration
+    );
+    Ok(())
+}
+
+async fn create_admin(username: &str) -> anyhow::Result<()> {
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .context("Failed to read the password from stdin")?;
+    let password = Secret::new(password.trim_end_matches(['\r', '\n']).to_owned());
+
+    let configuration = get_configuration().context("Error parsing configuration")?;
+    let db_connection = connect(&configuration.database).await?;
+    let user_id = authentication::create_admin(&db_connection, username, password).await?;
+    audit::record(
+        &PgAuditLog::new(db_connection),
+        NewAuditEvent::new(
+            Actor::System,
+            AuditAction::AdminCreated,
+            AuditTarget::user(user_id),
+        ),
+    )
+    .await;
+    println!("Created admin {} ({}).", username, user_id);
+    Ok(())
+}
+
+async fn import(file: &Path, mut options: ImportOptions) -> anyhow::Result<()> {
+    let input: Box<dyn tokio::io::AsyncRead + Unpin + Send> = if file == Path::new("-") {
+        Box::new(tokio::io::stdin())
+    } else {
+        Box::new(
+            tokio::fs::File::open(file)
+                .await
+                .with_context(|| format!("Failed to open {}", file.display()))?,
+        )
+    };
+
+    let configuration = get_configuration().context("Error parsing configuration")?;
+    options.name_rules = configuration.subscriber_names.clone();
+    options.check_mx = configuration.feature_enabled(MX_CHECK_FEATURE);
+    let db_connection = connect(&configuration.database).await?;
+    let subscribers = PgSubscriberRepository::new(db_connection.clone());
+    let policy =
+        EmailPolicy::with_dns_resolver(Arc::new(PgDomainRules::new(db_connection.clone())));
+    let report = import_subscribers(&subscribers, &policy, input, &options).await?;
+    audit::record(
+        &PgAuditLog::new(db_connection),
+        NewAuditEvent::new(
+            Actor::System,
+            AuditAction::SubscribersImported,
+            AuditTarget::subscriber_list(),
+        )
+        .details(report.summary()),
+    )
+    .await;
+
+    for row in &report.rejected {
+        println!("line {}: {}", row.line, row.reason);
+    }
+    println!(
+        "Imported {} subscriber(s), rejected {} line(s).",
+        report.imported,
+        report.rejected.len()
+    );
+    Ok(())
+}
 
-    let db_connection = PgPoolOptions::new()
+async fn connect(settings: &DatabaseSettings) -> anyhow::Result<PgPool> {
+    PgPoolOptions::new()
         .max_connections(DB_MAX_CONNECTIONS)
         .max_lifetime(DB_MAX_LIFETIME)
         .acquire_timeout(DB_ACQUIRE_TIMEOUT)
-        .connect_with(configuration.database.with_db())
+        .connect_with(settings.with_db())
         .await
-        .context("Failed to connect to database")?;
-    run(address, db_connection).await
+        .context("Failed to connect to database")
 }
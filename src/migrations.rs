@@ -0,0 +1,215 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Context};
+use sqlx::migrate::{Migration, Migrator};
+use sqlx::{PgConnection, PgPool};
+use tracing::{info, warn};
+
+use crate::domain::SubscriberEmail;
+
+/// The migrations in `migrations/`, embedded in the binary at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Key of the Postgres advisory lock held while migrating on startup.
+const MIGRATION_LOCK_KEY: i64 = 0x7a65_726f_3270_7264;
+
+/// Versions recorded in sqlx's bookkeeping table, empty on a fresh database.
+pub async fn applied_versions(db_connection: &PgPool) -> anyhow::Result<HashSet<i64>> {
+    let mut connection = db_connection
+        .acquire()
+        .await
+        .context("Failed to acquire a connection")?;
+    applied_versions_on(&mut connection).await
+}
+
+async fn applied_versions_on(connection: &mut PgConnection) -> anyhow::Result<HashSet<i64>> {
+    let table_exists =
+        sqlx::query_scalar!(r#"SELECT to_regclass('_sqlx_migrations') IS NOT NULL AS "exists!""#)
+            .fetch_one(&mut *connection)
+            .await
+            .context("Failed to look up the migrations table")?;
+    if !table_exists {
+        return Ok(HashSet::new());
+    }
+
+    let versions = sqlx::query_scalar!("SELECT version FROM _sqlx_migrations WHERE success")
+        .fetch_all(&mut *connection)
+        .await
+        .context("Failed to list applied migrations")?;
+    Ok(versions.into_iter().collect())
+}
+
+/// Embedded migrations that have not been applied yet, in the order they would run.
+pub async fn pending_migrations(db_connection: &PgPool) -> anyhow::Result<Vec<&'static Migration>> {
+    let applied = applied_versions(db_connection).await?;
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .collect())
+}
+
+#[tracing::instrument(name = "Run database migrations", skip(db_connection))]
+pub async fn run_migrations(db_connection: &PgPool) -> anyhow::Result<()> {
+    MIGRATOR
+        .run(db_connection)
+        .await
+        .context("Failed to migrate the database")?;
+    let mut connection = db_connection
+        .acquire()
+        .await
+        .context("Failed to acquire a connection")?;
+    backfill_canonical_emails(&mut connection).await
+}
+
+/// Make sure the schema matches this binary before serving traffic.
+///
+/// Fails if the database holds migrations this binary does not know about, which
+/// means a newer version has been deployed against it. When `migrate` is set, pending
+/// migrations are applied while holding an advisory lock, so that only one replica
+/// migrates at a time and the others wait for it to finish.
+#[tracing::instrument(name = "Prepare the database schema", skip(db_connection))]
+pub async fn prepare_database(db_connection: &PgPool, migrate: bool) -> anyhow::Result<()> {
+    let mut connection = db_connection
+        .acquire()
+        .await
+        .context("Failed to acquire a connection")?;
+
+    if !migrate {
+        ensure_no_unknown_migrations(&mut connection).await?;
+        let applied = applied_versions_on(&mut connection).await?;
+        let pending = MIGRATOR
+            .iter()
+            .filter(|migration| !applied.contains(&migration.version))
+            .count();
+        if pending > 0 {
+            warn!(
+                "{} migration(s) are pending and `database.migrate_on_startup` is disabled",
+                pending
+            );
+        }
+        return Ok(());
+    }
+
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(&mut *connection)
+        .await
+        .context("Failed to acquire the migration lock")?;
+    let outcome = migrate_locked(&mut connection).await;
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(&mut *connection)
+        .await
+        .context("Failed to release the migration lock")?;
+    outcome
+}
+
+async fn migrate_locked(connection: &mut PgConnection) -> anyhow::Result<()> {
+    ensure_no_unknown_migrations(connection).await?;
+    // Another replica may have migrated while we were waiting for the lock,
+    // in which case there is nothing left to do.
+    MIGRATOR
+        .run(&mut *connection)
+        .await
+        .context("Failed to migrate the database")?;
+    backfill_canonical_emails(connection).await?;
+    info!("Database schema is up to date");
+    Ok(())
+}
+
+/// Recompute the canonical email of subscriptions with non-ASCII addresses using
+/// [`SubscriberEmail::parse`], as the SQL migration adding `email_canonical` cannot
+/// punycode internationalised domains. The stored address is left as entered. Addresses
+/// that turn out to belong to another subscription are soft-deleted, keeping the
+/// confirmed one, or else the oldest.
+async fn backfill_canonical_emails(connection: &mut PgConnection) -> anyhow::Result<()> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, email, email_canonical, status, subscribed_at FROM subscriptions
+        WHERE deleted_at IS NULL AND octet_length(email_canonical) <> char_length(email_canonical)"#
+    )
+    .fetch_all(&mut *connection)
+    .await
+    .context("Failed to list non-ASCII subscriber emails")?;
+
+    let (mut updated, mut removed) = (0, 0);
+    for row in rows {
+        let email = match SubscriberEmail::parse(row.email.clone()) {
+            Ok(email) => email,
+            Err(e) => {
+                warn!(subscriber_id = %row.id, "Cannot canonicalise a stored email {:?}", e);
+                continue;
+            }
+        };
+        if email.canonical() == row.email_canonical {
+            continue;
+        }
+
+        let duplicate = sqlx::query!(
+            r#"
+            SELECT id, status, subscribed_at FROM subscriptions
+            WHERE email_canonical = $1 AND deleted_at IS NULL AND id <> $2"#,
+            email.canonical(),
+            row.id,
+        )
+        .fetch_optional(&mut *connection)
+        .await
+        .context("Failed to look up duplicate subscriber emails")?;
+        if let Some(duplicate) = duplicate {
+            let rank = |status: &str, subscribed_at| (status != "confirmed", subscribed_at);
+            let loser = if rank(&duplicate.status, duplicate.subscribed_at)
+                <= rank(&row.status, row.subscribed_at)
+            {
+                row.id
+            } else {
+                duplicate.id
+            };
+            sqlx::query!(
+                "UPDATE subscriptions SET deleted_at = now(), updated_at = now() WHERE id = $1",
+                loser
+            )
+            .execute(&mut *connection)
+            .await
+            .context("Failed to remove a duplicate subscription")?;
+            removed += 1;
+            if loser == row.id {
+                continue;
+            }
+        }
+
+        sqlx::query!(
+            "UPDATE subscriptions SET email_canonical = $2, updated_at = now() WHERE id = $1",
+            row.id,
+            email.canonical(),
+        )
+        .execute(&mut *connection)
+        .await
+        .context("Failed to update a subscriber email")?;
+        updated += 1;
+    }
+    if updated > 0 || removed > 0 {
+        info!(
+            updated,
+            removed, "Canonicalised internationalised subscriber emails"
+        );
+    }
+    Ok(())
+}
+
+async fn ensure_no_unknown_migrations(connection: &mut PgConnection) -> anyhow::Result<()> {
+    let known: HashSet<i64> = MIGRATOR.iter().map(|migration| migration.version).collect();
+    let mut unknown: Vec<i64> = applied_versions_on(connection)
+        .await?
+        .into_iter()
+        .filter(|version| !known.contains(version))
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort_unstable();
+        bail!(
+            "The database has migrations this binary does not know about ({:?}). \
+            Refusing to start against a schema from a newer release.",
+            unknown
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{
+    DeliveryCounts, IssueStatus, NewsletterIssue, NewsletterIssueError, NewsletterIssues, Schedule,
+};
+use crate::newsletter_templates::TemplateContent;
+
+/// Keeps newsletter issues in a `Vec`, for tests. Nothing publishes them but
+/// [`InMemoryNewsletterIssues::publish`].
+#[derive(Default)]
+pub struct InMemoryNewsletterIssues {
+    issues: Mutex<Vec<NewsletterIssue>>,
+}
+
+impl InMemoryNewsletterIssues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_issues<T>(&self, f: impl FnOnce(&mut Vec<NewsletterIssue>) -> T) -> T {
+        let mut issues = self
+            .issues
+            .lock()
+            .expect("The newsletter issues lock is poisoned");
+        f(&mut issues)
+    }
+
+    /// Publish a scheduled issue, standing in for the delivery worker. No email is sent.
+    pub fn publish(&self, id: Uuid) -> Result<Option<NewsletterIssue>, NewsletterIssueError> {
+        self.with_issues(|issues| {
+            let Some(issue) = issues.iter_mut().find(|i| i.id == id) else {
+                return Ok(None);
+            };
+            issue.status = issue.status.transition_to(IssueStatus::Published)?;
+            let now = Utc::now();
+            issue.published_at = Some(now);
+            issue.updated_at = now;
+            Ok(Some(issue.clone()))
+        })
+    }
+
+    fn transition(
+        &self,
+        id: Uuid,
+        status: IssueStatus,
+        schedule: Option<Schedule>,
+    ) -> Result<Option<NewsletterIssue>, NewsletterIssueError> {
+        self.with_issues(|issues| {
+            let Some(issue) = issues.iter_mut().find(|i| i.id == id) else {
+                return Ok(None);
+            };
+            issue.status = issue.status.transition_to(status)?;
+            if let Some(schedule) = schedule {
+                issue.scheduled_at = schedule.at;
+                issue.local_time = schedule.local_time;
+            }
+            issue.updated_at = Utc::now();
+            Ok(Some(issue.clone()))
+        })
+    }
+}
+
+#[async_trait]
+impl NewsletterIssues for InMemoryNewsletterIssues {
+    async fn list(&self) -> anyhow::Result<Vec<NewsletterIssue>> {
+        Ok(self.with_issues(|issues| {
+            let mut issues = issues.clone();
+            issues.sort_by_key(|issue| std::cmp::Reverse(issue.scheduled_at));
+            issues
+        }))
+    }
+
+    async fn find(&self, id: Uuid) -> anyhow::Result<Option<NewsletterIssue>> {
+        Ok(self.with_issues(|issues| issues.iter().find(|i| i.id == id).cloned()))
+    }
+
+    async fn insert(
+        &self,
+        template_id: Uuid,
+        content: &TemplateContent,
+        schedule: Schedule,
+    ) -> anyhow::Result<NewsletterIssue> {
+        let now = Utc::now();
+        let issue = NewsletterIssue {
+            id: Uuid::new_v4(),
+            template_id: Some(template_id),
+            content: content.clone(),
+            status: IssueStatus::Scheduled,
+            scheduled_at: schedule.at,
+            local_time: schedule.local_time,
+            published_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+        self.with_issues(|issues| issues.push(issue.clone()));
+        Ok(issue)
+    }
+
+    async fn reschedule(
+        &self,
+        id: Uuid,
+        schedule: Schedule,
+    ) -> Result<Option<NewsletterIssue>, NewsletterIssueError> {
+        self.transition(id, IssueStatus::Scheduled, Some(schedule))
+    }
+
+    async fn cancel(&self, id: Uuid) -> Result<Option<NewsletterIssue>, NewsletterIssueError> {
+        self.transition(id, IssueStatus::Cancelled, None)
+    }
+
+    async fn delivery_counts(&self, _id: Uuid) -> anyhow::Result<DeliveryCounts> {
+        Ok(DeliveryCounts::default())
+    }
+
+    async fn record_bounce(
+        &self,
+        _provider_message_id: &str,
+        _bounced_at: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+}
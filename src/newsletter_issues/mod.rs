@@ -0,0 +1,416 @@
+mod in_memory;
+mod postgres;
+mod worker;
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::newsletter_templates::TemplateContent;
+
+pub use in_memory::InMemoryNewsletterIssues;
+pub use postgres::PgNewsletterIssues;
+pub use worker::{DeliveryWorker, ExecutionOutcome};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueStatus {
+    /// Waiting for `scheduled_at`.
+    Scheduled,
+    /// Queued for delivery to every confirmed subscriber.
+    Published,
+    Cancelled,
+}
+
+impl IssueStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueStatus::Scheduled => "scheduled",
+            IssueStatus::Published => "published",
+            IssueStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Issues can be rescheduled or cancelled until they are published, and
+    /// cancelled issues can be scheduled again.
+    pub fn can_transition_to(self, next: IssueStatus) -> bool {
+        use IssueStatus::*;
+        matches!(
+            (self, next),
+            (Scheduled, Scheduled)
+                | (Scheduled, Published)
+                | (Scheduled, Cancelled)
+                | (Cancelled, Scheduled)
+        )
+    }
+
+    pub fn transition_to(self, next: IssueStatus) -> Result<IssueStatus, InvalidIssueTransition> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(InvalidIssueTransition {
+                from: self,
+                to: next,
+            })
+        }
+    }
+}
+
+impl TryFrom<String> for IssueStatus {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "scheduled" => Ok(IssueStatus::Scheduled),
+            "published" => Ok(IssueStatus::Published),
+            "cancelled" => Ok(IssueStatus::Cancelled),
+            other => Err(anyhow!("{} is not a valid newsletter issue status.", other)),
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("A newsletter issue cannot go from {} to {}.", from.as_str(), to.as_str())]
+pub struct InvalidIssueTransition {
+    pub from: IssueStatus,
+    pub to: IssueStatus,
+}
+
+/// When an issue goes out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    /// When the issue is published.
+    pub at: DateTime<Utc>,
+    /// Send to each subscriber at this time of day in their time zone, the first time
+    /// it comes round from `at`, rather than straight away.
+    pub local_time: Option<NaiveTime>,
+}
+
+impl Schedule {
+    pub fn immediate(at: DateTime<Utc>) -> Self {
+        Self {
+            at,
+            local_time: None,
+        }
+    }
+
+    /// When to send the issue to a subscriber in `timezone`, UTC when they did not give one.
+    pub fn delivery_time(&self, timezone: Option<Tz>) -> DateTime<Utc> {
+        let Some(local_time) = self.local_time else {
+            return self.at;
+        };
+        let timezone = timezone.unwrap_or(Tz::UTC);
+        let mut date = self.at.with_timezone(&timezone).date_naive();
+        loop {
+            if let Some(time) = local_datetime(timezone, date.and_time(local_time)) {
+                if time >= self.at {
+                    return time;
+                }
+            }
+            date = date.succ_opt().expect("The delivery date is out of range");
+        }
+    }
+}
+
+/// The first instant at `local` in `timezone`. Times skipped when the clocks go forward
+/// are moved forward by as much.
+fn local_datetime(timezone: Tz, local: chrono::NaiveDateTime) -> Option<DateTime<Utc>> {
+    (0..=2)
+        .find_map(|hours| {
+            timezone
+                .from_local_datetime(&(local + Duration::hours(hours)))
+                .earliest()
+        })
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// A newsletter as sent to subscribers. The content is copied from a template when the
+/// issue is created, so that later edits to the template do not change it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NewsletterIssue {
+    pub id: Uuid,
+    /// Unless the template has been deleted since.
+    pub template_id: Option<Uuid>,
+    #[serde(flatten)]
+    pub content: TemplateContent,
+    pub status: IssueStatus,
+    pub scheduled_at: DateTime<Utc>,
+    /// See [`Schedule::local_time`].
+    pub local_time: Option<NaiveTime>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What happened to the email sent to one subscriber.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Waiting to be sent, or to be retried.
+    Queued,
+    Sent,
+    /// Given up on after too many retries.
+    Failed,
+    /// Sent, then reported as bounced by the email provider.
+    Bounced,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Queued => "queued",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Bounced => "bounced",
+        }
+    }
+}
+
+impl TryFrom<String> for DeliveryStatus {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "queued" => Ok(DeliveryStatus::Queued),
+            "sent" => Ok(DeliveryStatus::Sent),
+            "failed" => Ok(DeliveryStatus::Failed),
+            "bounced" => Ok(DeliveryStatus::Bounced),
+            other => Err(anyhow!("{} is not a valid delivery status.", other)),
+        }
+    }
+}
+
+/// The number of emails of an issue in each [`DeliveryStatus`].
+///
+/// Subscribers skipped when their email is due, such as those no longer confirmed, are
+/// not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DeliveryCounts {
+    pub queued: i64,
+    pub sent: i64,
+    pub failed: i64,
+    pub bounced: i64,
+}
+
+impl DeliveryCounts {
+    pub fn add(&mut self, status: DeliveryStatus, count: i64) {
+        match status {
+            DeliveryStatus::Queued => self.queued += count,
+            DeliveryStatus::Sent => self.sent += count,
+            DeliveryStatus::Failed => self.failed += count,
+            DeliveryStatus::Bounced => self.bounced += count,
+        }
+    }
+
+    pub fn total(&self) -> i64 {
+        self.queued + self.sent + self.failed + self.bounced
+    }
+}
+
+/// An issue with how far it has gone out, as returned by `GET /admin/newsletters/{id}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IssueProgress {
+    #[serde(flatten)]
+    pub issue: NewsletterIssue,
+    pub deliveries: DeliveryCounts,
+    pub recipients: i64,
+    /// The percentage of emails no longer queued, to one decimal place.
+    pub progress: f64,
+}
+
+impl IssueProgress {
+    pub fn new(issue: NewsletterIssue, deliveries: DeliveryCounts) -> Self {
+        let recipients = deliveries.total();
+        let progress = if recipients > 0 {
+            let done = recipients - deliveries.queued;
+            (done as f64 * 1000.0 / recipients as f64).round() / 10.0
+        } else if issue.status == IssueStatus::Published {
+            // Published to nobody: there is nothing left to send.
+            100.0
+        } else {
+            0.0
+        };
+        Self {
+            issue,
+            deliveries,
+            recipients,
+            progress,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum NewsletterIssueError {
+    #[error(transparent)]
+    InvalidTransition(#[from] InvalidIssueTransition),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+/// Storage for newsletter issues. Publishing due issues is left to [`DeliveryWorker`].
+#[async_trait]
+pub trait NewsletterIssues: Send + Sync {
+    /// Every issue, the latest scheduled first.
+    async fn list(&self) -> anyhow::Result<Vec<NewsletterIssue>>;
+
+    async fn find(&self, id: Uuid) -> anyhow::Result<Option<NewsletterIssue>>;
+
+    /// Schedule a new issue with `content`, copied from the template `template_id`.
+    async fn insert(
+        &self,
+        template_id: Uuid,
+        content: &TemplateContent,
+        schedule: Schedule,
+    ) -> anyhow::Result<NewsletterIssue>;
+
+    /// Returns `None` if there is no issue with this id, and
+    /// [`NewsletterIssueError::InvalidTransition`] if it has been published.
+    async fn reschedule(
+        &self,
+        id: Uuid,
+        schedule: Schedule,
+    ) -> Result<Option<NewsletterIssue>, NewsletterIssueError>;
+
+    /// Returns `None` if there is no issue with this id, and
+    /// [`NewsletterIssueError::InvalidTransition`] unless it is scheduled.
+    async fn cancel(&self, id: Uuid) -> Result<Option<NewsletterIssue>, NewsletterIssueError>;
+
+    /// How many of the issue's emails are in each status. All zero until it is published.
+    async fn delivery_counts(&self, id: Uuid) -> anyhow::Result<DeliveryCounts>;
+
+    /// Mark the sent email with this provider id as bounced. Returns `false` if there is
+    /// none, for instance because the email was not a newsletter.
+    async fn record_bounce(
+        &self,
+        provider_message_id: &str,
+        bounced_at: DateTime<Utc>,
+    ) -> anyhow::Result<bool>;
+}
+
+pub type SharedNewsletterIssues = Arc<dyn NewsletterIssues>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn published_issues_cannot_be_rescheduled_or_cancelled() {
+        use IssueStatus::*;
+        assert_ok!(Scheduled.transition_to(Scheduled));
+        assert_ok!(Cancelled.transition_to(Scheduled));
+        assert_ok!(Scheduled.transition_to(Cancelled));
+        assert_err!(Published.transition_to(Scheduled));
+        assert_err!(Published.transition_to(Cancelled));
+        assert_err!(Cancelled.transition_to(Published));
+    }
+
+    #[test]
+    fn statuses_round_trip_through_strings() {
+        for status in [
+            IssueStatus::Scheduled,
+            IssueStatus::Published,
+            IssueStatus::Cancelled,
+        ] {
+            assert_eq!(
+                IssueStatus::try_from(status.as_str().to_owned()).unwrap(),
+                status
+            );
+        }
+        assert_err!(IssueStatus::try_from("sent".to_owned()));
+    }
+
+    fn at(local_time: &str, timezone: &str) -> DateTime<Utc> {
+        let schedule = Schedule {
+            // Saturday 25 March 2023, at noon in London.
+            at: Utc.with_ymd_and_hms(2023, 3, 25, 12, 0, 0).unwrap(),
+            local_time: Some(local_time.parse().unwrap()),
+        };
+        schedule.delivery_time(Some(timezone.parse().unwrap()))
+    }
+
+    #[test]
+    fn issues_without_a_local_time_go_out_when_published() {
+        let now = Utc::now();
+        assert_eq!(Schedule::immediate(now).delivery_time(None), now);
+        let tokyo = "Asia/Tokyo".parse().unwrap();
+        assert_eq!(Schedule::immediate(now).delivery_time(Some(tokyo)), now);
+    }
+
+    #[test]
+    fn local_times_are_the_next_ones_in_each_time_zone() {
+        let utc = |d, h, m| Utc.with_ymd_and_hms(2023, 3, d, h, m, 0).unwrap();
+        // Already Saturday evening in Tokyo, still Saturday morning in New York.
+        assert_eq!(at("09:00:00", "Asia/Tokyo"), utc(26, 0, 0));
+        assert_eq!(at("09:00:00", "America/New_York"), utc(25, 13, 0));
+        assert_eq!(at("12:00:00", "UTC"), utc(25, 12, 0));
+        assert_eq!(at("11:59:00", "UTC"), utc(26, 11, 59));
+        let schedule = Schedule {
+            at: utc(25, 12, 0),
+            local_time: Some(NaiveTime::from_hms_opt(9, 0, 0).unwrap()),
+        };
+        assert_eq!(schedule.delivery_time(None), utc(26, 9, 0));
+    }
+
+    #[test]
+    fn local_times_skipped_by_daylight_saving_time_are_moved_forward() {
+        // Clocks go from 01:00 to 02:00 on Sunday 26 March 2023 in London.
+        assert_eq!(
+            at("01:30:00", "Europe/London"),
+            Utc.with_ymd_and_hms(2023, 3, 26, 1, 30, 0).unwrap()
+        );
+        assert_eq!(
+            at("09:00:00", "Europe/London"),
+            Utc.with_ymd_and_hms(2023, 3, 26, 8, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn progress_counts_every_email_that_left_the_queue() {
+        let issue = NewsletterIssue {
+            id: Uuid::new_v4(),
+            template_id: None,
+            content: TemplateContent {
+                name: "Weekly".into(),
+                subject: "News".into(),
+                html_body: "<p>News</p>".into(),
+                text_body: "News".into(),
+            },
+            status: IssueStatus::Scheduled,
+            scheduled_at: Utc::now(),
+            local_time: None,
+            published_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let progress = |status, counts| {
+            let issue = NewsletterIssue {
+                status,
+                ..issue.clone()
+            };
+            IssueProgress::new(issue, counts).progress
+        };
+        let counts = DeliveryCounts {
+            queued: 1,
+            sent: 1,
+            failed: 0,
+            bounced: 1,
+        };
+
+        assert_eq!(progress(IssueStatus::Published, counts), 66.7);
+        assert_eq!(
+            progress(IssueStatus::Scheduled, DeliveryCounts::default()),
+            0.0
+        );
+        assert_eq!(
+            progress(IssueStatus::Published, DeliveryCounts::default()),
+            100.0
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{
+    DeliveryCounts, DeliveryStatus, IssueStatus, NewsletterIssue, NewsletterIssueError,
+    NewsletterIssues, Schedule,
+};
+use crate::newsletter_templates::TemplateContent;
+
+pub struct PgNewsletterIssues {
+    db_connection: PgPool,
+}
+
+impl PgNewsletterIssues {
+    pub fn new(db_connection: PgPool) -> Self {
+        Self { db_connection }
+    }
+
+    /// Locks the issue so that it is not published while its status changes.
+    async fn transition(
+        &self,
+        id: Uuid,
+        status: IssueStatus,
+        schedule: Option<Schedule>,
+    ) -> Result<Option<NewsletterIssue>, NewsletterIssueError> {
+        let mut transaction = self
+            .db_connection
+            .begin()
+            .await
+            .context("Failed to start a transaction")?;
+        let current = sqlx::query_scalar!(
+            "SELECT status FROM newsletter_issues WHERE id = $1 FOR UPDATE",
+            id
+        )
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to fetch the newsletter issue status")?;
+        let Some(current) = current else {
+            return Ok(None);
+        };
+        let status = IssueStatus::try_from(current)?.transition_to(status)?;
+        let row = sqlx::query_as!(
+            NewsletterIssueRow,
+            r#"
+            UPDATE newsletter_issues
+            SET status = $2,
+                scheduled_at = COALESCE($3, scheduled_at),
+                local_time = CASE WHEN $3::timestamptz IS NULL THEN local_time ELSE $4 END,
+                updated_at = $5
+            WHERE id = $1
+            RETURNING id, template_id, name, subject, html_body, text_body, status,
+                scheduled_at, local_time, published_at, created_at, updated_at"#,
+            id,
+            status.as_str(),
+            schedule.map(|schedule| schedule.at),
+            schedule.and_then(|schedule| schedule.local_time),
+            Utc::now()
+        )
+        .fetch_one(&mut transaction)
+        .await
+        .context("Failed to update the newsletter issue")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the newsletter issue update")?;
+        Ok(Some(row.try_into()?))
+    }
+}
+
+struct NewsletterIssueRow {
+    id: Uuid,
+    template_id: Option<Uuid>,
+    name: String,
+    subject: String,
+    html_body: String,
+    text_body: String,
+    status: String,
+    scheduled_at: DateTime<Utc>,
+    local_time: Option<NaiveTime>,
+    published_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<NewsletterIssueRow> for NewsletterIssue {
+    type Error = anyhow::Error;
+
+    fn try_from(row: NewsletterIssueRow) -> Result<Self, Self::Error> {
+        Ok(NewsletterIssue {
+            id: row.id,
+            template_id: row.template_id,
+            content: TemplateContent {
+                name: row.name,
+                subject: row.subject,
+                html_body: row.html_body,
+                text_body: row.text_body,
+            },
+            status: row.status.try_into()?,
+            scheduled_at: row.scheduled_at,
+            local_time: row.local_time,
+            published_at: row.published_at,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
+#[async_trait]
+impl NewsletterIssues for PgNewsletterIssues {
+    async fn list(&self) -> anyhow::Result<Vec<NewsletterIssue>> {
+        let rows = sqlx::query_as!(
+            NewsletterIssueRow,
+            r#"
+            SELECT id, template_id, name, subject, html_body, text_body, status,
+                scheduled_at, local_time, published_at, created_at, updated_at
+            FROM newsletter_issues ORDER BY scheduled_at DESC"#
+        )
+        .fetch_all(&self.db_connection)
+        .await
+        .context("Failed to list newsletter issues")?;
+        rows.into_iter().map(NewsletterIssue::try_from).collect()
+    }
+
+    async fn find(&self, id: Uuid) -> anyhow::Result<Option<NewsletterIssue>> {
+        let row = sqlx::query_as!(
+            NewsletterIssueRow,
+            r#"
+            SELECT id, template_id, name, subject, html_body, text_body, status,
+                scheduled_at, local_time, published_at, created_at, updated_at
+            FROM newsletter_issues WHERE id = $1"#,
+            id
+        )
+        .fetch_optional(&self.db_connection)
+        .await
+        .context("Failed to fetch newsletter issue")?;
+        row.map(NewsletterIssue::try_from).transpose()
+    }
+
+    #[tracing::instrument(name = "Insert newsletter issue", skip(self, content))]
+    async fn insert(
+        &self,
+        template_id: Uuid,
+        content: &TemplateContent,
+        schedule: Schedule,
+    ) -> anyhow::Result<NewsletterIssue> {
+        let row = sqlx::query_as!(
+            NewsletterIssueRow,
+            r#"
+            INSERT INTO newsletter_issues
+                (id, template_id, name, subject, html_body, text_body, status,
+                 scheduled_at, local_time, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $10)
+            RETURNING id, template_id, name, subject, html_body, text_body, status,
+                scheduled_at, local_time, published_at, created_at, updated_at"#,
+            Uuid::new_v4(),
+            template_id,
+            content.name,
+            content.subject,
+            content.html_body,
+            content.text_body,
+            IssueStatus::Scheduled.as_str(),
+            schedule.at,
+            schedule.local_time,
+            Utc::now()
+        )
+        .fetch_one(&self.db_connection)
+        .await
+        .context("Failed to insert newsletter issue")?;
+        row.try_into()
+    }
+
+    #[tracing::instrument(name = "Reschedule newsletter issue", skip(self))]
+    async fn reschedule(
+        &self,
+        id: Uuid,
+        schedule: Schedule,
+    ) -> Result<Option<NewsletterIssue>, NewsletterIssueError> {
+        self.transition(id, IssueStatus::Scheduled, Some(schedule))
+            .await
+    }
+
+    #[tracing::instrument(name = "Cancel newsletter issue", skip(self))]
+    async fn cancel(&self, id: Uuid) -> Result<Option<NewsletterIssue>, NewsletterIssueError> {
+        self.transition(id, IssueStatus::Cancelled, None).await
+    }
+
+    async fn delivery_counts(&self, id: Uuid) -> anyhow::Result<DeliveryCounts> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT status, COUNT(*) AS "count!" FROM deliveries
+            WHERE newsletter_issue_id = $1 GROUP BY status"#,
+            id
+        )
+        .fetch_all(&self.db_connection)
+        .await
+        .context("Failed to count the newsletter issue deliveries")?;
+        let mut counts = DeliveryCounts::default();
+        for row in rows {
+            counts.add(DeliveryStatus::try_from(row.status)?, row.count);
+        }
+        Ok(counts)
+    }
+
+    #[tracing::instrument(name = "Record a bounced newsletter delivery", skip(self))]
+    async fn record_bounce(
+        &self,
+        provider_message_id: &str,
+        bounced_at: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        let bounced = sqlx::query!(
+            r#"
+            UPDATE deliveries SET status = $2, bounced_at = $3, updated_at = $3
+            WHERE provider_message_id = $1 AND status = $4"#,
+            provider_message_id,
+            DeliveryStatus::Bounced.as_str(),
+            bounced_at,
+            DeliveryStatus::Sent.as_str()
+        )
+        .execute(&self.db_connection)
+        .await
+        .context("Failed to record the bounce")?;
+        Ok(bounced.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,455 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use super::{DeliveryStatus, IssueStatus, Schedule};
+use crate::audit::{self, Actor, AuditAction, AuditTarget, NewAuditEvent, SharedAuditLog};
+use crate::domain::{SubscriberEmail, SubscriberTimezone, SubscriptionStatus};
+use crate::email_client::{EmailClient, EmailMessage, Mailbox};
+use crate::newsletter_templates::{self, Personalisation, TemplateContent};
+use crate::privacy::generate_token;
+use crate::routes::{issue_view_link, unsubscribe_link};
+
+/// How often to look for issues that are due.
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(10);
+/// How long to wait before looking again when there is nothing to deliver.
+const EMPTY_QUEUE_DELAY: Duration = Duration::from_secs(10);
+const ERROR_DELAY: Duration = Duration::from_secs(1);
+/// Failed deliveries are retried after 1, 2, 4 and 8 minutes, then dropped.
+const MAX_RETRIES: i32 = 5;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Publishes issues once they are due, then sends them to each subscriber.
+///
+/// Everything is kept in Postgres, so a restart picks up where it left off, and rows
+/// are claimed with `FOR UPDATE SKIP LOCKED`, so that every replica can run a worker
+/// while each issue is still published once and each email sent once.
+pub struct DeliveryWorker {
+    db_pool: PgPool,
+    email_client: Arc<EmailClient>,
+    base_url: String,
+    audit_log: SharedAuditLog,
+}
+
+impl DeliveryWorker {
+    pub fn new(
+        db_pool: PgPool,
+        email_client: Arc<EmailClient>,
+        base_url: String,
+        audit_log: SharedAuditLog,
+    ) -> Self {
+        Self {
+            db_pool,
+            email_client,
+            base_url,
+            audit_log,
+        }
+    }
+
+    pub async fn run_until_stopped(self) {
+        tokio::join!(self.schedule_loop(), self.delivery_loop());
+    }
+
+    async fn schedule_loop(&self) {
+        let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.publish_due_issues(Utc::now()).await {
+                error!("Failed to publish due newsletter issues: {:?}", e);
+            }
+        }
+    }
+
+    async fn delivery_loop(&self) {
+        loop {
+            match self.try_execute_task(Utc::now()).await {
+                Ok(ExecutionOutcome::TaskCompleted) => {}
+                Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(EMPTY_QUEUE_DELAY).await,
+                Err(e) => {
+                    error!("Failed to deliver a newsletter issue: {:?}", e);
+                    tokio::time::sleep(ERROR_DELAY).await;
+                }
+            }
+        }
+    }
+
+    /// Publish every issue scheduled at or before `now`. Returns their ids.
+    pub async fn publish_due_issues(&self, now: DateTime<Utc>) -> anyhow::Result<Vec<Uuid>> {
+        let mut published = vec![];
+        while let Some(id) = self.publish_next_due_issue(now).await? {
+            published.push(id);
+        }
+        Ok(published)
+    }
+
+    /// Queue an email to every confirmed subscriber and mark the issue as published,
+    /// in the transaction holding the lock on the issue.
+    #[tracing::instrument(name = "Publish a due newsletter issue", skip(self))]
+    async fn publish_next_due_issue(&self, now: DateTime<Utc>) -> anyhow::Result<Option<Uuid>> {
+        let mut transaction = self
+            .db_pool
+            .begin()
+            .await
+            .context("Failed to start a transaction")?;
+        let issue = sqlx::query!(
+            r#"
+            SELECT id, scheduled_at, local_time FROM newsletter_issues
+            WHERE status = $1 AND scheduled_at <= $2
+            ORDER BY scheduled_at
+            FOR UPDATE SKIP LOCKED
+            LIMIT 1"#,
+            IssueStatus::Scheduled.as_str(),
+            now
+        )
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to look for due newsletter issues")?;
+        let Some(issue) = issue else {
+            return Ok(None);
+        };
+        let id = issue.id;
+        let recipients = match issue.local_time {
+            None => queue_deliveries(&mut transaction, id, now).await?,
+            Some(local_time) => {
+                let schedule = Schedule {
+                    at: issue.scheduled_at,
+                    local_time: Some(local_time),
+                };
+                queue_local_deliveries(&mut transaction, id, schedule).await?
+            }
+        };
+        sqlx::query!(
+            r#"
+            INSERT INTO deliveries
+                (newsletter_issue_id, subscriber_id, status, queued_at, updated_at)
+            SELECT newsletter_issue_id, subscriber_id, $2, $3, $3 FROM issue_delivery_queue
+            WHERE newsletter_issue_id = $1"#,
+            id,
+            DeliveryStatus::Queued.as_str(),
+            now
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to record the newsletter deliveries")?;
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_issues SET status = $2, published_at = $3, updated_at = $3
+            WHERE id = $1"#,
+            id,
+            IssueStatus::Published.as_str(),
+            now
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to mark the newsletter issue as published")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the newsletter issue publication")?;
+        info!(issue_id = %id, recipients, "Newsletter issue has been published");
+        audit::record(
+            self.audit_log.as_ref(),
+            NewAuditEvent::new(
+                Actor::System,
+                AuditAction::NewsletterPublished,
+                AuditTarget::newsletter(id),
+            )
+            .details(json!({ "recipients": recipients })),
+        )
+        .await;
+        Ok(Some(id))
+    }
+
+    /// Send one queued email that is due at `now`, if any.
+    pub async fn try_execute_task(&self, now: DateTime<Utc>) -> anyhow::Result<ExecutionOutcome> {
+        let mut transaction = self
+            .db_pool
+            .begin()
+            .await
+            .context("Failed to start a transaction")?;
+        let task = sqlx::query!(
+            r#"
+            SELECT newsletter_issue_id, subscriber_id, n_retries FROM issue_delivery_queue
+            WHERE execute_after <= $1
+            ORDER BY execute_after
+            FOR UPDATE SKIP LOCKED
+            LIMIT 1"#,
+            now
+        )
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to dequeue a newsletter delivery")?;
+        let Some(task) = task else {
+            return Ok(ExecutionOutcome::EmptyQueue);
+        };
+        let (issue_id, subscriber_id) = (task.newsletter_issue_id, task.subscriber_id);
+
+        match self
+            .deliver(&mut transaction, issue_id, subscriber_id)
+            .await
+        {
+            Ok(Some(message_id)) => {
+                record_delivery(
+                    &mut transaction,
+                    issue_id,
+                    subscriber_id,
+                    DeliveryStatus::Sent,
+                    Some(&message_id),
+                    now,
+                )
+                .await?;
+            }
+            Ok(None) => {
+                sqlx::query!(
+                    r#"
+                    DELETE FROM deliveries
+                    WHERE newsletter_issue_id = $1 AND subscriber_id = $2"#,
+                    issue_id,
+                    subscriber_id
+                )
+                .execute(&mut transaction)
+                .await
+                .context("Failed to remove the skipped newsletter delivery")?;
+            }
+            Err(e) if task.n_retries + 1 >= MAX_RETRIES => {
+                error!(%issue_id, %subscriber_id, "Giving up on a newsletter delivery: {:?}", e);
+                record_delivery(
+                    &mut transaction,
+                    issue_id,
+                    subscriber_id,
+                    DeliveryStatus::Failed,
+                    None,
+                    now,
+                )
+                .await?;
+            }
+            Err(e) => {
+                warn!(%issue_id, %subscriber_id, "Failed to deliver a newsletter issue, will retry: {:?}", e);
+                let execute_after = now + chrono::Duration::minutes(1 << task.n_retries);
+                sqlx::query!(
+                    r#"
+                    UPDATE issue_delivery_queue SET n_retries = n_retries + 1, execute_after = $3
+                    WHERE newsletter_issue_id = $1 AND subscriber_id = $2"#,
+                    issue_id,
+                    subscriber_id,
+                    execute_after
+                )
+                .execute(&mut transaction)
+                .await
+                .context("Failed to postpone the newsletter delivery")?;
+                transaction
+                    .commit()
+                    .await
+                    .context("Failed to commit the newsletter delivery")?;
+                return Ok(ExecutionOutcome::TaskCompleted);
+            }
+        }
+        sqlx::query!(
+            r#"
+            DELETE FROM issue_delivery_queue
+            WHERE newsletter_issue_id = $1 AND subscriber_id = $2"#,
+            issue_id,
+            subscriber_id
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to remove the newsletter delivery from the queue")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the newsletter delivery")?;
+        Ok(ExecutionOutcome::TaskCompleted)
+    }
+
+    /// Returns the email provider's id for the email, or `None` for subscribers who were
+    /// skipped because they are no longer confirmed by the time their email is due.
+    #[tracing::instrument(name = "Deliver a newsletter issue", skip(self, transaction))]
+    async fn deliver(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        issue_id: Uuid,
+        subscriber_id: Uuid,
+    ) -> anyhow::Result<Option<String>> {
+        let subscriber = sqlx::query!(
+            r#"
+            SELECT email, name FROM subscriptions
+            WHERE id = $1 AND status = $2 AND deleted_at IS NULL"#,
+            subscriber_id,
+            SubscriptionStatus::Confirmed.as_str()
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to fetch the subscriber")?;
+        let Some(subscriber) = subscriber else {
+            info!("Skipping a subscriber who is no longer confirmed");
+            return Ok(None);
+        };
+        let email = match SubscriberEmail::parse(subscriber.email.clone()) {
+            Ok(email) => email,
+            Err(e) => {
+                warn!(
+                    "Skipping a subscriber with an invalid stored email: {:?}",
+                    e
+                );
+                return Ok(None);
+            }
+        };
+        let content = sqlx::query_as!(
+            TemplateContent,
+            "SELECT name, subject, html_body, text_body FROM newsletter_issues WHERE id = $1",
+            issue_id
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .context("Failed to fetch the newsletter issue")?;
+        let token = subscription_token(transaction, subscriber_id).await?;
+
+        let personalisation = Personalisation {
+            name: subscriber.name.clone(),
+            email: subscriber.email,
+            unsubscribe_link: unsubscribe_link(&self.base_url, &token),
+            view_in_browser_link: issue_view_link(&self.base_url, issue_id, &token),
+        };
+        let rendered = newsletter_templates::render(&content, &personalisation)?;
+        let message_id = self
+            .email_client
+            .send(EmailMessage::new(
+                Mailbox::named(subscriber.name, email),
+                rendered.subject,
+                rendered.html,
+                rendered.text,
+            ))
+            .await?;
+        Ok(Some(message_id))
+    }
+}
+
+/// Queue an email to every confirmed subscriber, due straight away.
+async fn queue_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    now: DateTime<Utc>,
+) -> anyhow::Result<u64> {
+    let queued = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_id, execute_after)
+        SELECT $1, id, $2 FROM subscriptions
+        WHERE status = $3 AND deleted_at IS NULL"#,
+        issue_id,
+        now,
+        SubscriptionStatus::Confirmed.as_str()
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to queue the newsletter issue for delivery")?;
+    Ok(queued.rows_affected())
+}
+
+/// Queue an email to every confirmed subscriber, due at the issue's local time in their
+/// time zone. Subscribers without a valid time zone get it at that time in UTC.
+async fn queue_local_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    schedule: Schedule,
+) -> anyhow::Result<u64> {
+    let subscribers = sqlx::query!(
+        "SELECT id, timezone FROM subscriptions WHERE status = $1 AND deleted_at IS NULL",
+        SubscriptionStatus::Confirmed.as_str()
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to fetch the subscribers' time zones")?;
+    let (ids, execute_after): (Vec<Uuid>, Vec<DateTime<Utc>>) = subscribers
+        .into_iter()
+        .map(|subscriber| {
+            let timezone = subscriber
+                .timezone
+                .and_then(|timezone| SubscriberTimezone::parse(timezone).ok());
+            let due = schedule.delivery_time(timezone.map(|timezone| timezone.tz()));
+            (subscriber.id, due)
+        })
+        .unzip();
+    let queued = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_id, execute_after)
+        SELECT $1, * FROM UNNEST($2::uuid[], $3::timestamptz[])"#,
+        issue_id,
+        &ids,
+        &execute_after
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to queue the newsletter issue for delivery")?;
+    Ok(queued.rows_affected())
+}
+
+async fn record_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    status: DeliveryStatus,
+    provider_message_id: Option<&str>,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let sent_at = (status == DeliveryStatus::Sent).then_some(now);
+    sqlx::query!(
+        r#"
+        UPDATE deliveries
+        SET status = $3, provider_message_id = $4, sent_at = $5, updated_at = $6
+        WHERE newsletter_issue_id = $1 AND subscriber_id = $2"#,
+        issue_id,
+        subscriber_id,
+        status.as_str(),
+        provider_message_id,
+        sent_at,
+        now
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to record the newsletter delivery")?;
+    Ok(())
+}
+
+/// The latest token sent to the subscriber, or a new one for subscribers who never
+/// received any, such as imported ones.
+async fn subscription_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> anyhow::Result<String> {
+    let token = sqlx::query_scalar!(
+        r#"
+        SELECT subscription_token FROM subscription_tokens
+        WHERE subscriber_id = $1 ORDER BY created_at DESC LIMIT 1"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to fetch the subscription token")?;
+    if let Some(token) = token {
+        return Ok(token);
+    }
+    let token = generate_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, created_at)
+        VALUES ($1, $2, $3)"#,
+        token,
+        subscriber_id,
+        Utc::now()
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store a subscription token")?;
+    Ok(token)
+}
@@ -0,0 +1,90 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::{NewsletterTemplate, NewsletterTemplateError, NewsletterTemplates, TemplateContent};
+
+/// Keeps newsletter templates in a `Vec`, for tests.
+#[derive(Default)]
+pub struct InMemoryNewsletterTemplates {
+    templates: Mutex<Vec<NewsletterTemplate>>,
+}
+
+impl InMemoryNewsletterTemplates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_templates<T>(&self, f: impl FnOnce(&mut Vec<NewsletterTemplate>) -> T) -> T {
+        let mut templates = self
+            .templates
+            .lock()
+            .expect("The newsletter templates lock is poisoned");
+        f(&mut templates)
+    }
+}
+
+#[async_trait]
+impl NewsletterTemplates for InMemoryNewsletterTemplates {
+    async fn list(&self) -> anyhow::Result<Vec<NewsletterTemplate>> {
+        Ok(self.with_templates(|templates| {
+            let mut templates = templates.clone();
+            templates.sort_by(|a, b| a.content.name.cmp(&b.content.name));
+            templates
+        }))
+    }
+
+    async fn find(&self, id: Uuid) -> anyhow::Result<Option<NewsletterTemplate>> {
+        Ok(self.with_templates(|templates| templates.iter().find(|t| t.id == id).cloned()))
+    }
+
+    async fn insert(
+        &self,
+        content: &TemplateContent,
+    ) -> Result<NewsletterTemplate, NewsletterTemplateError> {
+        self.with_templates(|templates| {
+            if templates.iter().any(|t| t.content.name == content.name) {
+                return Err(NewsletterTemplateError::DuplicateName);
+            }
+            let now = Utc::now();
+            let template = NewsletterTemplate {
+                id: Uuid::new_v4(),
+                content: content.clone(),
+                created_at: now,
+                updated_at: now,
+            };
+            templates.push(template.clone());
+            Ok(template)
+        })
+    }
+
+    async fn update(
+        &self,
+        id: Uuid,
+        content: &TemplateContent,
+    ) -> Result<Option<NewsletterTemplate>, NewsletterTemplateError> {
+        self.with_templates(|templates| {
+            if templates
+                .iter()
+                .any(|t| t.id != id && t.content.name == content.name)
+            {
+                return Err(NewsletterTemplateError::DuplicateName);
+            }
+            Ok(templates.iter_mut().find(|t| t.id == id).map(|template| {
+                template.content = content.clone();
+                template.updated_at = Utc::now();
+                template.clone()
+            }))
+        })
+    }
+
+    async fn delete(&self, id: Uuid) -> anyhow::Result<bool> {
+        Ok(self.with_templates(|templates| {
+            let before = templates.len();
+            templates.retain(|t| t.id != id);
+            templates.len() < before
+        }))
+    }
+}
@@ -0,0 +1,87 @@
+mod in_memory;
+mod postgres;
+mod render;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+pub use in_memory::InMemoryNewsletterTemplates;
+pub use postgres::PgNewsletterTemplates;
+pub use render::{render, Personalisation, RenderedNewsletter, TemplatePart};
+
+/// What an admin writes: a subject, an HTML body and a plain-text body, each a template.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateContent {
+    pub name: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl TemplateContent {
+    /// Renders every part for [`Personalisation::sample`], so that mistakes are caught
+    /// when the template is saved rather than when an issue goes out.
+    pub fn validate(&self) -> Result<(), TemplateError> {
+        if self.name.trim().is_empty() {
+            return Err(TemplateError::EmptyName);
+        }
+        let sample = Personalisation::sample("https://example.com", Uuid::nil());
+        render(self, &sample).map(|_| ())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TemplateError {
+    #[error("A template name cannot be empty.")]
+    EmptyName,
+    #[error("The {part} does not render: {message}")]
+    Invalid { part: TemplatePart, message: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NewsletterTemplate {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub content: TemplateContent,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Error)]
+pub enum NewsletterTemplateError {
+    #[error("A newsletter template with this name already exists.")]
+    DuplicateName,
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+/// Storage for newsletter templates. Content is validated by the caller.
+#[async_trait]
+pub trait NewsletterTemplates: Send + Sync {
+    /// Every template, by name.
+    async fn list(&self) -> anyhow::Result<Vec<NewsletterTemplate>>;
+
+    async fn find(&self, id: Uuid) -> anyhow::Result<Option<NewsletterTemplate>>;
+
+    async fn insert(
+        &self,
+        content: &TemplateContent,
+    ) -> Result<NewsletterTemplate, NewsletterTemplateError>;
+
+    /// Returns `None` if there is no template with this id.
+    async fn update(
+        &self,
+        id: Uuid,
+        content: &TemplateContent,
+    ) -> Result<Option<NewsletterTemplate>, NewsletterTemplateError>;
+
+    /// Returns `false` if there is no template with this id.
+    async fn delete(&self, id: Uuid) -> anyhow::Result<bool>;
+}
+
+pub type SharedNewsletterTemplates = Arc<dyn NewsletterTemplates>;
@@ -0,0 +1,142 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{NewsletterTemplate, NewsletterTemplateError, NewsletterTemplates, TemplateContent};
+
+pub struct PgNewsletterTemplates {
+    db_connection: PgPool,
+}
+
+impl PgNewsletterTemplates {
+    pub fn new(db_connection: PgPool) -> Self {
+        Self { db_connection }
+    }
+}
+
+struct NewsletterTemplateRow {
+    id: Uuid,
+    name: String,
+    subject: String,
+    html_body: String,
+    text_body: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<NewsletterTemplateRow> for NewsletterTemplate {
+    fn from(row: NewsletterTemplateRow) -> Self {
+        NewsletterTemplate {
+            id: row.id,
+            content: TemplateContent {
+                name: row.name,
+                subject: row.subject,
+                html_body: row.html_body,
+                text_body: row.text_body,
+            },
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+fn to_template_error(e: sqlx::Error, context: &'static str) -> NewsletterTemplateError {
+    if let sqlx::Error::Database(db_error) = &e {
+        if db_error.constraint() == Some("newsletter_templates_name_key") {
+            return NewsletterTemplateError::DuplicateName;
+        }
+    }
+    NewsletterTemplateError::Unexpected(anyhow::Error::new(e).context(context))
+}
+
+#[async_trait]
+impl NewsletterTemplates for PgNewsletterTemplates {
+    async fn list(&self) -> anyhow::Result<Vec<NewsletterTemplate>> {
+        let rows = sqlx::query_as!(
+            NewsletterTemplateRow,
+            r#"
+            SELECT id, name, subject, html_body, text_body, created_at, updated_at
+            FROM newsletter_templates ORDER BY name"#
+        )
+        .fetch_all(&self.db_connection)
+        .await
+        .context("Failed to list newsletter templates")?;
+        Ok(rows.into_iter().map(NewsletterTemplate::from).collect())
+    }
+
+    async fn find(&self, id: Uuid) -> anyhow::Result<Option<NewsletterTemplate>> {
+        let row = sqlx::query_as!(
+            NewsletterTemplateRow,
+            r#"
+            SELECT id, name, subject, html_body, text_body, created_at, updated_at
+            FROM newsletter_templates WHERE id = $1"#,
+            id
+        )
+        .fetch_optional(&self.db_connection)
+        .await
+        .context("Failed to fetch newsletter template")?;
+        Ok(row.map(NewsletterTemplate::from))
+    }
+
+    #[tracing::instrument(name = "Insert newsletter template", skip(self, content), fields(name = %content.name))]
+    async fn insert(
+        &self,
+        content: &TemplateContent,
+    ) -> Result<NewsletterTemplate, NewsletterTemplateError> {
+        let row = sqlx::query_as!(
+            NewsletterTemplateRow,
+            r#"
+            INSERT INTO newsletter_templates
+                (id, name, subject, html_body, text_body, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $6)
+            RETURNING id, name, subject, html_body, text_body, created_at, updated_at"#,
+            Uuid::new_v4(),
+            content.name,
+            content.subject,
+            content.html_body,
+            content.text_body,
+            Utc::now()
+        )
+        .fetch_one(&self.db_connection)
+        .await
+        .map_err(|e| to_template_error(e, "Failed to insert newsletter template"))?;
+        Ok(row.into())
+    }
+
+    #[tracing::instrument(name = "Update newsletter template", skip(self, content))]
+    async fn update(
+        &self,
+        id: Uuid,
+        content: &TemplateContent,
+    ) -> Result<Option<NewsletterTemplate>, NewsletterTemplateError> {
+        let row = sqlx::query_as!(
+            NewsletterTemplateRow,
+            r#"
+            UPDATE newsletter_templates
+            SET name = $2, subject = $3, html_body = $4, text_body = $5, updated_at = $6
+            WHERE id = $1
+            RETURNING id, name, subject, html_body, text_body, created_at, updated_at"#,
+            id,
+            content.name,
+            content.subject,
+            content.html_body,
+            content.text_body,
+            Utc::now()
+        )
+        .fetch_optional(&self.db_connection)
+        .await
+        .map_err(|e| to_template_error(e, "Failed to update newsletter template"))?;
+        Ok(row.map(NewsletterTemplate::from))
+    }
+
+    #[tracing::instrument(name = "Delete newsletter template", skip(self))]
+    async fn delete(&self, id: Uuid) -> anyhow::Result<bool> {
+        let deleted = sqlx::query!("DELETE FROM newsletter_templates WHERE id = $1", id)
+            .execute(&self.db_connection)
+            .await
+            .context("Failed to delete newsletter template")?;
+        Ok(deleted.rows_affected() > 0)
+    }
+}
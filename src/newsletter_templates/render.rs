@@ -0,0 +1,200 @@
+use std::fmt;
+
+use minijinja::{AutoEscape, Environment, HtmlEscape, UndefinedBehavior};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::{TemplateContent, TemplateError};
+use crate::routes::unsubscribe_link;
+
+/// Stands in for a subscription token in previews, so that no real link is handed out.
+const SAMPLE_TOKEN: &str = "preview";
+
+/// The parts of a template, rendered separately. Only the HTML body is escaped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplatePart {
+    Subject,
+    HtmlBody,
+    TextBody,
+}
+
+impl TemplatePart {
+    /// The name the part is rendered under, which decides how it is escaped.
+    fn template_name(&self) -> &'static str {
+        match self {
+            TemplatePart::Subject => "subject",
+            TemplatePart::HtmlBody => "html_body",
+            TemplatePart::TextBody => "text_body",
+        }
+    }
+}
+
+impl fmt::Display for TemplatePart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplatePart::Subject => write!(f, "subject"),
+            TemplatePart::HtmlBody => write!(f, "HTML body"),
+            TemplatePart::TextBody => write!(f, "text body"),
+        }
+    }
+}
+
+/// The variables available to templates, for one subscriber.
+#[derive(Debug, Clone, Serialize)]
+pub struct Personalisation {
+    pub name: String,
+    pub email: String,
+    pub unsubscribe_link: String,
+    pub view_in_browser_link: String,
+}
+
+impl Personalisation {
+    /// A made-up subscriber, for previews and for validating templates.
+    pub fn sample(base_url: &str, template_id: Uuid) -> Self {
+        Self {
+            name: "Ursula".to_owned(),
+            email: "ursula@example.com".to_owned(),
+            unsubscribe_link: unsubscribe_link(base_url, SAMPLE_TOKEN),
+            // Only issues can be viewed in the browser, so previews link back to themselves.
+            view_in_browser_link: format!(
+                "{}/admin/newsletter-templates/{}/preview",
+                base_url, template_id
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RenderedNewsletter {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Templates are written by admins, but variables hold whatever subscribers typed in,
+/// so every value printed in the HTML body is escaped: the `safe` filter is gone, and
+/// `{% autoescape false %}` does not switch escaping off. Undefined variables are
+/// errors rather than blanks.
+static ENVIRONMENT: Lazy<Environment<'static>> = Lazy::new(|| {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.remove_filter("safe");
+    env.set_auto_escape_callback(|name| {
+        if name == TemplatePart::HtmlBody.template_name() {
+            AutoEscape::Html
+        } else {
+            AutoEscape::None
+        }
+    });
+    env.set_formatter(|out, state, value| {
+        if state.name() == TemplatePart::HtmlBody.template_name() && !value.is_safe() {
+            write!(out, "{}", HtmlEscape(&value.to_string()))?;
+            Ok(())
+        } else {
+            minijinja::escape_formatter(out, state, value)
+        }
+    });
+    env
+});
+
+fn render_part(
+    part: TemplatePart,
+    source: &str,
+    personalisation: &Personalisation,
+) -> Result<String, TemplateError> {
+    ENVIRONMENT
+        .render_named_str(part.template_name(), source, personalisation)
+        .map_err(|e| TemplateError::Invalid {
+            part,
+            message: e.to_string(),
+        })
+}
+
+/// Renders every part of `content` for one subscriber.
+pub fn render(
+    content: &TemplateContent,
+    personalisation: &Personalisation,
+) -> Result<RenderedNewsletter, TemplateError> {
+    let subject = render_part(TemplatePart::Subject, &content.subject, personalisation)?;
+    Ok(RenderedNewsletter {
+        // A line break would end the header.
+        subject: subject.split_whitespace().collect::<Vec<_>>().join(" "),
+        html: render_part(TemplatePart::HtmlBody, &content.html_body, personalisation)?,
+        text: render_part(TemplatePart::TextBody, &content.text_body, personalisation)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_ok};
+
+    fn content(html_body: &str) -> TemplateContent {
+        TemplateContent {
+            name: "Weekly".into(),
+            subject: "Hello {{ name }}".into(),
+            html_body: html_body.into(),
+            text_body: "Hi {{ name }}, unsubscribe at {{ unsubscribe_link }}".into(),
+        }
+    }
+
+    fn personalisation(name: &str) -> Personalisation {
+        Personalisation {
+            name: name.into(),
+            ..Personalisation::sample("https://example.com", Uuid::nil())
+        }
+    }
+
+    #[test]
+    fn names_are_escaped_in_html_only() {
+        let name = r#"<script>alert("hi")</script> & co"#;
+        let rendered = render(&content("<p>{{ name }}</p>"), &personalisation(name)).unwrap();
+
+        assert!(!rendered.html.contains("<script>"));
+        assert!(rendered.html.contains("&lt;script&gt;"));
+        assert!(rendered.html.contains("&amp; co"));
+        assert!(rendered.text.contains(name));
+        assert_eq!(rendered.subject, format!("Hello {}", name));
+    }
+
+    #[test]
+    fn escaping_cannot_be_switched_off() {
+        let personalisation = personalisation("<b>Ursula</b>");
+        let rendered = render(
+            &content("{% autoescape false %}{{ name }}{% endautoescape %}"),
+            &personalisation,
+        )
+        .unwrap();
+        assert_eq!(rendered.html, "&lt;b&gt;Ursula&lt;&#x2f;b&gt;");
+
+        let escaped_once = render(&content("{{ name|escape }}"), &personalisation).unwrap();
+        assert_eq!(escaped_once.html, rendered.html);
+
+        assert_err!(render(&content("{{ name|safe }}"), &personalisation));
+    }
+
+    #[test]
+    fn undefined_variables_are_errors() {
+        let error = content("Hi {{ nmae }}").validate().unwrap_err();
+        assert!(matches!(
+            error,
+            TemplateError::Invalid {
+                part: TemplatePart::HtmlBody,
+                ..
+            }
+        ));
+        assert_err!(content("{% if %}").validate());
+        assert_ok!(content("<a href=\"{{ view_in_browser_link }}\">View</a>").validate());
+    }
+
+    #[test]
+    fn line_breaks_are_removed_from_the_subject() {
+        let mut content = content("");
+        content.subject = "Hello\r\nBcc: {{ email }}".into();
+
+        let rendered = render(&content, &personalisation("Ursula")).unwrap();
+
+        assert_eq!(rendered.subject, "Hello Bcc: ursula@example.com");
+    }
+}
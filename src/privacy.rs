@@ -0,0 +1,286 @@
+//! Data-subject requests: exporting everything held about a subscriber, and erasing it.
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::{Attachment, EmailClient, EmailMessage};
+use crate::newsletter_issues::DeliveryStatus;
+use crate::repository::Subscriber;
+
+/// How long an erasure confirmation link stays valid, in hours.
+pub const ERASURE_TOKEN_TTL_HOURS: i64 = 24;
+
+/// Everything stored about one subscriber.
+#[derive(Debug, Serialize)]
+pub struct SubscriberData {
+    pub subscriber: Subscriber,
+    pub tokens: Vec<TokenRecord>,
+    pub deliveries: Vec<DeliveryRecord>,
+    pub exported_at: DateTime<Utc>,
+}
+
+/// A token issued to the subscriber. The token value itself is left out.
+#[derive(Debug, Serialize)]
+pub struct TokenRecord {
+    pub kind: &'static str,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A newsletter issue emailed, or about to be emailed, to the subscriber.
+#[derive(Debug, Serialize)]
+pub struct DeliveryRecord {
+    pub newsletter_issue_id: Uuid,
+    pub subject: String,
+    pub status: String,
+    pub queued_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub bounced_at: Option<DateTime<Utc>>,
+}
+
+/// A random alphanumeric token, to be sent in links.
+pub fn generate_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
+}
+
+#[tracing::instrument(name = "Collect subscriber data", skip(db_connection, subscriber), fields(subscriber_id = %subscriber.id))]
+pub async fn collect_subscriber_data(
+    db_connection: &PgPool,
+    subscriber: Subscriber,
+) -> anyhow::Result<SubscriberData> {
+    let mut tokens: Vec<TokenRecord> = sqlx::query!(
+        "SELECT created_at FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber.id
+    )
+    .fetch_all(db_connection)
+    .await
+    .context("Failed to fetch the subscriber's tokens")?
+    .into_iter()
+    .map(|row| TokenRecord {
+        kind: "subscription",
+        created_at: row.created_at,
+    })
+    .collect();
+    tokens.extend(
+        sqlx::query!(
+            "SELECT created_at FROM erasure_tokens WHERE subscriber_id = $1",
+            subscriber.id
+        )
+        .fetch_all(db_connection)
+        .await
+        .context("Failed to fetch the subscriber's tokens")?
+        .into_iter()
+        .map(|row| TokenRecord {
+            kind: "erasure",
+            created_at: row.created_at,
+        }),
+    );
+    tokens.sort_by_key(|token| token.created_at);
+    let deliveries = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT d.newsletter_issue_id, i.subject, d.status, d.queued_at, d.sent_at, d.bounced_at
+        FROM deliveries d JOIN newsletter_issues i ON i.id = d.newsletter_issue_id
+        WHERE d.subscriber_id = $1
+        ORDER BY d.queued_at"#,
+        subscriber.id
+    )
+    .fetch_all(db_connection)
+    .await
+    .context("Failed to fetch the subscriber's deliveries")?;
+
+    Ok(SubscriberData {
+        subscriber,
+        tokens,
+        deliveries,
+        exported_at: Utc::now(),
+    })
+}
+
+/// Email `data` to the subscriber's own address, the only one allowed to receive it.
+#[tracing::instrument(name = "Send subscriber data export", skip(email_client, data), fields(subscriber_id = %data.subscriber.id))]
+pub async fn send_data_export(
+    email_client: &EmailClient,
+    data: &SubscriberData,
+) -> anyhow::Result<()> {
+    let recipient = SubscriberEmail::parse(data.subscriber.email.clone())
+        .context("The stored email address is invalid")?;
+    let json = serde_json::to_string_pretty(data)?;
+    let text_body = format!(
+        "Here is all the data we hold about you, as requested:\n\n{}",
+        json
+    );
+    let html_body = format!(
+        "<p>Here is all the data we hold about you, as requested:</p><pre>{}</pre>",
+        escape_html(&json)
+    );
+    let message =
+        EmailMessage::new(recipient, "Your data export", html_body, text_body).attachment(
+            Attachment::new("data.json", "application/json", json.into_bytes()),
+        );
+    email_client.send(message).await?;
+    Ok(())
+}
+
+/// Store a new erasure token for `subscriber_id` and email the confirmation link.
+#[tracing::instrument(name = "Request erasure", skip(db_connection, email_client, subscriber, base_url), fields(subscriber_id = %subscriber.id))]
+pub async fn request_erasure(
+    db_connection: &PgPool,
+    email_client: &EmailClient,
+    subscriber: &Subscriber,
+    base_url: &str,
+) -> anyhow::Result<()> {
+    let recipient = SubscriberEmail::parse(subscriber.email.clone())
+        .context("The stored email address is invalid")?;
+    let token = generate_token();
+    sqlx::query!(
+        "INSERT INTO erasure_tokens (token, subscriber_id, created_at) VALUES ($1, $2, $3)",
+        token,
+        subscriber.id,
+        Utc::now()
+    )
+    .execute(db_connection)
+    .await
+    .context("Failed to store the erasure token")?;
+
+    let link = format!("{}/data-requests/erasure/confirm?token={}", base_url, token);
+    let text_body = format!(
+        "Visit {} to permanently erase your subscription and all the data we hold about you.\n\
+        The link expires in 24 hours. Ignore this email if you did not ask for it.",
+        link
+    );
+    let html_body = format!(
+        "<p>Click <a href=\"{}\">here</a> to permanently erase your subscription and all the data we hold about you.</p>\
+        <p>The link expires in 24 hours. Ignore this email if you did not ask for it.</p>",
+        link
+    );
+    email_client
+        .send_email(
+            &recipient,
+            "Confirm the erasure of your data",
+            &html_body,
+            &text_body,
+        )
+        .await
+}
+
+/// The subscriber an unexpired erasure token was issued to.
+pub async fn subscriber_for_erasure_token(
+    db_connection: &PgPool,
+    token: &str,
+) -> anyhow::Result<Option<Uuid>> {
+    let row = sqlx::query!(
+        "SELECT subscriber_id FROM erasure_tokens WHERE token = $1 AND created_at > $2",
+        token,
+        Utc::now() - Duration::hours(ERASURE_TOKEN_TTL_HOURS)
+    )
+    .fetch_optional(db_connection)
+    .await
+    .context("Failed to look up the erasure token")?;
+    Ok(row.map(|row| row.subscriber_id))
+}
+
+/// Permanently delete a subscriber's personal data, in a single transaction.
+///
+/// An anonymous record of the subscriber (id, status, signup date) is kept in
+/// `subscriber_erasures`, with who requested the erasure and when, and their
+/// newsletter deliveries are kept without the subscriber, so that aggregate counts
+/// do not change. Any new table holding personal data must be cleaned up here too.
+///
+/// Returns `false` if there was no such subscriber.
+#[tracing::instrument(name = "Erase subscriber", skip(db_connection))]
+pub async fn erase_subscriber(
+    db_connection: &PgPool,
+    subscriber_id: Uuid,
+    requested_by: &str,
+) -> anyhow::Result<bool> {
+    let mut transaction = db_connection
+        .begin()
+        .await
+        .context("Failed to start a transaction")?;
+    let erased = sqlx::query!(
+        r#"
+        INSERT INTO subscriber_erasures (subscriber_id, status, subscribed_at, erased_at, requested_by)
+        SELECT id, status, subscribed_at, $2, $3 FROM subscriptions WHERE id = $1
+        "#,
+        subscriber_id,
+        Utc::now(),
+        requested_by
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to record the erasure")?;
+    if erased.rows_affected() == 0 {
+        return Ok(false);
+    }
+    sqlx::query!(
+        "DELETE FROM erasure_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the subscriber's tokens")?;
+    // Emails still queued would be skipped once the subscriber is gone, like those of
+    // subscribers who unsubscribe before their email is due.
+    sqlx::query!(
+        "DELETE FROM deliveries WHERE subscriber_id = $1 AND status = $2",
+        subscriber_id,
+        DeliveryStatus::Queued.as_str()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the subscriber's queued deliveries")?;
+    sqlx::query!(
+        "UPDATE deliveries SET subscriber_id = NULL WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to anonymise the subscriber's deliveries")?;
+    sqlx::query!("DELETE FROM subscriptions WHERE id = $1", subscriber_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the subscriber")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the erasure")?;
+    Ok(true)
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_random_and_alphanumeric() {
+        let token = generate_token();
+        assert_eq!(token.len(), 25);
+        assert!(token.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(token, generate_token());
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        assert_eq!(
+            escape_html(r#"<a href="x">&</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+}
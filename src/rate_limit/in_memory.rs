@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use super::{BucketState, RateLimitStore, RateLimited, TokenBucket};
+
+/// Above this many buckets, the least recently updated ones are dropped. A
+/// dropped bucket starts full again, so this bounds memory at the cost of
+/// letting the oldest clients in early.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Keeps buckets in a `HashMap`, for single-instance deployments and tests.
+pub struct InMemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, BucketState>>,
+    max_buckets: usize,
+}
+
+impl InMemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::with_max_buckets(MAX_BUCKETS)
+    }
+
+    pub fn with_max_buckets(max_buckets: usize) -> Self {
+        Self {
+            buckets: Mutex::new(HashMap::new()),
+            max_buckets: max_buckets.max(1),
+        }
+    }
+}
+
+impl Default for InMemoryRateLimitStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Drops the least recently updated buckets, making room for a tenth of
+/// `max_buckets` so that a full store is not scanned on every request.
+fn evict(buckets: &mut HashMap<String, BucketState>, max_buckets: usize) {
+    let keep = max_buckets - max_buckets / 10;
+    let excess = buckets.len().saturating_sub(keep);
+    if excess == 0 {
+        return;
+    }
+    let mut updates: Vec<_> = buckets.values().map(|state| state.updated_at).collect();
+    let (_, cutoff, _) = updates.select_nth_unstable(excess - 1);
+    let cutoff = *cutoff;
+    let mut to_remove = excess;
+    buckets.retain(|_, state| {
+        if to_remove > 0 && state.updated_at <= cutoff {
+            to_remove -= 1;
+            false
+        } else {
+            true
+        }
+    });
+}
+
+#[async_trait]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn acquire(
+        &self,
+        key: &str,
+        bucket: &TokenBucket,
+    ) -> anyhow::Result<Result<(), RateLimited>> {
+        let now = Utc::now();
+        let mut buckets = self
+            .buckets
+            .lock()
+            .expect("The rate limit buckets lock is poisoned");
+        let (state, decision) = bucket.take(buckets.get(key).copied(), now);
+        buckets.insert(key.to_owned(), state);
+        if buckets.len() > self.max_buckets {
+            evict(&mut buckets, self.max_buckets);
+        }
+        Ok(decision)
+    }
+}
@@ -0,0 +1,274 @@
+mod in_memory;
+mod postgres;
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sha2::Sha256;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::configuration::SettingsHandle;
+use crate::domain::SubscriberEmail;
+use crate::error::AppError;
+
+pub use in_memory::InMemoryRateLimitStore;
+pub use postgres::PgRateLimitStore;
+
+/// Allows bursts of `capacity` requests, then `refill_per_hour` requests an hour.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenBucket {
+    pub capacity: u32,
+    pub refill_per_hour: u32,
+}
+
+/// The tokens left in a bucket as of `updated_at`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketState {
+    pub tokens: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("Too many requests, try again in {} seconds.", self.retry_after_seconds())]
+pub struct RateLimited {
+    pub retry_after: Duration,
+}
+
+impl RateLimited {
+    /// Rounded up, as sent in the `Retry-After` header.
+    pub fn retry_after_seconds(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil().max(1.0) as u64
+    }
+}
+
+impl TokenBucket {
+    /// Take a token from a bucket last seen as `state`, a missing bucket being full.
+    /// The new state is returned either way, to be stored.
+    pub fn take(
+        &self,
+        state: Option<BucketState>,
+        now: DateTime<Utc>,
+    ) -> (BucketState, Result<(), RateLimited>) {
+        let capacity = self.capacity as f64;
+        let refill_per_second = self.refill_per_hour as f64 / 3600.0;
+        let tokens = match state {
+            Some(state) => {
+                let elapsed = (now - state.updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+                (state.tokens + elapsed * refill_per_second).min(capacity)
+            }
+            None => capacity,
+        };
+
+        if tokens >= 1.0 {
+            let state = BucketState {
+                tokens: tokens - 1.0,
+                updated_at: now,
+            };
+            (state, Ok(()))
+        } else {
+            let state = BucketState {
+                tokens,
+                updated_at: now,
+            };
+            let retry_after = if refill_per_second > 0.0 {
+                Duration::from_secs_f64((1.0 - tokens) / refill_per_second)
+            } else {
+                Duration::from_secs(3600)
+            };
+            (state, Err(RateLimited { retry_after }))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitStoreKind {
+    /// Per process: each instance counts on its own.
+    #[default]
+    Memory,
+    /// Shared by every instance using the same database.
+    Postgres,
+}
+
+/// Where the buckets are kept.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Take a token from the bucket stored under `key`.
+    async fn acquire(
+        &self,
+        key: &str,
+        bucket: &TokenBucket,
+    ) -> anyhow::Result<Result<(), RateLimited>>;
+}
+
+/// Token buckets keyed by client IP or email address.
+///
+/// Keys are hashed with a secret so that the stores never hold addresses in the clear.
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    key_secret: Secret<String>,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, key_secret: Secret<String>) -> Self {
+        Self { store, key_secret }
+    }
+
+    pub async fn check_ip(&self, ip: IpAddr, bucket: &TokenBucket) -> Result<(), RateLimited> {
+        self.check(self.key("ip", &ip.to_string()), bucket).await
+    }
+
+    pub async fn check_email(
+        &self,
+        email: &SubscriberEmail,
+        bucket: &TokenBucket,
+    ) -> Result<(), RateLimited> {
+        self.check(self.key("email", email.canonical()), bucket)
+            .await
+    }
+
+    async fn check(&self, key: String, bucket: &TokenBucket) -> Result<(), RateLimited> {
+        match self.store.acquire(&key, bucket).await {
+            Ok(decision) => decision,
+            // Like a lost counter: better than turning everybody away.
+            Err(e) => {
+                warn!("Failed to check the rate limit {:?}", e);
+                Ok(())
+            }
+        }
+    }
+
+    fn key(&self, kind: &str, value: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key_secret.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(value.as_bytes());
+        let digest = mac.finalize().into_bytes();
+        format!(
+            "{}:{}",
+            kind,
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(digest)
+        )
+    }
+}
+
+/// The address of the client, read from `X-Forwarded-For` only when the request
+/// comes from one of `trusted_proxies`.
+///
+/// Walks the header from the right, as each proxy appends the address it got the
+/// request from, and stops at the first address that is not a trusted proxy.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> IpAddr {
+    if !trusted_proxies.contains(&peer) {
+        return peer;
+    }
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|address| address.trim().parse().ok())
+        .collect();
+    forwarded
+        .iter()
+        .rev()
+        .find(|address| !trusted_proxies.contains(address))
+        .or_else(|| forwarded.first())
+        .copied()
+        .unwrap_or(peer)
+}
+
+/// The address found by [`client_ip`], left in the request extensions by
+/// [`limit_by_client_ip`] for handlers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Middleware limiting requests per client IP with the `rate_limit.per_ip` bucket.
+///
+/// Requests without a peer address, which only happens when the router is called
+/// directly rather than served, are let through.
+pub async fn limit_by_client_ip<B>(
+    State(limiter): State<RateLimiter>,
+    State(settings): State<SettingsHandle>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, AppError> {
+    if let Some(ConnectInfo(peer)) = request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        let settings = settings.current();
+        let ip = client_ip(
+            peer.ip(),
+            request.headers(),
+            &settings.rate_limit.trusted_proxies,
+        );
+        limiter.check_ip(ip, &settings.rate_limit.per_ip).await?;
+        request.extensions_mut().insert(ClientIp(ip));
+    }
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use claims::{assert_err, assert_ok};
+
+    const BUCKET: TokenBucket = TokenBucket {
+        capacity: 2,
+        refill_per_hour: 60,
+    };
+
+    #[test]
+    fn buckets_allow_bursts_then_refill_over_time() {
+        let now = Utc::now();
+        let (state, decision) = BUCKET.take(None, now);
+        assert_ok!(decision);
+        let (state, decision) = BUCKET.take(Some(state), now);
+        assert_ok!(decision);
+
+        let (state, decision) = BUCKET.take(Some(state), now);
+        let limited = assert_err!(decision);
+        assert_eq!(limited.retry_after_seconds(), 60);
+
+        let (_, decision) = BUCKET.take(Some(state), now + chrono::Duration::seconds(60));
+        assert_ok!(decision);
+    }
+
+    #[test]
+    fn buckets_never_hold_more_than_their_capacity() {
+        let now = Utc::now();
+        let (state, _) = BUCKET.take(None, now - chrono::Duration::days(1));
+        let (state, _) = BUCKET.take(Some(state), now);
+        assert_eq!(state.tokens, 1.0);
+    }
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn forwarded_addresses_are_only_trusted_from_trusted_proxies() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let headers = forwarded_for("198.51.100.1, 203.0.113.7, 10.0.0.2");
+
+        assert_eq!(client_ip(proxy, &headers, &[]), proxy);
+        assert_eq!(
+            client_ip(proxy, &headers, &[proxy, "10.0.0.2".parse().unwrap()]),
+            client
+        );
+        assert_eq!(client_ip(proxy, &HeaderMap::new(), &[proxy]), proxy);
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+
+use super::{BucketState, RateLimitStore, RateLimited, TokenBucket};
+
+pub struct PgRateLimitStore {
+    db_connection: PgPool,
+}
+
+impl PgRateLimitStore {
+    pub fn new(db_connection: PgPool) -> Self {
+        Self { db_connection }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for PgRateLimitStore {
+    async fn acquire(
+        &self,
+        key: &str,
+        bucket: &TokenBucket,
+    ) -> anyhow::Result<Result<(), RateLimited>> {
+        let now = Utc::now();
+        let mut transaction = self
+            .db_connection
+            .begin()
+            .await
+            .context("Failed to start a transaction")?;
+        // Two requests creating the same bucket at once may both find it full: that
+        // costs one extra request at most, unlike locking the whole table.
+        let state = sqlx::query_as!(
+            BucketState,
+            "SELECT tokens, updated_at FROM rate_limit_buckets WHERE key = $1 FOR UPDATE",
+            key
+        )
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to fetch the rate limit bucket")?;
+
+        let (state, decision) = bucket.take(state, now);
+        sqlx::query!(
+            r#"
+            INSERT INTO rate_limit_buckets (key, tokens, updated_at) VALUES ($1, $2, $3)
+            ON CONFLICT (key) DO UPDATE SET tokens = EXCLUDED.tokens, updated_at = EXCLUDED.updated_at"#,
+            key,
+            state.tokens,
+            state.updated_at
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to store the rate limit bucket")?;
+        // Now and then, forget buckets that have long been full.
+        if rand::random::<u8>() == 0 {
+            sqlx::query!(
+                "DELETE FROM rate_limit_buckets WHERE updated_at < $1",
+                now - Duration::days(1)
+            )
+            .execute(&mut transaction)
+            .await
+            .context("Failed to delete stale rate limit buckets")?;
+        }
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the rate limit bucket")?;
+        Ok(decision)
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream};
+use uuid::Uuid;
+
+use super::{
+    RepositoryError, Subscriber, SubscriberFilter, SubscriberRepository, SubscriberUpdate,
+};
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriptionStatus};
+
+/// A stored subscriber. Deleted ones are kept with a `deleted_at`, like the soft-deleted
+/// rows in Postgres, and hidden from every read.
+struct Row {
+    subscriber: Subscriber,
+    deleted_at: Option<DateTime<Utc>>,
+}
+
+fn live(rows: &[Row]) -> impl Iterator<Item = &Subscriber> {
+    rows.iter()
+        .filter(|row| row.deleted_at.is_none())
+        .map(|row| &row.subscriber)
+}
+
+fn live_mut(rows: &mut [Row]) -> impl Iterator<Item = &mut Subscriber> {
+    rows.iter_mut()
+        .filter(|row| row.deleted_at.is_none())
+        .map(|row| &mut row.subscriber)
+}
+
+/// Keeps subscribers in a `Vec`, for tests and local experiments.
+#[derive(Default)]
+pub struct InMemorySubscriberRepository {
+    subscribers: Mutex<Vec<Row>>,
+    /// Subscriber ids by token.
+    tokens: Mutex<HashMap<String, Uuid>>,
+}
+
+impl InMemorySubscriberRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_subscribers<T>(&self, f: impl FnOnce(&mut Vec<Row>) -> T) -> T {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .expect("The subscribers lock is poisoned");
+        f(&mut subscribers)
+    }
+}
+
+/// Stored emails are kept as entered, so they are compared through their canonical form.
+fn same_address(stored: &str, email: &SubscriberEmail) -> bool {
+    SubscriberEmail::parse(stored.to_owned()).is_ok_and(|s| s.canonical() == email.canonical())
+}
+
+#[async_trait]
+impl SubscriberRepository for InMemorySubscriberRepository {
+    async fn insert(&self, subscriber: &NewSubscriber) -> Result<Subscriber, RepositoryError> {
+        self.with_subscribers(|subscribers| {
+            if live(subscribers).any(|s| same_address(&s.email, &subscriber.email)) {
+                return Err(RepositoryError::DuplicateEmail);
+            }
+            let now = Utc::now();
+            let stored = Subscriber {
+                id: Uuid::new_v4(),
+                email: subscriber.email.original().to_owned(),
+                name: subscriber.name.as_ref().to_owned(),
+                timezone: subscriber.timezone.map(|tz| tz.as_ref().to_owned()),
+                status: SubscriptionStatus::PendingConfirmation,
+                subscribed_at: now,
+                confirmed_at: None,
+                unsubscribed_at: None,
+                updated_at: now,
+            };
+            subscribers.push(Row {
+                subscriber: stored.clone(),
+                deleted_at: None,
+            });
+            Ok(stored)
+        })
+    }
+
+    async fn insert_many(
+        &self,
+        new_subscribers: &[NewSubscriber],
+        status: SubscriptionStatus,
+    ) -> Result<Vec<Subscriber>, RepositoryError> {
+        let subscribed_at = Utc::now();
+        Ok(self.with_subscribers(|subscribers| {
+            let mut inserted = vec![];
+            for subscriber in new_subscribers {
+                if live(subscribers).any(|s| same_address(&s.email, &subscriber.email)) {
+                    continue;
+                }
+                let stored = Subscriber {
+                    id: Uuid::new_v4(),
+                    email: subscriber.email.original().to_owned(),
+                    name: subscriber.name.as_ref().to_owned(),
+                    timezone: subscriber.timezone.map(|tz| tz.as_ref().to_owned()),
+                    status,
+                    subscribed_at,
+                    confirmed_at: (status == SubscriptionStatus::Confirmed)
+                        .then_some(subscribed_at),
+                    unsubscribed_at: None,
+                    updated_at: subscribed_at,
+                };
+                subscribers.push(Row {
+                    subscriber: stored.clone(),
+                    deleted_at: None,
+                });
+                inserted.push(stored);
+            }
+            inserted
+        }))
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<Subscriber>, RepositoryError> {
+        Ok(self.with_subscribers(|subscribers| live(subscribers).find(|s| s.id == id).cloned()))
+    }
+
+    async fn find_by_email(
+        &self,
+        email: &SubscriberEmail,
+    ) -> Result<Option<Subscriber>, RepositoryError> {
+        Ok(self.with_subscribers(|subscribers| {
+            live(subscribers)
+                .find(|s| same_address(&s.email, email))
+                .cloned()
+        }))
+    }
+
+    async fn update_status(
+        &self,
+        id: Uuid,
+        status: SubscriptionStatus,
+    ) -> Result<Option<Subscriber>, RepositoryError> {
+        self.with_subscribers(|subscribers| {
+            let Some(subscriber) = live_mut(subscribers).find(|s| s.id == id) else {
+                return Ok(None);
+            };
+            subscriber.status = subscriber.status.transition_to(status)?;
+            let now = Utc::now();
+            match status {
+                SubscriptionStatus::Confirmed => subscriber.confirmed_at = Some(now),
+                SubscriptionStatus::Unsubscribed => subscriber.unsubscribed_at = Some(now),
+                SubscriptionStatus::PendingConfirmation => {}
+            }
+            subscriber.updated_at = now;
+            Ok(Some(subscriber.clone()))
+        })
+    }
+
+    async fn update(
+        &self,
+        id: Uuid,
+        update: &SubscriberUpdate,
+    ) -> Result<Option<Subscriber>, RepositoryError> {
+        self.with_subscribers(|subscribers| {
+            if let Some(email) = &update.email {
+                if live(subscribers).any(|s| s.id != id && same_address(&s.email, email)) {
+                    return Err(RepositoryError::DuplicateEmail);
+                }
+            }
+            Ok(live_mut(subscribers).find(|s| s.id == id).map(|s| {
+                if let Some(name) = &update.name {
+                    s.name = name.as_ref().to_owned();
+                }
+                if let Some(email) = &update.email {
+                    s.email = email.original().to_owned();
+                }
+                s.updated_at = Utc::now();
+                s.clone()
+            }))
+        })
+    }
+
+    async fn list(&self, filter: &SubscriberFilter) -> Result<Vec<Subscriber>, RepositoryError> {
+        Ok(self.with_subscribers(|subscribers| {
+            let mut matching: Vec<_> = live(subscribers)
+                .filter(|s| filter.matches(s))
+                .cloned()
+                .collect();
+            matching.sort_by_key(|s| (s.subscribed_at, s.id));
+            if let Some(limit) = filter.limit {
+                matching.truncate(limit.max(0) as usize);
+            }
+            matching
+        }))
+    }
+
+    fn stream(
+        &self,
+        filter: SubscriberFilter,
+    ) -> BoxStream<'static, Result<Subscriber, RepositoryError>> {
+        let matching = self.with_subscribers(|subscribers| {
+            let mut matching: Vec<_> = live(subscribers)
+                .filter(|s| filter.matches(s))
+                .cloned()
+                .collect();
+            matching.sort_by_key(|s| (s.subscribed_at, s.id));
+            matching
+        });
+        Box::pin(stream::iter(matching.into_iter().map(Ok)))
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<bool, RepositoryError> {
+        Ok(self.with_subscribers(|subscribers| {
+            let Some(row) = subscribers
+                .iter_mut()
+                .find(|row| row.subscriber.id == id && row.deleted_at.is_none())
+            else {
+                return false;
+            };
+            let now = Utc::now();
+            row.deleted_at = Some(now);
+            row.subscriber.updated_at = now;
+            true
+        }))
+    }
+
+    async fn insert_token(&self, subscriber_id: Uuid, token: &str) -> Result<(), RepositoryError> {
+        self.tokens
+            .lock()
+            .expect("The tokens lock is poisoned")
+            .insert(token.to_owned(), subscriber_id);
+        Ok(())
+    }
+
+    async fn find_by_token(&self, token: &str) -> Result<Option<Subscriber>, RepositoryError> {
+        let subscriber_id = self
+            .tokens
+            .lock()
+            .expect("The tokens lock is poisoned")
+            .get(token)
+            .copied();
+        match subscriber_id {
+            Some(id) => self.find_by_id(id).await,
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+mod in_memory;
+mod postgres;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use serde::Serialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::domain::{
+    InvalidStatusTransition, NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus,
+};
+
+pub use in_memory::InMemorySubscriberRepository;
+pub use postgres::PgSubscriberRepository;
+
+/// A subscriber as stored, whatever validation rules were in place when it was saved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Subscriber {
+    pub id: Uuid,
+    /// As entered, with its domain normalised. See [`SubscriberEmail`].
+    pub email: String,
+    pub name: String,
+    /// An IANA time zone name, if the subscriber gave one. See [`crate::domain::SubscriberTimezone`].
+    pub timezone: Option<String>,
+    pub status: SubscriptionStatus,
+    pub subscribed_at: DateTime<Utc>,
+    /// When the subscriber last confirmed, if ever.
+    pub confirmed_at: Option<DateTime<Utc>>,
+    /// When the subscriber last unsubscribed, if ever.
+    pub unsubscribed_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Criteria for [`SubscriberRepository::list`]. Unset fields do not filter.
+#[derive(Debug, Clone, Default)]
+pub struct SubscriberFilter {
+    pub status: Option<SubscriptionStatus>,
+    pub subscribed_since: Option<DateTime<Utc>>,
+    pub subscribed_until: Option<DateTime<Utc>>,
+    /// Matches subscribers whose email or name starts with this text, ignoring case.
+    pub search: Option<String>,
+    /// Keyset pagination: only subscribers strictly after this `(subscribed_at, id)` pair.
+    pub after: Option<(DateTime<Utc>, Uuid)>,
+    pub limit: Option<i64>,
+}
+
+/// Corrections to a subscriber's details. Unset fields are left unchanged.
+#[derive(Debug, Default)]
+pub struct SubscriberUpdate {
+    pub name: Option<SubscriberName>,
+    pub email: Option<SubscriberEmail>,
+}
+
+impl SubscriberFilter {
+    fn matches(&self, subscriber: &Subscriber) -> bool {
+        let starts_with =
+            |value: &str, prefix: &str| value.to_lowercase().starts_with(&prefix.to_lowercase());
+        self.status.is_none_or(|status| subscriber.status == status)
+            && self
+                .subscribed_since
+                .is_none_or(|since| subscriber.subscribed_at >= since)
+            && self
+                .subscribed_until
+                .is_none_or(|until| subscriber.subscribed_at < until)
+            && self.search.as_deref().is_none_or(|search| {
+                starts_with(&subscriber.email, search) || starts_with(&subscriber.name, search)
+            })
+            && self
+                .after
+                .is_none_or(|after| (subscriber.subscribed_at, subscriber.id) > after)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RepositoryError {
+    #[error("A subscriber with this email already exists")]
+    DuplicateEmail,
+    #[error(transparent)]
+    InvalidTransition(#[from] InvalidStatusTransition),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+/// Storage for subscribers, so that handlers do not depend on a specific database.
+///
+/// Deleted subscribers are kept but never returned, updated or counted as taking
+/// their email address.
+#[async_trait]
+pub trait SubscriberRepository: Send + Sync {
+    async fn insert(&self, subscriber: &NewSubscriber) -> Result<Subscriber, RepositoryError>;
+
+    /// Insert `subscribers` with `status`, skipping emails that are already taken,
+    /// including repeats within `subscribers`. Returns the subscribers actually inserted.
+    async fn insert_many(
+        &self,
+        subscribers: &[NewSubscriber],
+        status: SubscriptionStatus,
+    ) -> Result<Vec<Subscriber>, RepositoryError>;
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<Subscriber>, RepositoryError>;
+
+    /// Matches on the canonical form of `email`, ignoring the case of its local part.
+    async fn find_by_email(
+        &self,
+        email: &SubscriberEmail,
+    ) -> Result<Option<Subscriber>, RepositoryError>;
+
+    /// Returns `None` if there is no subscriber with this id, and
+    /// [`RepositoryError::InvalidTransition`] if their current status cannot change to `status`.
+    async fn update_status(
+        &self,
+        id: Uuid,
+        status: SubscriptionStatus,
+    ) -> Result<Option<Subscriber>, RepositoryError>;
+
+    /// Returns `None` if there is no subscriber with this id.
+    async fn update(
+        &self,
+        id: Uuid,
+        update: &SubscriberUpdate,
+    ) -> Result<Option<Subscriber>, RepositoryError>;
+
+    /// Subscribers matching `filter`, oldest first.
+    async fn list(&self, filter: &SubscriberFilter) -> Result<Vec<Subscriber>, RepositoryError>;
+
+    /// Like [`SubscriberRepository::list`], but yields subscribers one at a time
+    /// so that large lists can be processed without holding them all in memory.
+    fn stream(
+        &self,
+        filter: SubscriberFilter,
+    ) -> BoxStream<'static, Result<Subscriber, RepositoryError>>;
+
+    /// Soft-deletes the subscriber. Returns `false` if there was no subscriber with this id.
+    async fn delete(&self, id: Uuid) -> Result<bool, RepositoryError>;
+
+    /// Store a token sent to the subscriber in confirmation and unsubscribe links.
+    async fn insert_token(&self, subscriber_id: Uuid, token: &str) -> Result<(), RepositoryError>;
+
+    /// The subscriber `token` was sent to, unless they have been deleted since.
+    async fn find_by_token(&self, token: &str) -> Result<Option<Subscriber>, RepositoryError>;
+}
+
+pub type SharedSubscriberRepository = Arc<dyn SubscriberRepository>;
@@ -0,0 +1,389 @@
+use anyhow::Context;
+use async_stream::try_stream;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use tracing::error;
+use uuid::Uuid;
+
+use super::{
+    RepositoryError, Subscriber, SubscriberFilter, SubscriberRepository, SubscriberUpdate,
+};
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriptionStatus};
+
+pub struct PgSubscriberRepository {
+    db_connection: PgPool,
+}
+
+impl PgSubscriberRepository {
+    pub fn new(db_connection: PgPool) -> Self {
+        Self { db_connection }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    timezone: Option<String>,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+    unsubscribed_at: Option<DateTime<Utc>>,
+    updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<SubscriberRow> for Subscriber {
+    type Error = RepositoryError;
+
+    fn try_from(row: SubscriberRow) -> Result<Self, Self::Error> {
+        Ok(Subscriber {
+            id: row.id,
+            email: row.email,
+            name: row.name,
+            timezone: row.timezone,
+            status: row.status.try_into()?,
+            subscribed_at: row.subscribed_at,
+            confirmed_at: row.confirmed_at,
+            unsubscribed_at: row.unsubscribed_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
+fn to_repository_error(e: sqlx::Error, context: &'static str) -> RepositoryError {
+    if let sqlx::Error::Database(db_error) = &e {
+        if db_error.constraint() == Some("subscriptions_email_key") {
+            return RepositoryError::DuplicateEmail;
+        }
+    }
+    error!("Failed to execute query {:?}", e);
+    RepositoryError::Unexpected(anyhow::Error::new(e).context(context))
+}
+
+#[async_trait]
+impl SubscriberRepository for PgSubscriberRepository {
+    #[tracing::instrument(name = "Insert a new subscriber into database", skip(self, subscriber))]
+    async fn insert(&self, subscriber: &NewSubscriber) -> Result<Subscriber, RepositoryError> {
+        let row = sqlx::query_as!(
+            SubscriberRow,
+            r#"
+            INSERT INTO subscriptions
+                (id, email, email_canonical, name, timezone, subscribed_at, updated_at, status)
+            VALUES ($1, $2, $3, $4, $5, $6, $6, $7)
+            RETURNING id, email, name, timezone, status, subscribed_at, confirmed_at, unsubscribed_at, updated_at"#,
+            Uuid::new_v4(),
+            subscriber.email.original(),
+            subscriber.email.canonical(),
+            subscriber.name.as_ref(),
+            subscriber.timezone.as_ref().map(AsRef::as_ref),
+            Utc::now(),
+            SubscriptionStatus::PendingConfirmation.as_str()
+        )
+        .fetch_one(&self.db_connection)
+        .await
+        .map_err(|e| to_repository_error(e, "Failed to insert subscriber"))?;
+        row.try_into()
+    }
+
+    #[tracing::instrument(
+        name = "Insert a batch of subscribers into database",
+        skip(self, subscribers),
+        fields(batch_size = subscribers.len())
+    )]
+    async fn insert_many(
+        &self,
+        subscribers: &[NewSubscriber],
+        status: SubscriptionStatus,
+    ) -> Result<Vec<Subscriber>, RepositoryError> {
+        let ids: Vec<Uuid> = subscribers.iter().map(|_| Uuid::new_v4()).collect();
+        let emails: Vec<String> = subscribers
+            .iter()
+            .map(|s| s.email.original().to_owned())
+            .collect();
+        let canonical_emails: Vec<String> = subscribers
+            .iter()
+            .map(|s| s.email.canonical().to_owned())
+            .collect();
+        let names: Vec<String> = subscribers
+            .iter()
+            .map(|s| s.name.as_ref().to_owned())
+            .collect();
+        // Empty for subscribers without a time zone, stored as `NULL`.
+        let timezones: Vec<String> = subscribers
+            .iter()
+            .map(|s| {
+                s.timezone
+                    .map_or_else(String::new, |tz| tz.as_ref().to_owned())
+            })
+            .collect();
+        // `ON CONFLICT DO NOTHING` also skips repeats within the batch itself.
+        sqlx::query_as!(
+            SubscriberRow,
+            r#"
+            INSERT INTO subscriptions
+                (id, email, email_canonical, name, timezone, subscribed_at, updated_at, status,
+                 confirmed_at)
+            SELECT id, email, email_canonical, name, NULLIF(timezone, ''), $6, $6, $7,
+                CASE WHEN $7 = 'confirmed' THEN $6::timestamptz END
+            FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::text[], $5::text[])
+                AS batch(id, email, email_canonical, name, timezone)
+            ON CONFLICT (email_canonical) WHERE deleted_at IS NULL DO NOTHING
+            RETURNING id, email, name, timezone, status, subscribed_at, confirmed_at, unsubscribed_at, updated_at"#,
+            &ids,
+            &emails,
+            &canonical_emails,
+            &names,
+            &timezones,
+            Utc::now(),
+            status.as_str()
+        )
+        .fetch_all(&self.db_connection)
+        .await
+        .map_err(|e| to_repository_error(e, "Failed to insert subscribers"))?
+        .into_iter()
+        .map(Subscriber::try_from)
+        .collect()
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<Subscriber>, RepositoryError> {
+        sqlx::query_as!(
+            SubscriberRow,
+            r#"
+            SELECT id, email, name, timezone, status, subscribed_at, confirmed_at, unsubscribed_at, updated_at
+            FROM subscriptions WHERE id = $1 AND deleted_at IS NULL"#,
+            id
+        )
+        .fetch_optional(&self.db_connection)
+        .await
+        .map_err(|e| to_repository_error(e, "Failed to fetch subscriber"))?
+        .map(Subscriber::try_from)
+        .transpose()
+    }
+
+    async fn find_by_email(
+        &self,
+        email: &SubscriberEmail,
+    ) -> Result<Option<Subscriber>, RepositoryError> {
+        sqlx::query_as!(
+            SubscriberRow,
+            r#"
+            SELECT id, email, name, timezone, status, subscribed_at, confirmed_at, unsubscribed_at, updated_at
+            FROM subscriptions WHERE email_canonical = $1 AND deleted_at IS NULL"#,
+            email.canonical()
+        )
+        .fetch_optional(&self.db_connection)
+        .await
+        .map_err(|e| to_repository_error(e, "Failed to fetch subscriber"))?
+        .map(Subscriber::try_from)
+        .transpose()
+    }
+
+    #[tracing::instrument(name = "Update subscriber status", skip(self))]
+    async fn update_status(
+        &self,
+        id: Uuid,
+        status: SubscriptionStatus,
+    ) -> Result<Option<Subscriber>, RepositoryError> {
+        let mut transaction = self
+            .db_connection
+            .begin()
+            .await
+            .context("Failed to start a transaction")?;
+        // Lock the row so that concurrent changes cannot skip a transition check.
+        let current = sqlx::query!(
+            r#"
+            SELECT status FROM subscriptions
+            WHERE id = $1 AND deleted_at IS NULL
+            FOR UPDATE"#,
+            id
+        )
+        .fetch_optional(&mut transaction)
+        .await
+        .map_err(|e| to_repository_error(e, "Failed to fetch subscriber status"))?;
+        let current: SubscriptionStatus = match current {
+            Some(row) => row.status.try_into()?,
+            None => return Ok(None),
+        };
+        let status = current.transition_to(status)?;
+
+        let row = sqlx::query_as!(
+            SubscriberRow,
+            r#"
+            UPDATE subscriptions
+            SET status = $2,
+                updated_at = $3,
+                confirmed_at = CASE WHEN $2 = 'confirmed' THEN $3 ELSE confirmed_at END,
+                unsubscribed_at = CASE WHEN $2 = 'unsubscribed' THEN $3 ELSE unsubscribed_at END
+            WHERE id = $1
+            RETURNING id, email, name, timezone, status, subscribed_at, confirmed_at, unsubscribed_at, updated_at"#,
+            id,
+            status.as_str(),
+            Utc::now()
+        )
+        .fetch_one(&mut transaction)
+        .await
+        .map_err(|e| to_repository_error(e, "Failed to update subscriber status"))?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the status update")?;
+        Ok(Some(row.try_into()?))
+    }
+
+    #[tracing::instrument(name = "Update subscriber details", skip(self, update))]
+    async fn update(
+        &self,
+        id: Uuid,
+        update: &SubscriberUpdate,
+    ) -> Result<Option<Subscriber>, RepositoryError> {
+        sqlx::query_as!(
+            SubscriberRow,
+            r#"
+            UPDATE subscriptions
+            SET name = COALESCE($2, name),
+                email = COALESCE($3, email),
+                email_canonical = COALESCE($4, email_canonical),
+                updated_at = $5
+            WHERE id = $1 AND deleted_at IS NULL
+            RETURNING id, email, name, timezone, status, subscribed_at, confirmed_at, unsubscribed_at, updated_at"#,
+            id,
+            update.name.as_ref().map(|name| name.as_ref()),
+            update.email.as_ref().map(|email| email.original()),
+            update.email.as_ref().map(|email| email.canonical()),
+            Utc::now()
+        )
+        .fetch_optional(&self.db_connection)
+        .await
+        .map_err(|e| to_repository_error(e, "Failed to update subscriber"))?
+        .map(Subscriber::try_from)
+        .transpose()
+    }
+
+    async fn list(&self, filter: &SubscriberFilter) -> Result<Vec<Subscriber>, RepositoryError> {
+        filtered_query(filter)
+            .build_query_as::<SubscriberRow>()
+            .fetch_all(&self.db_connection)
+            .await
+            .map_err(|e| to_repository_error(e, "Failed to list subscribers"))?
+            .into_iter()
+            .map(Subscriber::try_from)
+            .collect()
+    }
+
+    fn stream(
+        &self,
+        filter: SubscriberFilter,
+    ) -> BoxStream<'static, Result<Subscriber, RepositoryError>> {
+        let db_connection = self.db_connection.clone();
+        Box::pin(try_stream! {
+            let mut query = filtered_query(&filter);
+            // `fetch` pulls rows as they are consumed rather than loading them all.
+            let mut rows = query
+                .build_query_as::<SubscriberRow>()
+                .fetch(&db_connection);
+            while let Some(row) = rows
+                .try_next()
+                .await
+                .map_err(|e| to_repository_error(e, "Failed to stream subscribers"))?
+            {
+                yield Subscriber::try_from(row)?;
+            }
+        })
+    }
+
+    #[tracing::instrument(name = "Delete subscriber", skip(self))]
+    async fn delete(&self, id: Uuid) -> Result<bool, RepositoryError> {
+        let deleted = sqlx::query!(
+            r#"
+            UPDATE subscriptions SET deleted_at = $2, updated_at = $2
+            WHERE id = $1 AND deleted_at IS NULL"#,
+            id,
+            Utc::now()
+        )
+        .execute(&self.db_connection)
+        .await
+        .context("Failed to delete subscriber")?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn insert_token(&self, subscriber_id: Uuid, token: &str) -> Result<(), RepositoryError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscription_tokens (subscription_token, subscriber_id, created_at)
+            VALUES ($1, $2, $3)"#,
+            token,
+            subscriber_id,
+            Utc::now()
+        )
+        .execute(&self.db_connection)
+        .await
+        .context("Failed to store subscription token")?;
+        Ok(())
+    }
+
+    async fn find_by_token(&self, token: &str) -> Result<Option<Subscriber>, RepositoryError> {
+        sqlx::query_as!(
+            SubscriberRow,
+            r#"
+            SELECT s.id, s.email, s.name, s.timezone, s.status, s.subscribed_at, s.confirmed_at,
+                s.unsubscribed_at, s.updated_at
+            FROM subscription_tokens t JOIN subscriptions s ON s.id = t.subscriber_id
+            WHERE t.subscription_token = $1 AND s.deleted_at IS NULL"#,
+            token
+        )
+        .fetch_optional(&self.db_connection)
+        .await
+        .map_err(|e| to_repository_error(e, "Failed to fetch subscriber by token"))?
+        .map(Subscriber::try_from)
+        .transpose()
+    }
+}
+
+fn filtered_query(filter: &SubscriberFilter) -> QueryBuilder<'static, Postgres> {
+    let mut query: QueryBuilder<Postgres> = QueryBuilder::new(
+        "SELECT id, email, name, timezone, status, subscribed_at, confirmed_at, unsubscribed_at, updated_at \
+        FROM subscriptions WHERE deleted_at IS NULL",
+    );
+    if let Some(status) = filter.status {
+        query.push(" AND status = ").push_bind(status.as_str());
+    }
+    if let Some(since) = filter.subscribed_since {
+        query.push(" AND subscribed_at >= ").push_bind(since);
+    }
+    if let Some(until) = filter.subscribed_until {
+        query.push(" AND subscribed_at < ").push_bind(until);
+    }
+    if let Some(search) = &filter.search {
+        let pattern = format!("{}%", escape_like(search));
+        query
+            .push(" AND (email ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR name ILIKE ")
+            .push_bind(pattern)
+            .push(")");
+    }
+    if let Some((subscribed_at, id)) = filter.after {
+        query
+            .push(" AND (subscribed_at, id) > (")
+            .push_bind(subscribed_at)
+            .push(", ")
+            .push_bind(id)
+            .push(")");
+    }
+    query.push(" ORDER BY subscribed_at, id");
+    if let Some(limit) = filter.limit {
+        query.push(" LIMIT ").push_bind(limit);
+    }
+    query
+}
+
+/// Escape the `LIKE` wildcards so user input only ever matches literally.
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
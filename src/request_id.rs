@@ -0,0 +1,47 @@
+use std::convert::Infallible;
+
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, Request};
+use tracing::Span;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The id given to the current request by `SetRequestIdLayer`, or by the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub Option<String>);
+
+impl RequestId {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        Self(
+            headers
+                .get(REQUEST_ID_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned),
+        )
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestId
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_headers(&parts.headers))
+    }
+}
+
+/// The span every request is handled in, so that all its logs carry the request id.
+pub fn make_span<B>(request: &Request<B>) -> Span {
+    let request_id = RequestId::from_headers(request.headers()).0;
+    tracing::info_span!(
+        "http_request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = request_id.as_deref().unwrap_or_default(),
+    )
+}
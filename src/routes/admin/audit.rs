@@ -0,0 +1,71 @@
+use axum::extract::{Query, State};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{AuditAction, AuditEvent, AuditFilter, SharedAuditLog};
+use crate::authentication::AdminUser;
+use crate::error::AppError;
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 500;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ListAuditEventsQuery {
+    /// e.g. `subscriber:<id>`.
+    pub target: Option<String>,
+    /// e.g. `admin:<username>`.
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// The `next_cursor` of the previous page.
+    pub cursor: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AuditEventPage {
+    pub events: Vec<AuditEvent>,
+    /// Absent on the last page.
+    pub next_cursor: Option<i64>,
+}
+
+/// Audit events, newest first.
+#[tracing::instrument(name = "List audit events", skip(audit_log, admin), fields(admin = %admin.username))]
+pub async fn list_audit_events(
+    admin: AdminUser,
+    State(audit_log): State<SharedAuditLog>,
+    Query(query): Query<ListAuditEventsQuery>,
+) -> Result<Json<AuditEventPage>, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::BadRequest(format!(
+            "limit must be between 1 and {}.",
+            MAX_PAGE_SIZE
+        )));
+    }
+
+    let filter = AuditFilter {
+        target: query.target,
+        actor: query.actor,
+        action: query.action,
+        since: query.since,
+        until: query.until,
+        before: query.cursor,
+        // One extra row tells whether there is a next page.
+        limit: Some(limit + 1),
+    };
+    let mut events = audit_log.query(&filter).await?;
+    let next_cursor = if events.len() as i64 > limit {
+        events.truncate(limit as usize);
+        events.last().map(|event| event.id)
+    } else {
+        None
+    };
+
+    Ok(Json(AuditEventPage {
+        events,
+        next_cursor,
+    }))
+}
@@ -0,0 +1,89 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::info;
+
+use crate::audit::{self, AuditAction, AuditTarget, NewAuditEvent, SharedAuditLog};
+use crate::authentication::AdminUser;
+use crate::email_policy::{self, DomainRule, DomainRuleEntry, EmailPolicy};
+use crate::error::AppError;
+use crate::request_id::RequestId;
+
+#[derive(Deserialize, Debug)]
+pub struct SetDomainRuleBody {
+    pub rule: DomainRule,
+}
+
+fn parse_domain(domain: &str) -> Result<String, AppError> {
+    email_policy::parse_domain(domain).map_err(|e| AppError::BadRequest(e.to_string()))
+}
+
+#[tracing::instrument(name = "List email domain rules", skip(email_policy, admin), fields(admin = %admin.username))]
+pub async fn list_email_domain_rules(
+    admin: AdminUser,
+    State(email_policy): State<EmailPolicy>,
+) -> Result<Json<Vec<DomainRuleEntry>>, AppError> {
+    Ok(Json(email_policy.rules().list().await?))
+}
+
+/// Blocks or allows new subscribers from a domain and its subdomains.
+#[tracing::instrument(name = "Set email domain rule", skip(email_policy, audit_log, admin), fields(admin = %admin.username))]
+pub async fn set_email_domain_rule(
+    admin: AdminUser,
+    request_id: RequestId,
+    State(email_policy): State<EmailPolicy>,
+    State(audit_log): State<SharedAuditLog>,
+    Path(domain): Path<String>,
+    Json(body): Json<SetDomainRuleBody>,
+) -> Result<Json<DomainRuleEntry>, AppError> {
+    let domain = parse_domain(&domain)?;
+    let entry = email_policy
+        .rules()
+        .set(&domain, body.rule, &admin.actor().to_string())
+        .await?;
+    info!("Email domain rule has been set");
+    audit::record(
+        audit_log.as_ref(),
+        NewAuditEvent::new(
+            admin.actor(),
+            AuditAction::EmailDomainRuleSet,
+            AuditTarget::email_domain(&domain),
+        )
+        .request_id(request_id.0)
+        .details(json!({ "rule": body.rule })),
+    )
+    .await;
+    Ok(Json(entry))
+}
+
+#[tracing::instrument(name = "Remove email domain rule", skip(email_policy, audit_log, admin), fields(admin = %admin.username))]
+pub async fn remove_email_domain_rule(
+    admin: AdminUser,
+    request_id: RequestId,
+    State(email_policy): State<EmailPolicy>,
+    State(audit_log): State<SharedAuditLog>,
+    Path(domain): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let domain = parse_domain(&domain)?;
+    if email_policy.rules().remove(&domain).await? {
+        info!("Email domain rule has been removed");
+        audit::record(
+            audit_log.as_ref(),
+            NewAuditEvent::new(
+                admin.actor(),
+                AuditAction::EmailDomainRuleRemoved,
+                AuditTarget::email_domain(&domain),
+            )
+            .request_id(request_id.0),
+        )
+        .await;
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound(format!(
+            "There is no rule for the domain {}.",
+            domain
+        )))
+    }
+}
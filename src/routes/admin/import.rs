@@ -0,0 +1,49 @@
+use std::io;
+
+use axum::extract::{BodyStream, Query, State};
+use axum::Json;
+use futures::TryStreamExt;
+use tokio_util::io::StreamReader;
+
+use crate::audit::{self, AuditAction, AuditTarget, NewAuditEvent};
+use crate::authentication::AdminUser;
+use crate::email_policy::MX_CHECK_FEATURE;
+use crate::error::AppError;
+use crate::import::{self, ImportOptions, ImportReport};
+use crate::request_id::RequestId;
+use crate::state::AppState;
+
+/// Import the CSV sent as the request body. The upload is streamed, so there
+/// is no size limit beyond the time it takes to insert the rows.
+#[tracing::instrument(name = "Import subscribers from CSV", skip(state, admin, body), fields(admin = %admin.username))]
+pub async fn import_subscribers(
+    admin: AdminUser,
+    request_id: RequestId,
+    State(state): State<AppState>,
+    Query(mut options): Query<ImportOptions>,
+    body: BodyStream,
+) -> Result<Json<ImportReport>, AppError> {
+    let settings = state.settings.current();
+    options.name_rules = settings.subscriber_names.clone();
+    options.check_mx = settings.feature_enabled(MX_CHECK_FEATURE);
+    let input = StreamReader::new(body.map_err(io::Error::other));
+    let report = import::import_subscribers(
+        state.subscribers.as_ref(),
+        &state.email_policy,
+        input,
+        &options,
+    )
+    .await?;
+    audit::record(
+        state.audit_log.as_ref(),
+        NewAuditEvent::new(
+            admin.actor(),
+            AuditAction::SubscribersImported,
+            AuditTarget::subscriber_list(),
+        )
+        .request_id(request_id.0)
+        .details(report.summary()),
+    )
+    .await;
+    Ok(Json(report))
+}
@@ -0,0 +1,15 @@
+mod audit;
+mod email_domains;
+mod export;
+mod import;
+mod newsletter_templates;
+mod newsletters;
+mod subscribers;
+
+pub use audit::*;
+pub use email_domains::*;
+pub use export::*;
+pub use import::*;
+pub use newsletter_templates::*;
+pub use newsletters::*;
+pub use subscribers::*;
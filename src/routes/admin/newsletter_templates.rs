@@ -0,0 +1,152 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::audit::{self, AuditAction, AuditTarget, NewAuditEvent, SharedAuditLog};
+use crate::authentication::AdminUser;
+use crate::error::AppError;
+use crate::newsletter_templates::{
+    self, NewsletterTemplate, Personalisation, RenderedNewsletter, SharedNewsletterTemplates,
+    TemplateContent,
+};
+use crate::repository::SharedSubscriberRepository;
+use crate::request_id::RequestId;
+use crate::state::ApplicationBaseUrl;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct PreviewQuery {
+    /// Renders for this subscriber instead of a made-up one.
+    pub subscriber_id: Option<Uuid>,
+}
+
+fn not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("There is no newsletter template with id {}.", id))
+}
+
+#[tracing::instrument(name = "List newsletter templates", skip(templates, admin), fields(admin = %admin.username))]
+pub async fn list_newsletter_templates(
+    admin: AdminUser,
+    State(templates): State<SharedNewsletterTemplates>,
+) -> Result<Json<Vec<NewsletterTemplate>>, AppError> {
+    Ok(Json(templates.list().await?))
+}
+
+#[tracing::instrument(name = "Get newsletter template", skip(templates, admin), fields(admin = %admin.username))]
+pub async fn get_newsletter_template(
+    admin: AdminUser,
+    State(templates): State<SharedNewsletterTemplates>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<NewsletterTemplate>, AppError> {
+    let template = templates.find(id).await?.ok_or_else(|| not_found(id))?;
+    Ok(Json(template))
+}
+
+/// Templates that do not render for a sample subscriber are rejected.
+#[tracing::instrument(name = "Create newsletter template", skip(templates, audit_log, admin, content), fields(admin = %admin.username))]
+pub async fn create_newsletter_template(
+    admin: AdminUser,
+    request_id: RequestId,
+    State(templates): State<SharedNewsletterTemplates>,
+    State(audit_log): State<SharedAuditLog>,
+    Json(content): Json<TemplateContent>,
+) -> Result<(StatusCode, Json<NewsletterTemplate>), AppError> {
+    content.validate()?;
+    let template = templates.insert(&content).await?;
+    info!(template_id = %template.id, "Newsletter template has been created");
+    audit::record(
+        audit_log.as_ref(),
+        NewAuditEvent::new(
+            admin.actor(),
+            AuditAction::NewsletterTemplateCreated,
+            AuditTarget::newsletter_template(template.id),
+        )
+        .request_id(request_id.0),
+    )
+    .await;
+    Ok((StatusCode::CREATED, Json(template)))
+}
+
+#[tracing::instrument(name = "Update newsletter template", skip(templates, audit_log, admin, content), fields(admin = %admin.username))]
+pub async fn update_newsletter_template(
+    admin: AdminUser,
+    request_id: RequestId,
+    State(templates): State<SharedNewsletterTemplates>,
+    State(audit_log): State<SharedAuditLog>,
+    Path(id): Path<Uuid>,
+    Json(content): Json<TemplateContent>,
+) -> Result<Json<NewsletterTemplate>, AppError> {
+    content.validate()?;
+    let template = templates
+        .update(id, &content)
+        .await?
+        .ok_or_else(|| not_found(id))?;
+    info!("Newsletter template has been updated");
+    audit::record(
+        audit_log.as_ref(),
+        NewAuditEvent::new(
+            admin.actor(),
+            AuditAction::NewsletterTemplateUpdated,
+            AuditTarget::newsletter_template(id),
+        )
+        .request_id(request_id.0),
+    )
+    .await;
+    Ok(Json(template))
+}
+
+#[tracing::instrument(name = "Delete newsletter template", skip(templates, audit_log, admin), fields(admin = %admin.username))]
+pub async fn delete_newsletter_template(
+    admin: AdminUser,
+    request_id: RequestId,
+    State(templates): State<SharedNewsletterTemplates>,
+    State(audit_log): State<SharedAuditLog>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    if !templates.delete(id).await? {
+        return Err(not_found(id));
+    }
+    info!("Newsletter template has been deleted");
+    audit::record(
+        audit_log.as_ref(),
+        NewAuditEvent::new(
+            admin.actor(),
+            AuditAction::NewsletterTemplateDeleted,
+            AuditTarget::newsletter_template(id),
+        )
+        .request_id(request_id.0),
+    )
+    .await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The subject and both bodies as a subscriber would receive them. Links in the
+/// preview carry a placeholder token, so they lead nowhere.
+#[tracing::instrument(name = "Preview newsletter template", skip(templates, subscribers, base_url, admin), fields(admin = %admin.username))]
+pub async fn preview_newsletter_template(
+    admin: AdminUser,
+    State(templates): State<SharedNewsletterTemplates>,
+    State(subscribers): State<SharedSubscriberRepository>,
+    State(base_url): State<ApplicationBaseUrl>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<PreviewQuery>,
+) -> Result<Json<RenderedNewsletter>, AppError> {
+    let template = templates.find(id).await?.ok_or_else(|| not_found(id))?;
+    let mut personalisation = Personalisation::sample(&base_url.0, id);
+    if let Some(subscriber_id) = query.subscriber_id {
+        let subscriber = subscribers
+            .find_by_id(subscriber_id)
+            .await?
+            .ok_or_else(|| {
+                AppError::NotFound(format!("There is no subscriber with id {}.", subscriber_id))
+            })?;
+        personalisation.name = subscriber.name;
+        personalisation.email = subscriber.email;
+    }
+    Ok(Json(newsletter_templates::render(
+        &template.content,
+        &personalisation,
+    )?))
+}
@@ -0,0 +1,154 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, NaiveTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::audit::{self, AuditAction, AuditTarget, NewAuditEvent, SharedAuditLog};
+use crate::authentication::AdminUser;
+use crate::error::AppError;
+use crate::newsletter_issues::{IssueProgress, NewsletterIssue, Schedule, SharedNewsletterIssues};
+use crate::newsletter_templates::SharedNewsletterTemplates;
+use crate::request_id::RequestId;
+
+#[derive(Deserialize, Debug)]
+pub struct NewIssueRequest {
+    pub template_id: Uuid,
+    /// As soon as possible when unset or in the past.
+    pub scheduled_at: Option<DateTime<Utc>>,
+    /// Such as `09:00:00`, to send at that time in each subscriber's time zone.
+    pub local_time: Option<NaiveTime>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScheduleRequest {
+    pub scheduled_at: DateTime<Utc>,
+    pub local_time: Option<NaiveTime>,
+}
+
+impl From<ScheduleRequest> for Schedule {
+    fn from(request: ScheduleRequest) -> Self {
+        Schedule {
+            at: request.scheduled_at,
+            local_time: request.local_time,
+        }
+    }
+}
+
+fn not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("There is no newsletter issue with id {}.", id))
+}
+
+#[tracing::instrument(name = "List newsletter issues", skip(issues, admin), fields(admin = %admin.username))]
+pub async fn list_newsletters(
+    admin: AdminUser,
+    State(issues): State<SharedNewsletterIssues>,
+) -> Result<Json<Vec<NewsletterIssue>>, AppError> {
+    Ok(Json(issues.list().await?))
+}
+
+/// The issue along with how many of its emails have been sent, failed or bounced.
+#[tracing::instrument(name = "Get newsletter issue", skip(issues, admin), fields(admin = %admin.username))]
+pub async fn get_newsletter(
+    admin: AdminUser,
+    State(issues): State<SharedNewsletterIssues>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<IssueProgress>, AppError> {
+    let issue = issues.find(id).await?.ok_or_else(|| not_found(id))?;
+    let deliveries = issues.delivery_counts(id).await?;
+    Ok(Json(IssueProgress::new(issue, deliveries)))
+}
+
+/// Copies the template into a new issue, published by the delivery worker once due.
+#[tracing::instrument(name = "Schedule newsletter issue", skip(issues, templates, audit_log, admin), fields(admin = %admin.username))]
+pub async fn create_newsletter(
+    admin: AdminUser,
+    request_id: RequestId,
+    State(issues): State<SharedNewsletterIssues>,
+    State(templates): State<SharedNewsletterTemplates>,
+    State(audit_log): State<SharedAuditLog>,
+    Json(request): Json<NewIssueRequest>,
+) -> Result<(StatusCode, Json<NewsletterIssue>), AppError> {
+    let template = templates.find(request.template_id).await?.ok_or_else(|| {
+        AppError::BadRequest(format!(
+            "There is no newsletter template with id {}.",
+            request.template_id
+        ))
+    })?;
+    let schedule = Schedule {
+        at: request.scheduled_at.unwrap_or_else(Utc::now),
+        local_time: request.local_time,
+    };
+    let issue = issues
+        .insert(template.id, &template.content, schedule)
+        .await?;
+    info!(issue_id = %issue.id, scheduled_at = %schedule.at, "Newsletter issue has been scheduled");
+    audit::record(
+        audit_log.as_ref(),
+        NewAuditEvent::new(
+            admin.actor(),
+            AuditAction::NewsletterScheduled,
+            AuditTarget::newsletter(issue.id),
+        )
+        .request_id(request_id.0)
+        .details(json!({ "scheduled_at": schedule.at, "local_time": schedule.local_time })),
+    )
+    .await;
+    Ok((StatusCode::CREATED, Json(issue)))
+}
+
+/// Also schedules cancelled issues again. Published issues cannot be rescheduled.
+#[tracing::instrument(name = "Reschedule newsletter issue", skip(issues, audit_log, admin), fields(admin = %admin.username))]
+pub async fn reschedule_newsletter(
+    admin: AdminUser,
+    request_id: RequestId,
+    State(issues): State<SharedNewsletterIssues>,
+    State(audit_log): State<SharedAuditLog>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<ScheduleRequest>,
+) -> Result<Json<NewsletterIssue>, AppError> {
+    let schedule = Schedule::from(request);
+    let issue = issues
+        .reschedule(id, schedule)
+        .await?
+        .ok_or_else(|| not_found(id))?;
+    info!(scheduled_at = %schedule.at, "Newsletter issue has been rescheduled");
+    audit::record(
+        audit_log.as_ref(),
+        NewAuditEvent::new(
+            admin.actor(),
+            AuditAction::NewsletterRescheduled,
+            AuditTarget::newsletter(id),
+        )
+        .request_id(request_id.0)
+        .details(json!({ "scheduled_at": schedule.at, "local_time": schedule.local_time })),
+    )
+    .await;
+    Ok(Json(issue))
+}
+
+#[tracing::instrument(name = "Cancel newsletter issue", skip(issues, audit_log, admin), fields(admin = %admin.username))]
+pub async fn cancel_newsletter(
+    admin: AdminUser,
+    request_id: RequestId,
+    State(issues): State<SharedNewsletterIssues>,
+    State(audit_log): State<SharedAuditLog>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<NewsletterIssue>, AppError> {
+    let issue = issues.cancel(id).await?.ok_or_else(|| not_found(id))?;
+    info!("Newsletter issue has been cancelled");
+    audit::record(
+        audit_log.as_ref(),
+        NewAuditEvent::new(
+            admin.actor(),
+            AuditAction::NewsletterCancelled,
+            AuditTarget::newsletter(id),
+        )
+        .request_id(request_id.0),
+    )
+    .await;
+    Ok(Json(issue))
+}
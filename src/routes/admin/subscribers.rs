@@ -0,0 +1,294 @@
+use anyhow::Context;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::audit::{self, AuditAction, AuditTarget, NewAuditEvent, SharedAuditLog};
+use crate::authentication::AdminUser;
+use crate::domain::{SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::email_policy::MX_CHECK_FEATURE;
+use crate::error::AppError;
+use crate::privacy::{self, SubscriberData};
+use crate::repository::{
+    SharedSubscriberRepository, Subscriber, SubscriberFilter, SubscriberUpdate,
+};
+use crate::request_id::RequestId;
+use crate::state::AppState;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ListSubscribersQuery {
+    pub status: Option<SubscriptionStatus>,
+    pub subscribed_since: Option<DateTime<Utc>>,
+    pub subscribed_until: Option<DateTime<Utc>>,
+    /// Prefix of the email or the name.
+    pub search: Option<String>,
+    /// The `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubscriberPage {
+    pub subscribers: Vec<Subscriber>,
+    /// Absent on the last page.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateSubscriberBody {
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+/// Cursors are opaque to clients: `<subscribed_at>|<id>` of the last row, base64-encoded.
+fn encode_cursor(subscriber: &Subscriber) -> String {
+    let raw = format!(
+        "{}|{}",
+        subscriber.subscribed_at.to_rfc3339(),
+        subscriber.id
+    );
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(raw)
+}
+
+fn decode_cursor(cursor: &str) -> anyhow::Result<(DateTime<Utc>, Uuid)> {
+    let raw = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(cursor)?;
+    let raw = String::from_utf8(raw)?;
+    let (subscribed_at, id) = raw.split_once('|').context("Missing separator")?;
+    Ok((
+        DateTime::parse_from_rfc3339(subscribed_at)?.with_timezone(&Utc),
+        id.parse()?,
+    ))
+}
+
+#[tracing::instrument(name = "List subscribers", skip(subscribers, admin), fields(admin = %admin.username))]
+pub async fn list_subscribers(
+    admin: AdminUser,
+    State(subscribers): State<SharedSubscriberRepository>,
+    Query(query): Query<ListSubscribersQuery>,
+) -> Result<Json<SubscriberPage>, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::BadRequest(format!(
+            "limit must be between 1 and {}.",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let after = query
+        .cursor
+        .as_deref()
+        .map(decode_cursor)
+        .transpose()
+        .map_err(|_| AppError::BadRequest("Invalid cursor.".to_owned()))?;
+
+    let filter = SubscriberFilter {
+        status: query.status,
+        subscribed_since: query.subscribed_since,
+        subscribed_until: query.subscribed_until,
+        search: query.search.filter(|search| !search.is_empty()),
+        after,
+        // One extra row tells whether there is a next page.
+        limit: Some(limit + 1),
+    };
+    let mut page = subscribers.list(&filter).await?;
+    let next_cursor = if page.len() as i64 > limit {
+        page.truncate(limit as usize);
+        page.last().map(encode_cursor)
+    } else {
+        None
+    };
+
+    Ok(Json(SubscriberPage {
+        subscribers: page,
+        next_cursor,
+    }))
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(subscribers, admin), fields(admin = %admin.username))]
+pub async fn get_subscriber(
+    admin: AdminUser,
+    State(subscribers): State<SharedSubscriberRepository>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Subscriber>, AppError> {
+    subscribers
+        .find_by_id(id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("There is no subscriber with id {}.", id)))
+}
+
+/// A new email goes through the same [`crate::email_policy::EmailPolicy`] checks as signups.
+#[tracing::instrument(name = "Update subscriber", skip(state, admin, body), fields(admin = %admin.username))]
+pub async fn update_subscriber(
+    admin: AdminUser,
+    request_id: RequestId,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(body): Json<UpdateSubscriberBody>,
+) -> Result<Json<Subscriber>, AppError> {
+    let settings = state.settings.current();
+    let name_rules = &settings.subscriber_names;
+    let update = SubscriberUpdate {
+        name: body
+            .name
+            .map(|name| SubscriberName::parse_with(name, name_rules))
+            .transpose()
+            .map_err(|e| AppError::BadRequest(e.to_string()))?,
+        email: body
+            .email
+            .map(SubscriberEmail::parse)
+            .transpose()
+            .map_err(|e| AppError::BadRequest(e.to_string()))?,
+    };
+    if let Some(email) = &update.email {
+        state
+            .email_policy
+            .check(email, settings.feature_enabled(MX_CHECK_FEATURE))
+            .await?;
+    }
+
+    let updated = state
+        .subscribers
+        .update(id, &update)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("There is no subscriber with id {}.", id)))?;
+    info!("Subscriber details have been updated");
+    // Only the names of the fields: the values are personal data.
+    let fields: Vec<_> = [
+        ("name", update.name.is_some()),
+        ("email", update.email.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(field, changed)| changed.then_some(field))
+    .collect();
+    audit::record(
+        state.audit_log.as_ref(),
+        NewAuditEvent::new(
+            admin.actor(),
+            AuditAction::SubscriberUpdated,
+            AuditTarget::subscriber(id),
+        )
+        .request_id(request_id.0)
+        .details(json!({ "fields": fields })),
+    )
+    .await;
+    Ok(Json(updated))
+}
+
+#[tracing::instrument(name = "Delete subscriber", skip(subscribers, audit_log, admin), fields(admin = %admin.username))]
+pub async fn delete_subscriber(
+    admin: AdminUser,
+    request_id: RequestId,
+    State(subscribers): State<SharedSubscriberRepository>,
+    State(audit_log): State<SharedAuditLog>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    if subscribers.delete(id).await? {
+        info!("Subscriber has been deleted");
+        audit::record(
+            audit_log.as_ref(),
+            NewAuditEvent::new(
+                admin.actor(),
+                AuditAction::SubscriberDeleted,
+                AuditTarget::subscriber(id),
+            )
+            .request_id(request_id.0),
+        )
+        .await;
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound(format!(
+            "There is no subscriber with id {}.",
+            id
+        )))
+    }
+}
+
+/// Everything held about a subscriber, as sent to them on request.
+#[tracing::instrument(name = "Get subscriber data", skip(subscribers, db_connection, admin), fields(admin = %admin.username))]
+pub async fn get_subscriber_data(
+    admin: AdminUser,
+    State(subscribers): State<SharedSubscriberRepository>,
+    State(db_connection): State<PgPool>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SubscriberData>, AppError> {
+    let subscriber = subscribers
+        .find_by_id(id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("There is no subscriber with id {}.", id)))?;
+    let data = privacy::collect_subscriber_data(&db_connection, subscriber).await?;
+    Ok(Json(data))
+}
+
+/// Unlike `DELETE`, which only hides the subscriber, removes their personal data for good,
+/// leaving an anonymous record of who erased them.
+#[tracing::instrument(name = "Erase subscriber", skip(db_connection, audit_log, admin), fields(admin = %admin.username))]
+pub async fn erase_subscriber(
+    admin: AdminUser,
+    request_id: RequestId,
+    State(db_connection): State<PgPool>,
+    State(audit_log): State<SharedAuditLog>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let requested_by = admin.actor().to_string();
+    if privacy::erase_subscriber(&db_connection, id, &requested_by).await? {
+        info!("Subscriber has been erased");
+        audit::record(
+            audit_log.as_ref(),
+            NewAuditEvent::new(
+                admin.actor(),
+                AuditAction::SubscriberErased,
+                AuditTarget::subscriber(id),
+            )
+            .request_id(request_id.0),
+        )
+        .await;
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound(format!(
+            "There is no subscriber with id {}.",
+            id
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::assert_err;
+
+    #[test]
+    fn cursors_round_trip() {
+        let subscriber = Subscriber {
+            id: Uuid::new_v4(),
+            email: "ursula@example.com".into(),
+            name: "Ursula".into(),
+            timezone: None,
+            status: SubscriptionStatus::Confirmed,
+            subscribed_at: Utc::now(),
+            confirmed_at: Some(Utc::now()),
+            unsubscribed_at: None,
+            updated_at: Utc::now(),
+        };
+        let (subscribed_at, id) = decode_cursor(&encode_cursor(&subscriber)).unwrap();
+        assert_eq!(subscribed_at, subscriber.subscribed_at);
+        assert_eq!(id, subscriber.id);
+    }
+
+    #[test]
+    fn tampered_cursors_are_rejected() {
+        assert_err!(decode_cursor("not a cursor"));
+        assert_err!(decode_cursor(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD.encode("2022-12-01|nope")
+        ));
+    }
+}
@@ -0,0 +1,134 @@
+use std::future::Future;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Form;
+use sqlx::PgPool;
+use tracing::{error, info, Instrument};
+
+use super::{DataRequestFormData, TokenQuery};
+use crate::audit::{self, Actor, AuditAction, AuditTarget, NewAuditEvent, SharedAuditLog};
+use crate::domain::SubscriberEmail;
+use crate::error::AppError;
+use crate::privacy;
+use crate::repository::Subscriber;
+use crate::request_id::RequestId;
+use crate::state::AppState;
+
+// Both requests get the same answer whether or not the address is subscribed,
+// so that they cannot be used to find out who is.
+const REQUEST_ACCEPTED: &str =
+    "If this address is subscribed, we have sent an email to it with the next steps.";
+
+/// Rate limit the request by address, then answer straight away and handle it in the
+/// background, so that neither the answer nor its timing depends on whether the address
+/// is subscribed, or on the email provider.
+async fn accept<F, Fut>(
+    state: AppState,
+    form: DataRequestFormData,
+    handle: F,
+) -> Result<(StatusCode, &'static str), AppError>
+where
+    F: FnOnce(AppState, Subscriber) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), AppError>> + Send,
+{
+    let email =
+        SubscriberEmail::parse(form.email).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    let settings = state.settings.current();
+    state
+        .rate_limiter
+        .check_email(&email, &settings.rate_limit.per_email)
+        .await?;
+    let span = tracing::Span::current();
+    tokio::spawn(
+        async move {
+            let outcome = match state.subscribers.find_by_email(&email).await {
+                Ok(Some(subscriber)) => handle(state, subscriber).await,
+                Ok(None) => Ok(()),
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = outcome {
+                error!("Failed to handle a data request: {:?}", e);
+            }
+        }
+        .instrument(span),
+    );
+    Ok((StatusCode::ACCEPTED, REQUEST_ACCEPTED))
+}
+
+/// Email a copy of everything we hold to the subscriber's address.
+#[tracing::instrument(name = "Request a data export", skip_all)]
+pub async fn request_data_export(
+    request_id: RequestId,
+    State(state): State<AppState>,
+    Form(form): Form<DataRequestFormData>,
+) -> Result<(StatusCode, &'static str), AppError> {
+    accept(state, form, |state, subscriber| async move {
+        let subscriber_id = subscriber.id;
+        let data = privacy::collect_subscriber_data(&state.db_pool, subscriber).await?;
+        privacy::send_data_export(&state.email_client, &data).await?;
+        info!("Data export sent");
+        audit::record(
+            state.audit_log.as_ref(),
+            NewAuditEvent::new(
+                Actor::Subscriber,
+                AuditAction::DataExportSent,
+                AuditTarget::subscriber(subscriber_id),
+            )
+            .request_id(request_id.0),
+        )
+        .await;
+        Ok(())
+    })
+    .await
+}
+
+/// Email a link that erases the subscriber once followed.
+#[tracing::instrument(name = "Request an erasure", skip_all)]
+pub async fn request_erasure(
+    State(state): State<AppState>,
+    Form(form): Form<DataRequestFormData>,
+) -> Result<(StatusCode, &'static str), AppError> {
+    accept(state, form, |state, subscriber| async move {
+        privacy::request_erasure(
+            &state.db_pool,
+            &state.email_client,
+            &subscriber,
+            &state.base_url.0,
+        )
+        .await?;
+        info!("Erasure confirmation sent");
+        Ok(())
+    })
+    .await
+}
+
+#[tracing::instrument(name = "Confirm an erasure", skip_all)]
+pub async fn confirm_erasure(
+    request_id: RequestId,
+    State(db_connection): State<PgPool>,
+    State(audit_log): State<SharedAuditLog>,
+    Query(query): Query<TokenQuery>,
+) -> Result<&'static str, AppError> {
+    let subscriber_id = privacy::subscriber_for_erasure_token(&db_connection, &query.token)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("This link is invalid or has expired.".to_owned()))?;
+    privacy::erase_subscriber(
+        &db_connection,
+        subscriber_id,
+        &Actor::Subscriber.to_string(),
+    )
+    .await?;
+    info!(%subscriber_id, "Subscriber erased on their request");
+    audit::record(
+        audit_log.as_ref(),
+        NewAuditEvent::new(
+            Actor::Subscriber,
+            AuditAction::SubscriberErased,
+            AuditTarget::subscriber(subscriber_id),
+        )
+        .request_id(request_id.0),
+    )
+    .await;
+    Ok("Your subscription and all the data we held about you have been erased.")
+}
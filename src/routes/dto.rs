@@ -4,4 +4,25 @@ use serde::Deserialize;
 pub struct SubscriptionFormData {
     pub name: String,
     pub email: String,
+    /// An IANA time zone, filled in by the signup page from the browser's settings.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Hidden from people, see [`crate::bot_protection`].
+    #[serde(default)]
+    pub website: Option<String>,
+    #[serde(default)]
+    pub form_token: Option<String>,
+    /// Also accepted under the field names used by the hCaptcha and Turnstile widgets.
+    #[serde(default, alias = "h-captcha-response", alias = "cf-turnstile-response")]
+    pub captcha_response: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DataRequestFormData {
+    pub email: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TokenQuery {
+    pub token: String,
 }
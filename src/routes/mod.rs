@@ -1,7 +1,16 @@
+pub mod admin;
+mod data_requests;
 mod dto;
 mod health_check;
+mod newsletters;
+mod pages;
 mod subscriptions;
+mod webhooks;
 
+pub use data_requests::*;
 pub use dto::*;
 pub use health_check::*;
+pub use newsletters::*;
+pub use pages::*;
 pub use subscriptions::*;
+pub use webhooks::*;
@@ -0,0 +1,71 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use uuid::Uuid;
+
+use super::{unsubscribe_link, TokenQuery};
+use crate::error::AppError;
+use crate::newsletter_issues::{IssueStatus, SharedNewsletterIssues};
+use crate::newsletter_templates::{self, Personalisation, TemplateContent};
+use crate::repository::{SharedSubscriberRepository, Subscriber};
+use crate::state::ApplicationBaseUrl;
+use crate::templates::{HtmlTemplate, InvalidLinkPage};
+
+/// The link to the HTML part of a newsletter issue, as `token`'s subscriber received it.
+pub fn issue_view_link(base_url: &str, issue_id: Uuid, token: &str) -> String {
+    format!("{}/newsletters/{}/view?token={}", base_url, issue_id, token)
+}
+
+/// The "view in browser" page of an issue sent by the delivery worker, personalised
+/// like the email. Issues that have not been published yet are not shown.
+#[tracing::instrument(
+    name = "View a newsletter issue in the browser",
+    skip(issues, subscribers, base_url, query)
+)]
+pub async fn view_newsletter_issue(
+    State(issues): State<SharedNewsletterIssues>,
+    State(subscribers): State<SharedSubscriberRepository>,
+    State(base_url): State<ApplicationBaseUrl>,
+    Path(issue_id): Path<Uuid>,
+    Query(query): Query<TokenQuery>,
+) -> Result<Response, AppError> {
+    let issue = issues
+        .find(issue_id)
+        .await?
+        .filter(|issue| issue.status == IssueStatus::Published);
+    let subscriber = subscribers.find_by_token(&query.token).await?;
+    view_page(
+        issue.map(|issue| issue.content),
+        subscriber,
+        issue_view_link(&base_url.0, issue_id, &query.token),
+        &base_url.0,
+        &query.token,
+    )
+}
+
+fn view_page(
+    content: Option<TemplateContent>,
+    subscriber: Option<Subscriber>,
+    view_in_browser_link: String,
+    base_url: &str,
+    token: &str,
+) -> Result<Response, AppError> {
+    let (Some(content), Some(subscriber)) = (content, subscriber) else {
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            HtmlTemplate(InvalidLinkPage {
+                heading: "We could not find this newsletter",
+                message: "This link is invalid or no longer works.",
+            }),
+        )
+            .into_response());
+    };
+    let personalisation = Personalisation {
+        name: subscriber.name,
+        email: subscriber.email,
+        unsubscribe_link: unsubscribe_link(base_url, token),
+        view_in_browser_link,
+    };
+    let rendered = newsletter_templates::render(&content, &personalisation)?;
+    Ok(Html(rendered.html).into_response())
+}
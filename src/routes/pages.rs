@@ -0,0 +1,179 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use axum_extra::extract::cookie::SignedCookieJar;
+use chrono::Utc;
+use tracing::info;
+
+use super::subscriptions::add_subscriber;
+use super::{SubscriptionFormData, TokenQuery};
+use crate::audit::{self, Actor, AuditAction, AuditTarget, NewAuditEvent, SharedAuditLog};
+use crate::bot_protection::BotProtection;
+use crate::configuration::SettingsHandle;
+use crate::domain::SubscriptionStatus;
+use crate::error::AppError;
+use crate::flash::{self, FlashMessage};
+use crate::rate_limit::ClientIp;
+use crate::repository::SharedSubscriberRepository;
+use crate::request_id::RequestId;
+use crate::state::AppState;
+use crate::templates::{
+    CheckInboxPage, ConfirmedPage, HtmlTemplate, InvalidLinkPage, SignupPage, UnsubscribePage,
+    UnsubscribedPage,
+};
+
+const CHECK_INBOX_PATH: &str = "/subscriptions/check-your-inbox";
+
+fn invalid_link(heading: &'static str) -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        HtmlTemplate(InvalidLinkPage {
+            heading,
+            message: "This link is invalid or no longer works.",
+        }),
+    )
+        .into_response()
+}
+
+/// What to tell the visitor about an error caused by what they submitted.
+/// Other errors get the usual error response.
+fn flash_for(error: &AppError) -> Option<FlashMessage> {
+    match error {
+        AppError::BadRequest(message)
+        | AppError::Conflict(message)
+        | AppError::ServiceUnavailable(message) => Some(FlashMessage::error(message.as_str())),
+        AppError::EmailRejected(rejection) => Some(FlashMessage::error(rejection.to_string())),
+        AppError::RateLimited(limited) => Some(FlashMessage::error(limited.to_string())),
+        _ => None,
+    }
+}
+
+#[tracing::instrument(name = "Show the signup page", skip_all)]
+pub async fn signup_page(
+    State(bot_protection): State<BotProtection>,
+    State(settings): State<SettingsHandle>,
+    jar: SignedCookieJar,
+) -> (SignedCookieJar, HtmlTemplate<SignupPage>) {
+    let (jar, flash) = flash::take(jar);
+    let captcha = settings
+        .current()
+        .bot_protection
+        .captcha
+        .as_ref()
+        .and_then(|captcha| captcha.widget.clone());
+    let page = SignupPage {
+        flash,
+        form_token: bot_protection.issue_form_token(Utc::now()),
+        captcha,
+    };
+    (jar, HtmlTemplate(page))
+}
+
+/// The signup form posts back to its own page, which shows any error as a flash message.
+#[axum_macros::debug_handler(state = AppState)]
+pub async fn signup(
+    request_id: RequestId,
+    State(state): State<AppState>,
+    client_ip: Option<Extension<ClientIp>>,
+    jar: SignedCookieJar,
+    Form(form): Form<SubscriptionFormData>,
+) -> Result<(SignedCookieJar, Redirect), AppError> {
+    let remote_ip = client_ip.map(|Extension(ClientIp(ip))| ip);
+    match add_subscriber(&state, request_id, remote_ip, form).await {
+        Ok(()) => Ok((jar, Redirect::to(CHECK_INBOX_PATH))),
+        Err(e) => match flash_for(&e) {
+            Some(message) => Ok((flash::push(jar, &message), Redirect::to("/"))),
+            None => Err(e),
+        },
+    }
+}
+
+pub async fn check_inbox_page() -> HtmlTemplate<CheckInboxPage> {
+    HtmlTemplate(CheckInboxPage)
+}
+
+/// Followed from the confirmation email. Following it again is harmless.
+#[tracing::instrument(name = "Confirm a subscription", skip_all)]
+pub async fn confirm_subscription(
+    request_id: RequestId,
+    State(subscribers): State<SharedSubscriberRepository>,
+    State(audit_log): State<SharedAuditLog>,
+    Query(query): Query<TokenQuery>,
+) -> Result<Response, AppError> {
+    let Some(subscriber) = subscribers.find_by_token(&query.token).await? else {
+        return Ok(invalid_link("We could not confirm your subscription"));
+    };
+    match subscriber.status {
+        SubscriptionStatus::Confirmed => {}
+        SubscriptionStatus::PendingConfirmation => {
+            subscribers
+                .update_status(subscriber.id, SubscriptionStatus::Confirmed)
+                .await?;
+            info!(subscriber_id = %subscriber.id, "Subscription confirmed");
+            audit::record(
+                audit_log.as_ref(),
+                NewAuditEvent::new(
+                    Actor::Subscriber,
+                    AuditAction::SubscriptionConfirmed,
+                    AuditTarget::subscriber(subscriber.id),
+                )
+                .request_id(request_id.0),
+            )
+            .await;
+        }
+        // Unsubscribing wins over a confirmation link found later in the inbox.
+        SubscriptionStatus::Unsubscribed => {
+            return Ok(invalid_link("We could not confirm your subscription"));
+        }
+    }
+    Ok(HtmlTemplate(ConfirmedPage {
+        name: subscriber.name,
+    })
+    .into_response())
+}
+
+/// Asks for confirmation, so that link scanners opening the link do not unsubscribe anyone.
+#[tracing::instrument(name = "Show the unsubscribe page", skip_all)]
+pub async fn unsubscribe_page(
+    State(subscribers): State<SharedSubscriberRepository>,
+    Query(query): Query<TokenQuery>,
+) -> Result<Response, AppError> {
+    let Some(subscriber) = subscribers.find_by_token(&query.token).await? else {
+        return Ok(invalid_link("We could not unsubscribe you"));
+    };
+    Ok(HtmlTemplate(UnsubscribePage {
+        email: subscriber.email,
+        token: query.token,
+    })
+    .into_response())
+}
+
+#[tracing::instrument(name = "Unsubscribe", skip_all)]
+pub async fn unsubscribe(
+    request_id: RequestId,
+    State(subscribers): State<SharedSubscriberRepository>,
+    State(audit_log): State<SharedAuditLog>,
+    Form(form): Form<TokenQuery>,
+) -> Result<Response, AppError> {
+    let Some(subscriber) = subscribers.find_by_token(&form.token).await? else {
+        return Ok(invalid_link("We could not unsubscribe you"));
+    };
+    if subscriber.status != SubscriptionStatus::Unsubscribed {
+        subscribers
+            .update_status(subscriber.id, SubscriptionStatus::Unsubscribed)
+            .await?;
+        info!(subscriber_id = %subscriber.id, "Subscriber unsubscribed");
+        audit::record(
+            audit_log.as_ref(),
+            NewAuditEvent::new(
+                Actor::Subscriber,
+                AuditAction::Unsubscribed,
+                AuditTarget::subscriber(subscriber.id),
+            )
+            .request_id(request_id.0),
+        )
+        .await;
+    }
+    Ok(HtmlTemplate(UnsubscribedPage).into_response())
+}
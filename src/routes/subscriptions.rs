@@ -1,85 +1,236 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::audit::{self, Actor, AuditAction, AuditTarget, NewAuditEvent};
+use crate::bot_protection::{BotProtection, FormSubmission, Verdict};
+use crate::domain::{
+    NameRules, NewSubscriber, SubscriberEmail, SubscriberName, SubscriberTimezone,
+    SubscriptionStatus,
+};
+use crate::email_client::{EmailClient, EmailMessage, Mailbox};
+use crate::email_policy::MX_CHECK_FEATURE;
 use crate::error::AppError;
+use crate::privacy::generate_token;
+use crate::rate_limit::ClientIp;
+use crate::repository::{RepositoryError, Subscriber};
+use crate::request_id::RequestId;
+use crate::state::AppState;
+use crate::templates::{ConfirmationEmailHtml, ConfirmationEmailText};
 
 use super::SubscriptionFormData;
+use anyhow::Context;
+use askama::Template;
 use axum::extract::State;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
-use axum::{Extension, Form};
+use axum::{Extension, Form, Json};
 use chrono::Utc;
-use sqlx::PgPool;
-use tracing::Instrument;
-use tracing::{debug, error, info, info_span};
-use uuid::Uuid;
+use serde::Serialize;
+use serde_json::json;
+use std::net::IpAddr;
+use tracing::info;
 
-impl TryFrom<SubscriptionFormData> for NewSubscriber {
-    type Error = AppError;
+#[derive(Serialize, Debug)]
+pub struct FormTokenResponse {
+    pub form_token: String,
+}
+
+impl SubscriptionFormData {
+    fn bot_signals(&self) -> FormSubmission<'_> {
+        FormSubmission {
+            honeypot: self.website.as_deref(),
+            form_token: self.form_token.as_deref(),
+            captcha_response: self.captcha_response.as_deref(),
+        }
+    }
 
-    fn try_from(form: SubscriptionFormData) -> Result<Self, Self::Error> {
-        let name = match SubscriberName::parse(form.name) {
+    fn parse(self, name_rules: &NameRules) -> Result<NewSubscriber, AppError> {
+        let name = match SubscriberName::parse_with(self.name, name_rules) {
             Ok(name) => name,
             Err(e) => return Err(AppError::BadRequest(e.to_string())),
         };
-        let email = match SubscriberEmail::parse(form.email) {
+        let email = match SubscriberEmail::parse(self.email) {
             Ok(email) => email,
             Err(e) => return Err(AppError::BadRequest(e.to_string())),
         };
+        let timezone = match self.timezone.filter(|tz| !tz.trim().is_empty()) {
+            Some(timezone) => match SubscriberTimezone::parse(timezone) {
+                Ok(timezone) => Some(timezone),
+                Err(e) => return Err(AppError::BadRequest(e.to_string())),
+            },
+            None => None,
+        };
 
-        Ok(NewSubscriber { email, name })
+        Ok(NewSubscriber {
+            email,
+            name,
+            timezone,
+        })
     }
 }
 
-#[axum_macros::debug_handler]
-#[tracing::instrument(name = "Adding a new subscriber", skip(form, db_connection), fields(
-    subscriber_email = %form.email,
-    subscriber_name = %form.name
-))]
+/// The link confirming the subscription that `token` was sent for.
+pub fn confirmation_link(base_url: &str, token: &str) -> String {
+    format!("{}/subscriptions/confirm?token={}", base_url, token)
+}
+
+/// The link to the page unsubscribing the subscriber `token` was sent to.
+pub fn unsubscribe_link(base_url: &str, token: &str) -> String {
+    format!("{}/subscriptions/unsubscribe?token={}", base_url, token)
+}
+
+#[axum_macros::debug_handler(state = AppState)]
 pub async fn subscriptions(
-    State(db_connection): State<PgPool>,
+    request_id: RequestId,
+    State(state): State<AppState>,
+    client_ip: Option<Extension<ClientIp>>,
     Form(form): Form<SubscriptionFormData>,
 ) -> Result<String, AppError> {
-    let subscriber = match form.try_into() {
+    let remote_ip = client_ip.map(|Extension(ClientIp(ip))| ip);
+    add_subscriber(&state, request_id, remote_ip, form).await?;
+    Ok("New subscriber details has been saved".to_owned())
+}
+
+/// Everything behind `POST /subscriptions` and the signup page, up to the confirmation email.
+///
+/// Submissions caught by the honeypot succeed without saving anything, so that bots
+/// have no reason to adapt.
+#[tracing::instrument(name = "Adding a new subscriber", skip(state, request_id, form), fields(
+    subscriber_email = %form.email,
+    subscriber_name = %form.name
+))]
+pub(super) async fn add_subscriber(
+    state: &AppState,
+    request_id: RequestId,
+    remote_ip: Option<IpAddr>,
+    form: SubscriptionFormData,
+) -> Result<(), AppError> {
+    let settings = state.settings.current();
+    let verdict = state
+        .bot_protection
+        .check(form.bot_signals(), &settings.bot_protection, remote_ip)
+        .await?;
+    if verdict == Verdict::Bot {
+        info!("Dropping a submission that filled in the honeypot");
+        return Ok(());
+    }
+
+    let subscriber = match form.parse(&settings.subscriber_names) {
         Ok(subscriber) => subscriber,
         Err(e) => return Err(e),
     };
+    let check_mx = settings.feature_enabled(MX_CHECK_FEATURE);
+    state
+        .email_policy
+        .check(&subscriber.email, check_mx)
+        .await?;
+    state
+        .rate_limiter
+        .check_email(&subscriber.email, &settings.rate_limit.per_email)
+        .await?;
 
-    match insert_subscriber(&db_connection, &subscriber).await {
-        Ok(_) => {
+    let stored = match state.subscribers.insert(&subscriber).await {
+        Ok(stored) => {
             info!("New subscriber details has been saved");
-            return Ok("New subscriber details has been saved".to_owned());
+            audit::record(
+                state.audit_log.as_ref(),
+                NewAuditEvent::new(
+                    Actor::Subscriber,
+                    AuditAction::SubscriberCreated,
+                    AuditTarget::subscriber(stored.id),
+                )
+                .request_id(request_id.0),
+            )
+            .await;
+            stored
         }
-        Err(err) => {
-            error!("Failed to save subscriber details {:?}", err);
-            return Err(AppError::InternalServerError(format!(
-                "Failed to save subscriber details {:?}",
-                err
-            )));
+        Err(RepositoryError::DuplicateEmail) => {
+            resubscribe(state, request_id, &subscriber.email).await?
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let token = generate_token();
+    state.subscribers.insert_token(stored.id, &token).await?;
+    send_confirmation_email(&state.email_client, &subscriber, &state.base_url.0, &token).await?;
+    Ok(())
+}
+
+/// Signing up again with the address of a subscriber who has not confirmed, or who has
+/// unsubscribed, sends them a new confirmation email. Confirmed subscribers get a conflict.
+async fn resubscribe(
+    state: &AppState,
+    request_id: RequestId,
+    email: &SubscriberEmail,
+) -> Result<Subscriber, AppError> {
+    let existing = state
+        .subscribers
+        .find_by_email(email)
+        .await?
+        .ok_or(RepositoryError::DuplicateEmail)?;
+    match existing.status {
+        SubscriptionStatus::Confirmed => Err(RepositoryError::DuplicateEmail.into()),
+        SubscriptionStatus::PendingConfirmation => {
+            info!(subscriber_id = %existing.id, "Sending a new confirmation email");
+            Ok(existing)
+        }
+        SubscriptionStatus::Unsubscribed => {
+            let stored = state
+                .subscribers
+                .update_status(existing.id, SubscriptionStatus::PendingConfirmation)
+                .await?
+                .ok_or(RepositoryError::DuplicateEmail)?;
+            info!(subscriber_id = %stored.id, "Unsubscribed subscriber has signed up again");
+            audit::record(
+                state.audit_log.as_ref(),
+                NewAuditEvent::new(
+                    Actor::Subscriber,
+                    AuditAction::SubscriberUpdated,
+                    AuditTarget::subscriber(stored.id),
+                )
+                .request_id(request_id.0)
+                .details(json!({ "status": stored.status.as_str() })),
+            )
+            .await;
+            Ok(stored)
         }
     }
 }
 
-#[tracing::instrument(
-    name = "Insert a new subscriber into database",
-    skip(subscriber, db_connection)
-)]
-pub async fn insert_subscriber(
-    db_connection: &PgPool,
+#[tracing::instrument(name = "Send a confirmation email", skip_all)]
+async fn send_confirmation_email(
+    email_client: &EmailClient,
     subscriber: &NewSubscriber,
+    base_url: &str,
+    token: &str,
 ) -> anyhow::Result<()> {
-    sqlx::query!(
-        r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at)
-        VALUES ($1, $2, $3, $4)"#,
-        Uuid::new_v4(),
-        subscriber.email.as_ref(),
-        subscriber.name.as_ref(),
-        Utc::now()
-    )
-    .execute(&*db_connection)
-    .await
-    .map_err(|e| {
-        error!("Failed to execute query {:?}", e);
-        e
-    })?;
+    let confirmation_link = confirmation_link(base_url, token);
+    let unsubscribe_link = unsubscribe_link(base_url, token);
+    let html_body = ConfirmationEmailHtml {
+        name: subscriber.name.as_ref(),
+        confirmation_link: &confirmation_link,
+        unsubscribe_link: &unsubscribe_link,
+    }
+    .render()
+    .context("Failed to render the confirmation email")?;
+    let text_body = ConfirmationEmailText {
+        name: subscriber.name.as_ref(),
+        confirmation_link: &confirmation_link,
+        unsubscribe_link: &unsubscribe_link,
+    }
+    .render()
+    .context("Failed to render the confirmation email")?;
+    let recipient = Mailbox::named(subscriber.name.as_ref(), subscriber.email.clone());
+    email_client
+        .send(EmailMessage::new(
+            recipient, "Welcome!", html_body, text_body,
+        ))
+        .await?;
     Ok(())
 }
+
+/// A signed timestamp for the signup form to post back as `form_token`,
+/// checked against `bot_protection.min_fill_seconds`.
+#[tracing::instrument(name = "Issuing a form token", skip(bot_protection))]
+pub async fn subscription_form_token(
+    State(bot_protection): State<BotProtection>,
+) -> Json<FormTokenResponse> {
+    Json(FormTokenResponse {
+        form_token: bot_protection.issue_form_token(Utc::now()),
+    })
+}
@@ -0,0 +1,70 @@
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::authentication::verify_webhook_secret;
+use crate::error::AppError;
+use crate::state::AppState;
+
+/// The parts of Postmark's bounce webhook that are used.
+#[derive(Deserialize, Debug)]
+pub struct PostmarkBounce {
+    #[serde(rename = "RecordType")]
+    pub record_type: String,
+    /// Such as `HardBounce`, `SoftBounce` or `Transient`.
+    #[serde(rename = "Type")]
+    pub bounce_type: String,
+    #[serde(rename = "MessageID")]
+    pub message_id: String,
+    #[serde(rename = "BouncedAt")]
+    pub bounced_at: Option<DateTime<Utc>>,
+}
+
+impl PostmarkBounce {
+    /// Whether the address will never accept the email, as opposed to a full
+    /// mailbox or an unreachable server.
+    fn is_hard(&self) -> bool {
+        self.record_type == "Bounce" && self.bounce_type == "HardBounce"
+    }
+}
+
+/// Postmark's bounce webhook, configured with `email_client.webhook_secret` as the
+/// password of the Basic credentials in its URL.
+///
+/// Only hard bounces of newsletters are recorded: other bounce types and emails
+/// such as confirmations are ignored.
+#[tracing::instrument(
+    name = "Record a bounce reported by Postmark",
+    skip(state, headers, bounce),
+    fields(message_id = %bounce.message_id, bounce_type = %bounce.bounce_type)
+)]
+pub async fn record_postmark_bounce(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(bounce): Json<PostmarkBounce>,
+) -> Result<StatusCode, AppError> {
+    let settings = state.settings.current();
+    let secret = settings
+        .email_client
+        .webhook_secret
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("Webhooks are not configured.".to_owned()))?;
+    verify_webhook_secret(&headers, secret)?;
+
+    if !bounce.is_hard() {
+        info!("Ignoring a bounce that is not a hard bounce");
+        return Ok(StatusCode::OK);
+    }
+    let bounced_at = bounce.bounced_at.unwrap_or_else(Utc::now);
+    if state
+        .newsletter_issues
+        .record_bounce(&bounce.message_id, bounced_at)
+        .await?
+    {
+        info!("Newsletter delivery has bounced");
+    }
+    Ok(StatusCode::OK)
+}
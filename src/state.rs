@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum_extra::extract::cookie::Key;
+use axum_macros::FromRef;
+use secrecy::Secret;
+use sqlx::PgPool;
+
+use crate::audit::{PgAuditLog, SharedAuditLog};
+use crate::bot_protection::{BotProtection, HttpCaptchaVerifier, SharedCaptchaVerifier};
+use crate::configuration::SettingsHandle;
+use crate::email_client::EmailClient;
+use crate::email_policy::{EmailPolicy, PgDomainRules};
+use crate::flash;
+use crate::newsletter_issues::{PgNewsletterIssues, SharedNewsletterIssues};
+use crate::newsletter_templates::{PgNewsletterTemplates, SharedNewsletterTemplates};
+use crate::rate_limit::{
+    InMemoryRateLimitStore, PgRateLimitStore, RateLimitStore, RateLimitStoreKind, RateLimiter,
+};
+use crate::repository::{PgSubscriberRepository, SharedSubscriberRepository};
+
+/// Everything handlers can extract with `State<T>`, for any field type `T`.
+///
+/// New subsystems are added as a field here, without touching the handlers
+/// that do not need them.
+#[derive(Clone, FromRef)]
+pub struct AppState {
+    pub db_pool: PgPool,
+    pub subscribers: SharedSubscriberRepository,
+    pub settings: SettingsHandle,
+    pub base_url: ApplicationBaseUrl,
+    pub hmac_secret: HmacSecret,
+    pub email_client: Arc<EmailClient>,
+    pub audit_log: SharedAuditLog,
+    pub email_policy: EmailPolicy,
+    pub rate_limiter: RateLimiter,
+    pub bot_protection: BotProtection,
+    pub newsletter_templates: SharedNewsletterTemplates,
+    pub newsletter_issues: SharedNewsletterIssues,
+    /// Signs cookies, such as flash messages.
+    pub cookie_key: Key,
+}
+
+/// The public URL of the application, used to build links sent to subscribers.
+#[derive(Clone, Debug)]
+pub struct ApplicationBaseUrl(pub String);
+
+/// Key used to sign values handed out to clients.
+#[derive(Clone)]
+pub struct HmacSecret(pub Secret<String>);
+
+impl AppState {
+    /// Wire the Postgres-backed implementations, reading static values from `settings`.
+    pub fn new(db_pool: PgPool, settings: SettingsHandle) -> anyhow::Result<Self> {
+        let current = settings.current();
+        let rate_limit_store: Arc<dyn RateLimitStore> = match current.rate_limit.store {
+            RateLimitStoreKind::Memory => Arc::new(InMemoryRateLimitStore::new()),
+            RateLimitStoreKind::Postgres => Arc::new(PgRateLimitStore::new(db_pool.clone())),
+        };
+        let captcha = match &current.bot_protection.captcha {
+            Some(captcha) => {
+                Some(Arc::new(HttpCaptchaVerifier::from_settings(captcha)?) as SharedCaptchaVerifier)
+            }
+            None => None,
+        };
+        Ok(Self {
+            subscribers: Arc::new(PgSubscriberRepository::new(db_pool.clone())),
+            audit_log: Arc::new(PgAuditLog::new(db_pool.clone())),
+            email_policy: EmailPolicy::with_dns_resolver(Arc::new(PgDomainRules::new(
+                db_pool.clone(),
+            ))),
+            base_url: ApplicationBaseUrl(current.application.base_url.clone()),
+            rate_limiter: RateLimiter::new(
+                rate_limit_store,
+                current.application.hmac_secret.clone(),
+            ),
+            bot_protection: BotProtection::new(current.application.hmac_secret.clone(), captcha),
+            newsletter_templates: Arc::new(PgNewsletterTemplates::new(db_pool.clone())),
+            newsletter_issues: Arc::new(PgNewsletterIssues::new(db_pool.clone())),
+            cookie_key: flash::cookie_key(&current.application.hmac_secret),
+            hmac_secret: HmacSecret(current.application.hmac_secret.clone()),
+            email_client: Arc::new(
+                EmailClient::from_settings(&settings)
+                    .context("Failed to build the email client")?,
+            ),
+            db_pool,
+            settings,
+        })
+    }
+
+    pub fn with_subscribers(mut self, subscribers: SharedSubscriberRepository) -> Self {
+        self.subscribers = subscribers;
+        self
+    }
+
+    pub fn with_audit_log(mut self, audit_log: SharedAuditLog) -> Self {
+        self.audit_log = audit_log;
+        self
+    }
+
+    pub fn with_email_policy(mut self, email_policy: EmailPolicy) -> Self {
+        self.email_policy = email_policy;
+        self
+    }
+
+    pub fn with_newsletter_templates(
+        mut self,
+        newsletter_templates: SharedNewsletterTemplates,
+    ) -> Self {
+        self.newsletter_templates = newsletter_templates;
+        self
+    }
+
+    pub fn with_newsletter_issues(mut self, newsletter_issues: SharedNewsletterIssues) -> Self {
+        self.newsletter_issues = newsletter_issues;
+        self
+    }
+
+    pub fn with_captcha_verifier(mut self, captcha: SharedCaptchaVerifier) -> Self {
+        self.bot_protection = self.bot_protection.with_captcha_verifier(captcha);
+        self
+    }
+}
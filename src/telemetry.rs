@@ -1,9 +1,15 @@
 use std::{env::VarError, str::FromStr};
 
+use anyhow::Context;
 use derive_builder::Builder;
+use tracing::info;
 use tracing::log::LevelFilter;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::{
+    filter::Directive, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+use crate::configuration::SettingsHandle;
 
 #[derive(Debug, Builder)]
 pub struct TracingOptions {
@@ -13,16 +19,108 @@ pub struct TracingOptions {
     pub tower_http_level: LevelFilter,
 }
 
-pub fn init_tracing(crate_name: String, options: TracingOptions) {
-    let crate_level = options.crate_level.as_str().to_lowercase();
-    let tower_http_level = options.tower_http_level.as_str().to_lowercase();
+/// Changes the crate log level of the subscriber installed by [`init_tracing`].
+#[derive(Clone)]
+pub struct LogLevelHandle {
+    crate_name: String,
+    crate_level: LevelFilter,
+    tower_http_level: LevelFilter,
+    /// `RUST_LOG`, when it was set at startup.
+    env_directives: Option<String>,
+    handle: reload::Handle<EnvFilter, Registry>,
+}
+
+impl LogLevelHandle {
+    /// Override the crate's level, keeping the other directives in place.
+    pub fn set_crate_level(&self, crate_level: LevelFilter) -> anyhow::Result<()> {
+        let env_filter = startup_filter(
+            self.env_directives.as_deref(),
+            &self.crate_name,
+            self.crate_level,
+            self.tower_http_level,
+        )
+        .add_directive(crate_directive(&self.crate_name, crate_level)?);
+        self.handle
+            .reload(env_filter)
+            .context("Failed to update the log level")
+    }
 
-    let env_filter_level = format!(
+    /// Go back to the filter installed at startup.
+    pub fn reset(&self) -> anyhow::Result<()> {
+        let env_filter = startup_filter(
+            self.env_directives.as_deref(),
+            &self.crate_name,
+            self.crate_level,
+            self.tower_http_level,
+        );
+        self.handle
+            .reload(env_filter)
+            .context("Failed to reset the log level")
+    }
+
+    /// Apply `application.log_level` every time the configuration is reloaded,
+    /// going back to the startup level when it is unset.
+    pub async fn follow(self, mut settings: SettingsHandle) {
+        while let Ok(settings) = settings.changed().await {
+            let level = settings
+                .application
+                .log_level
+                .as_deref()
+                .and_then(|level| LevelFilter::from_str(level).ok());
+            let outcome = match level {
+                Some(level) => self.set_crate_level(level),
+                None => self.reset(),
+            };
+            match outcome {
+                Ok(_) => info!("Log level set to {}", level.unwrap_or(self.crate_level)),
+                Err(e) => tracing::error!("{:?}", e),
+            }
+        }
+    }
+}
+
+fn filter_directives(
+    crate_name: &str,
+    crate_level: LevelFilter,
+    tower_http_level: LevelFilter,
+) -> String {
+    format!(
         "{}={},tower_http={}",
-        crate_name, crate_level, tower_http_level
-    );
+        crate_name,
+        crate_level.as_str().to_lowercase(),
+        tower_http_level.as_str().to_lowercase()
+    )
+}
+
+/// `RUST_LOG` when it holds valid directives, the levels in the options otherwise.
+fn startup_filter(
+    env_directives: Option<&str>,
+    crate_name: &str,
+    crate_level: LevelFilter,
+    tower_http_level: LevelFilter,
+) -> EnvFilter {
+    env_directives
+        .and_then(|directives| EnvFilter::try_new(directives).ok())
+        .unwrap_or_else(|| filter_directives(crate_name, crate_level, tower_http_level).into())
+}
 
-    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| env_filter_level.into());
+fn crate_directive(crate_name: &str, crate_level: LevelFilter) -> anyhow::Result<Directive> {
+    format!("{}={}", crate_name, crate_level.as_str().to_lowercase())
+        .parse()
+        .context("Failed to parse the log level directive")
+}
+
+pub fn init_tracing(crate_name: String, options: TracingOptions) -> LogLevelHandle {
+    let env_directives = std::env::var(EnvFilter::DEFAULT_ENV)
+        .ok()
+        .filter(|directives| EnvFilter::try_new(directives).is_ok());
+    let env_filter = startup_filter(
+        env_directives.as_deref(),
+        &crate_name,
+        options.crate_level,
+        options.tower_http_level,
+    );
+    let (env_filter, handle) = reload::Layer::new(env_filter);
     let formatting_layer = BunyanFormattingLayer::new(
         "zero2prod".into(),
         // Output the formatted spans to stdout.
@@ -34,13 +132,21 @@ pub fn init_tracing(crate_name: String, options: TracingOptions) {
         .with(JsonStorageLayer)
         .with(formatting_layer)
         .init();
+
+    LogLevelHandle {
+        crate_name,
+        crate_level: options.crate_level,
+        tower_http_level: options.tower_http_level,
+        env_directives,
+        handle,
+    }
 }
 
 pub fn parse_log_level(
     env_var: Result<String, VarError>,
     default_value_opt: Option<LevelFilter>,
 ) -> LevelFilter {
-    let default_value = default_value_opt.unwrap_or_else(|| LevelFilter::Off);
+    let default_value = default_value_opt.unwrap_or(LevelFilter::Off);
     match env_var {
         Ok(log_level) => match LevelFilter::from_str(log_level.as_str()) {
             Ok(filter_level) => filter_level,
@@ -49,3 +155,39 @@ pub fn parse_log_level(
         Err(_) => default_value,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_crate_level_is_set_on_top_of_rust_log() {
+        let filter = startup_filter(
+            Some("warn,sqlx=info,zero2prod=debug"),
+            "zero2prod",
+            LevelFilter::Info,
+            LevelFilter::Off,
+        )
+        .add_directive(crate_directive("zero2prod", LevelFilter::Error).unwrap());
+
+        let directives = filter.to_string();
+        assert!(directives.contains("sqlx=info"));
+        assert!(directives.contains("zero2prod=error"));
+        assert!(!directives.contains("zero2prod=debug"));
+    }
+
+    #[test]
+    fn the_options_are_used_without_rust_log() {
+        for env_directives in [None, Some("not a=valid=directive")] {
+            let filter = startup_filter(
+                env_directives,
+                "zero2prod",
+                LevelFilter::Info,
+                LevelFilter::Off,
+            );
+            let directives = filter.to_string();
+            assert!(directives.contains("zero2prod=info"));
+            assert!(directives.contains("tower_http=off"));
+        }
+    }
+}
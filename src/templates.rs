@@ -0,0 +1,101 @@
+//! HTML pages and emails, checked against their structs at compile time.
+
+use askama::Template;
+use axum::response::{Html, IntoResponse, Response};
+
+use crate::configuration::CaptchaWidget;
+use crate::error::AppError;
+use crate::flash::FlashMessage;
+
+/// Renders a template as an HTML response.
+pub struct HtmlTemplate<T>(pub T);
+
+impl<T: Template> IntoResponse for HtmlTemplate<T> {
+    fn into_response(self) -> Response {
+        match self.0.render() {
+            Ok(html) => Html(html).into_response(),
+            Err(e) => AppError::Other(anyhow::Error::new(e).context("Failed to render a page"))
+                .into_response(),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "signup.html")]
+pub struct SignupPage {
+    pub flash: Option<FlashMessage>,
+    pub form_token: String,
+    pub captcha: Option<CaptchaWidget>,
+}
+
+#[derive(Template)]
+#[template(path = "check_inbox.html")]
+pub struct CheckInboxPage;
+
+#[derive(Template)]
+#[template(path = "confirmed.html")]
+pub struct ConfirmedPage {
+    pub name: String,
+}
+
+/// For confirmation and unsubscribe links that do not work (anymore).
+#[derive(Template)]
+#[template(path = "invalid_link.html")]
+pub struct InvalidLinkPage {
+    pub heading: &'static str,
+    pub message: &'static str,
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribe.html")]
+pub struct UnsubscribePage {
+    pub email: String,
+    pub token: String,
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribed.html")]
+pub struct UnsubscribedPage;
+
+#[derive(Template)]
+#[template(path = "emails/confirmation.html")]
+pub struct ConfirmationEmailHtml<'a> {
+    pub name: &'a str,
+    pub confirmation_link: &'a str,
+    pub unsubscribe_link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/confirmation.txt")]
+pub struct ConfirmationEmailText<'a> {
+    pub name: &'a str,
+    pub confirmation_link: &'a str,
+    pub unsubscribe_link: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_escaped_in_html_but_not_in_text() {
+        let html = ConfirmationEmailHtml {
+            name: "<Ursula>",
+            confirmation_link: "https://example.com/confirm?token=a&b",
+            unsubscribe_link: "https://example.com/unsubscribe",
+        }
+        .render()
+        .unwrap();
+        assert!(html.contains("&lt;Ursula&gt;"));
+
+        let text = ConfirmationEmailText {
+            name: "<Ursula>",
+            confirmation_link: "https://example.com/confirm?token=a&b",
+            unsubscribe_link: "https://example.com/unsubscribe",
+        }
+        .render()
+        .unwrap();
+        assert!(text.contains("<Ursula>"));
+        assert!(text.contains("token=a&b"));
+    }
+}
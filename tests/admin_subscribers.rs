@@ -0,0 +1,425 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::response::Response;
+use common::{TestAdmin, TestApp};
+use serde_json::{json, Value};
+use test_context::test_context;
+use tower::ServiceExt;
+use zero2prod::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use zero2prod::repository::{PgSubscriberRepository, Subscriber, SubscriberRepository};
+
+async fn insert_subscriber(app: &TestApp, name: &str, email: &str) -> Subscriber {
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(email.to_owned()).unwrap(),
+        name: SubscriberName::parse(name.to_owned()).unwrap(),
+        timezone: None,
+    };
+    PgSubscriberRepository::new(app.db_pool.clone())
+        .insert(&new_subscriber)
+        .await
+        .expect("Failed to insert subscriber")
+}
+
+async fn send(
+    app: &TestApp,
+    admin: Option<&TestAdmin>,
+    method: Method,
+    uri: &str,
+    body: Option<Value>,
+) -> Response {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(admin) = admin {
+        request = request.header(header::AUTHORIZATION, admin.basic_auth());
+    }
+    let body = match body {
+        Some(body) => {
+            request = request.header(header::CONTENT_TYPE, "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    app.router()
+        .oneshot(request.body(body).unwrap())
+        .await
+        .expect("Failed to call api")
+}
+
+async fn json_body(response: Response) -> Value {
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&body).expect("The body is not valid JSON")
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn admin_routes_reject_missing_or_invalid_credentials(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    let impostor = TestAdmin {
+        username: admin.username.clone(),
+        password: "not-the-right-password".to_owned(),
+    };
+
+    let response = send(app, None, Method::GET, "/admin/subscribers", None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.headers()[header::WWW_AUTHENTICATE],
+        r#"Basic realm="admin""#
+    );
+
+    let response = send(
+        app,
+        Some(&impostor),
+        Method::GET,
+        "/admin/subscribers",
+        None,
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = send(app, Some(&admin), Method::GET, "/admin/subscribers", None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn subscribers_are_listed_page_by_page(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    for i in 0..5 {
+        insert_subscriber(
+            app,
+            &format!("Reader {}", i),
+            &format!("reader{}@example.com", i),
+        )
+        .await;
+    }
+
+    let mut uri = "/admin/subscribers?limit=2".to_owned();
+    let mut emails = vec![];
+    let mut pages = 0;
+    loop {
+        let response = send(app, Some(&admin), Method::GET, &uri, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let page = json_body(response).await;
+        pages += 1;
+        for subscriber in page["subscribers"].as_array().unwrap() {
+            emails.push(subscriber["email"].as_str().unwrap().to_owned());
+        }
+        match page["next_cursor"].as_str() {
+            Some(cursor) => uri = format!("/admin/subscribers?limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+
+    assert_eq!(pages, 3);
+    let expected: Vec<_> = (0..5).map(|i| format!("reader{}@example.com", i)).collect();
+    assert_eq!(emails, expected);
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn subscribers_can_be_filtered(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    insert_subscriber(app, "Ursula", "ursula@example.com").await;
+    let octavia = insert_subscriber(app, "Octavia", "octavia@example.com").await;
+    PgSubscriberRepository::new(app.db_pool.clone())
+        .update_status(octavia.id, SubscriptionStatus::Confirmed)
+        .await
+        .unwrap();
+
+    let response = send(
+        app,
+        Some(&admin),
+        Method::GET,
+        "/admin/subscribers?search=URS",
+        None,
+    )
+    .await;
+    let page = json_body(response).await;
+    assert_eq!(page["subscribers"].as_array().unwrap().len(), 1);
+    assert_eq!(page["subscribers"][0]["name"], "Ursula");
+
+    let response = send(
+        app,
+        Some(&admin),
+        Method::GET,
+        "/admin/subscribers?status=confirmed",
+        None,
+    )
+    .await;
+    let page = json_body(response).await;
+    assert_eq!(page["subscribers"].as_array().unwrap().len(), 1);
+    assert_eq!(page["subscribers"][0]["email"], "octavia@example.com");
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn invalid_list_parameters_are_rejected(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+
+    for uri in [
+        "/admin/subscribers?limit=0",
+        "/admin/subscribers?limit=1000",
+        "/admin/subscribers?cursor=garbage",
+    ] {
+        let response = send(app, Some(&admin), Method::GET, uri, None).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn a_single_subscriber_can_be_fetched(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    let subscriber = insert_subscriber(app, "Ursula", "ursula@example.com").await;
+
+    let uri = format!("/admin/subscribers/{}", subscriber.id);
+    let response = send(app, Some(&admin), Method::GET, &uri, None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = json_body(response).await;
+    assert_eq!(body["email"], "ursula@example.com");
+    assert_eq!(body["status"], "pending_confirmation");
+
+    let uri = format!("/admin/subscribers/{}", uuid::Uuid::new_v4());
+    let response = send(app, Some(&admin), Method::GET, &uri, None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn subscriber_details_can_be_edited(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    let subscriber = insert_subscriber(app, "Ursula", "ursula@example.com").await;
+    insert_subscriber(app, "Octavia", "octavia@example.com").await;
+    let uri = format!("/admin/subscribers/{}", subscriber.id);
+
+    let response = send(
+        app,
+        Some(&admin),
+        Method::PATCH,
+        &uri,
+        Some(json!({ "name": "Ursula K. Le Guin" })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = json_body(response).await;
+    assert_eq!(body["name"], "Ursula K. Le Guin");
+    assert_eq!(body["email"], "ursula@example.com");
+
+    let response = send(
+        app,
+        Some(&admin),
+        Method::PATCH,
+        &uri,
+        Some(json!({ "email": "not-an-email" })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = send(
+        app,
+        Some(&admin),
+        Method::PATCH,
+        &uri,
+        Some(json!({ "email": "octavia@example.com" })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // Admins go through the same domain checks as signups.
+    let response = send(
+        app,
+        Some(&admin),
+        Method::PATCH,
+        &uri,
+        Some(json!({ "email": "ursula@mailinator.com" })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(json_body(response).await["reason"], "disposable_domain");
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn subscribers_can_be_deleted(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    let subscriber = insert_subscriber(app, "Ursula", "ursula@example.com").await;
+    let uri = format!("/admin/subscribers/{}", subscriber.id);
+
+    let response = send(app, Some(&admin), Method::DELETE, &uri, None).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = send(app, Some(&admin), Method::DELETE, &uri, None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = send(app, Some(&admin), Method::GET, &uri, None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // The row is kept, only marked as deleted.
+    let stored = sqlx::query!(
+        "SELECT deleted_at FROM subscriptions WHERE id = $1",
+        subscriber.id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(stored.deleted_at.is_some());
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn subscribers_can_be_imported_from_csv(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    insert_subscriber(app, "Octavia", "octavia@example.com").await;
+    let csv = "email,name\n\
+               ursula@example.com,Ursula\n\
+               octavia@example.com,Octavia\n\
+               not-an-email,Nobody\n";
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/admin/subscribers/import?confirmed=true")
+        .header(header::AUTHORIZATION, admin.basic_auth())
+        .header(header::CONTENT_TYPE, "text/csv")
+        .body(Body::from(csv))
+        .unwrap();
+    let response = app.router().oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let report = json_body(response).await;
+    assert_eq!(report["imported"], 1);
+    let lines: Vec<_> = report["rejected"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| row["line"].as_u64().unwrap())
+        .collect();
+    assert_eq!(lines, vec![3, 4]);
+    let ursula = PgSubscriberRepository::new(app.db_pool.clone())
+        .find_by_email(&SubscriberEmail::parse("ursula@example.com".to_owned()).unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(ursula.status, SubscriptionStatus::Confirmed);
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn an_import_without_the_expected_columns_is_rejected(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/admin/subscribers/import")
+        .header(header::AUTHORIZATION, admin.basic_auth())
+        .body(Body::from("address\nursula@example.com\n"))
+        .unwrap();
+    let response = app.router().oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+async fn export(app: &TestApp, admin: &TestAdmin, uri: &str, gzip: bool) -> Response {
+    let mut request = Request::builder()
+        .uri(uri)
+        .header(header::AUTHORIZATION, admin.basic_auth());
+    if gzip {
+        request = request.header(header::ACCEPT_ENCODING, "gzip");
+    }
+    app.router()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+async fn text_body(response: Response) -> String {
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn subscribers_are_exported_as_csv_with_the_selected_columns(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    insert_subscriber(app, "Le Guin, Ursula", "ursula@example.com").await;
+    insert_subscriber(app, "Octavia", "octavia@example.com").await;
+
+    let response = export(
+        app,
+        &admin,
+        "/admin/subscribers/export?format=csv&columns=email,name",
+        false,
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/csv; charset=utf-8"
+    );
+    assert_eq!(
+        text_body(response).await,
+        "email,name\n\
+         ursula@example.com,\"Le Guin, Ursula\"\n\
+         octavia@example.com,Octavia\n"
+    );
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn subscribers_are_exported_as_jsonl_filtered_by_status(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    insert_subscriber(app, "Ursula", "ursula@example.com").await;
+    let octavia = insert_subscriber(app, "Octavia", "octavia@example.com").await;
+    PgSubscriberRepository::new(app.db_pool.clone())
+        .update_status(octavia.id, SubscriptionStatus::Confirmed)
+        .await
+        .unwrap();
+
+    let response = export(
+        app,
+        &admin,
+        "/admin/subscribers/export?format=jsonl&status=confirmed",
+        false,
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = text_body(response).await;
+    let lines: Vec<Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["email"], "octavia@example.com");
+    assert_eq!(lines[0]["id"], octavia.id.to_string());
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn exports_are_gzipped_when_the_client_accepts_it(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    insert_subscriber(app, "Ursula", "ursula@example.com").await;
+
+    let response = export(app, &admin, "/admin/subscribers/export", true).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..2], &[0x1f, 0x8b], "The body is not gzip-compressed");
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn exports_with_unknown_columns_are_rejected(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+
+    let response = export(
+        app,
+        &admin,
+        "/admin/subscribers/export?columns=email,password",
+        false,
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
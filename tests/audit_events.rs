@@ -0,0 +1,133 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::response::Response;
+use chrono::{Duration, SecondsFormat, Utc};
+use common::{TestAdmin, TestApp};
+use serde_json::{json, Value};
+use test_context::test_context;
+use tower::ServiceExt;
+use zero2prod::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use zero2prod::repository::{PgSubscriberRepository, Subscriber, SubscriberRepository};
+
+async fn insert_subscriber(app: &TestApp, email: &str) -> Subscriber {
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(email.to_owned()).unwrap(),
+        name: SubscriberName::parse("Ursula".to_owned()).unwrap(),
+        timezone: None,
+    };
+    PgSubscriberRepository::new(app.db_pool.clone())
+        .insert(&new_subscriber)
+        .await
+        .unwrap()
+}
+
+async fn send(
+    app: &TestApp,
+    admin: &TestAdmin,
+    method: Method,
+    uri: &str,
+    body: Option<Value>,
+) -> Response {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::AUTHORIZATION, admin.basic_auth())
+        .header("x-request-id", "req-42");
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    };
+    app.router()
+        .oneshot(request.unwrap())
+        .await
+        .expect("Failed to call api")
+}
+
+async fn json_body(response: Response) -> Value {
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&body).expect("The body is not valid JSON")
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn admin_edits_are_queryable_by_target(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    let subscriber = insert_subscriber(app, "ursula@example.com").await;
+    let other = insert_subscriber(app, "le.guin@example.com").await;
+    let uri = format!("/admin/subscribers/{}", subscriber.id);
+
+    let response = send(
+        app,
+        &admin,
+        Method::PATCH,
+        &uri,
+        Some(json!({ "name": "Ursula K." })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = send(app, &admin, Method::DELETE, &uri, None).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let other_uri = format!("/admin/subscribers/{}", other.id);
+    send(app, &admin, Method::DELETE, &other_uri, None).await;
+
+    let query = format!("/admin/audit-events?target=subscriber:{}", subscriber.id);
+    let response = send(app, &admin, Method::GET, &query, None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let page = json_body(response).await;
+    let events = page["events"].as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    // Newest first.
+    assert_eq!(events[0]["action"], "subscriber_deleted");
+    assert_eq!(events[1]["action"], "subscriber_updated");
+    assert_eq!(events[1]["actor"], format!("admin:{}", admin.username));
+    assert_eq!(events[1]["request_id"], "req-42");
+    // Only the names of the changed fields, never their values.
+    assert_eq!(events[1]["details"], json!({ "fields": ["name"] }));
+    assert_eq!(page["next_cursor"], Value::Null);
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn audit_events_can_be_filtered_by_time_and_paginated(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    for email in ["a@example.com", "b@example.com", "c@example.com"] {
+        let subscriber = insert_subscriber(app, email).await;
+        let uri = format!("/admin/subscribers/{}", subscriber.id);
+        send(app, &admin, Method::DELETE, &uri, None).await;
+    }
+
+    let response = send(
+        app,
+        &admin,
+        Method::GET,
+        "/admin/audit-events?limit=2",
+        None,
+    )
+    .await;
+    let page = json_body(response).await;
+    assert_eq!(page["events"].as_array().unwrap().len(), 2);
+    let cursor = page["next_cursor"].as_i64().unwrap();
+    let uri = format!("/admin/audit-events?limit=2&cursor={}", cursor);
+    let page = json_body(send(app, &admin, Method::GET, &uri, None).await).await;
+    assert_eq!(page["events"].as_array().unwrap().len(), 1);
+    assert_eq!(page["next_cursor"], Value::Null);
+
+    let since = (Utc::now() + Duration::minutes(1)).to_rfc3339_opts(SecondsFormat::Secs, true);
+    let uri = format!("/admin/audit-events?since={}", since);
+    let page = json_body(send(app, &admin, Method::GET, &uri, None).await).await;
+    assert!(page["events"].as_array().unwrap().is_empty());
+
+    let response = send(
+        app,
+        &admin,
+        Method::GET,
+        "/admin/audit-events?limit=0",
+        None,
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::response::Response;
+use chrono::{Duration, Utc};
+use common::InMemoryApp;
+use secrecy::Secret;
+use serde_json::Value;
+use tower::ServiceExt;
+use wiremock::matchers::method;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::bot_protection::{HttpCaptchaVerifier, MockCaptchaVerifier};
+use zero2prod::repository::{SubscriberFilter, SubscriberRepository};
+
+async fn subscribe(app: &InMemoryApp, extra_fields: &str) -> Response {
+    let request = Request::builder()
+        .uri("/subscriptions")
+        .method(Method::POST)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(format!(
+            "name=Ursula&email=ursula%40example.com{}",
+            extra_fields
+        )))
+        .unwrap();
+    app.router()
+        .oneshot(request)
+        .await
+        .expect("Failed to call api")
+}
+
+async fn saved_subscribers(app: &InMemoryApp) -> usize {
+    app.subscribers
+        .list(&SubscriberFilter::default())
+        .await
+        .unwrap()
+        .len()
+}
+
+#[tokio::test]
+async fn filled_honeypots_are_dropped_silently() {
+    let app = InMemoryApp::new().await;
+
+    let response = subscribe(&app, "&website=https%3A%2F%2Fspam.example").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(saved_subscribers(&app).await, 0);
+
+    let response = subscribe(&app, "&website=").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(saved_subscribers(&app).await, 1);
+}
+
+#[tokio::test]
+async fn forms_submitted_too_quickly_are_rejected() {
+    let app = InMemoryApp::configured(|settings| {
+        settings.bot_protection.min_fill_seconds = Some(3);
+    })
+    .await;
+
+    let response = app
+        .router()
+        .oneshot(
+            Request::builder()
+                .uri("/subscriptions/form-token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    let token = body["form_token"].as_str().unwrap();
+
+    let response = subscribe(&app, &format!("&form_token={}", token)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = subscribe(&app, "").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(saved_subscribers(&app).await, 0);
+
+    let token = app
+        .state
+        .bot_protection
+        .issue_form_token(Utc::now() - Duration::seconds(10));
+    let response = subscribe(&app, &format!("&form_token={}", token)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(saved_subscribers(&app).await, 1);
+
+    let response = subscribe(&app, &format!("&form_token={}", token)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn a_solved_captcha_is_required_when_a_verifier_is_configured() {
+    let mut app = InMemoryApp::new().await;
+    app.state = app
+        .state
+        .with_captcha_verifier(Arc::new(MockCaptchaVerifier::accepting("solved")));
+
+    let response = subscribe(&app, "").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = subscribe(&app, "&h-captcha-response=guessed").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(saved_subscribers(&app).await, 0);
+
+    let response = subscribe(&app, "&cf-turnstile-response=solved").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(saved_subscribers(&app).await, 1);
+}
+
+#[tokio::test]
+async fn an_unreachable_captcha_provider_asks_to_retry_later() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+    let verifier = HttpCaptchaVerifier::new(
+        format!("{}/siteverify", server.uri()),
+        Secret::new("captcha-secret".into()),
+        std::time::Duration::from_millis(200),
+    )
+    .unwrap();
+    let mut app = InMemoryApp::new().await;
+    app.state = app.state.with_captcha_verifier(Arc::new(verifier));
+
+    let response = subscribe(&app, "&h-captcha-response=solved").await;
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(saved_subscribers(&app).await, 0);
+}
@@ -0,0 +1,292 @@
+#![allow(dead_code)]
+
+use async_trait::async_trait;
+use axum::Router;
+use base64::Engine;
+use once_cell::sync::Lazy;
+use secrecy::Secret;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Executor, PgPool};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use test_context::AsyncTestContext;
+use tracing::log::LevelFilter;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::audit::InMemoryAuditLog;
+use zero2prod::configuration::{get_configuration, DatabaseSettings, Settings, SettingsHandle};
+use zero2prod::email_policy::{EmailPolicy, FakeMxResolver, InMemoryDomainRules};
+use zero2prod::new_router;
+use zero2prod::newsletter_issues::InMemoryNewsletterIssues;
+use zero2prod::newsletter_templates::InMemoryNewsletterTemplates;
+use zero2prod::repository::InMemorySubscriberRepository;
+use zero2prod::state::AppState;
+use zero2prod::telemetry;
+
+static TRACING: Lazy<()> = Lazy::new(|| {
+    let test_log_level = match std::env::var("TEST_LOG_LEVEL") {
+        Ok(log_level) => match LevelFilter::from_str(log_level.as_str()) {
+            Ok(filter_level) => filter_level,
+            Err(_) => LevelFilter::Off,
+        },
+        Err(_) => LevelFilter::Off,
+    };
+
+    let tracing_options = telemetry::TracingOptionsBuilder::default()
+        .crate_level(test_log_level)
+        .tower_http_level(LevelFilter::Off)
+        .build()
+        .unwrap();
+    telemetry::init_tracing("zero2prod".into(), tracing_options);
+});
+
+pub struct TestApp {
+    pub db_name: String,
+    pub db_pool: PgPool,
+    pub settings: DatabaseSettings,
+    pub app_settings: SettingsHandle,
+    /// Stands in for the email provider's API.
+    pub email_server: MockServer,
+}
+
+impl TestApp {
+    // pub async fn new() -> Self {
+    //     Lazy::force(&TRACING);
+
+    //     let mut configuration = get_configuration().expect("Failed to get configuration");
+    //     configuration.database.database_name = Uuid::new_v4().to_string();
+
+    //     let db_pool = configure_database(&configuration.database).await;
+
+    //     TestApp {
+    //         db_name: configuration.database.database_name.clone(),
+    //         db_pool,
+    //     }
+    // }
+
+    pub fn router(&self) -> Router {
+        new_router(
+            AppState::new(self.db_pool.clone(), self.app_settings.clone())
+                .expect("Failed to build the application state"),
+        )
+    }
+
+    /// Create an admin with a random password, for the routes behind Basic auth.
+    pub async fn create_test_admin(&self) -> TestAdmin {
+        let admin = TestAdmin {
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        };
+        zero2prod::authentication::create_admin(
+            &self.db_pool,
+            &admin.username,
+            Secret::new(admin.password.clone()),
+        )
+        .await
+        .expect("Failed to create the test admin");
+        admin
+    }
+
+    /// Make the email server accept every email, as needed by signups.
+    pub async fn accept_emails(&self) {
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&self.email_server)
+            .await;
+    }
+
+    /// The requests received by the email server so far, as JSON.
+    pub async fn sent_emails(&self) -> Vec<serde_json::Value> {
+        received_emails(&self.email_server).await
+    }
+
+    /// Like [`TestApp::sent_emails`], once at least `count` emails have been sent in
+    /// the background, or after a few seconds.
+    pub async fn wait_for_emails(&self, count: usize) -> Vec<serde_json::Value> {
+        for _ in 0..100 {
+            let emails = self.sent_emails().await;
+            if emails.len() >= count {
+                return emails;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        self.sent_emails().await
+    }
+
+    /// Fails if anything besides `db_pool` is still connected to the test database, so
+    /// that leaked connections show up instead of being cut.
+    pub async fn drop_db(&self) -> anyhow::Result<()> {
+        // Connections are handed back to the pool by spawned tasks, and `close` misses
+        // those still on their way: wait until every connection is idle first.
+        for _ in 0..100 {
+            if self.db_pool.num_idle() >= self.db_pool.size() as usize {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        self.db_pool.close().await;
+
+        let connection = PgPoolOptions::new()
+            .max_connections(1)
+            .acquire_timeout(Duration::from_secs(10))
+            .connect_with(self.settings.without_db())
+            .await?;
+        connection
+            .execute(format!(r#"DROP DATABASE "{}";"#, self.db_name).as_str())
+            .await?;
+        connection.close().await;
+        Ok(())
+    }
+}
+
+/// The requests received by `email_server` so far, as JSON.
+async fn received_emails(email_server: &MockServer) -> Vec<serde_json::Value> {
+    email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice(&request.body).unwrap())
+        .collect()
+}
+
+pub struct TestAdmin {
+    pub username: String,
+    pub password: String,
+}
+
+impl TestAdmin {
+    /// The value of the `Authorization` header.
+    pub fn basic_auth(&self) -> String {
+        let credentials = format!("{}:{}", self.username, self.password);
+        format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode(credentials)
+        )
+    }
+}
+
+/// An application backed by in-memory storage, for tests that do not need a database.
+pub struct InMemoryApp {
+    pub subscribers: Arc<InMemorySubscriberRepository>,
+    /// Stands in for the email provider's API, accepting every email.
+    pub email_server: MockServer,
+    pub audit_log: Arc<InMemoryAuditLog>,
+    pub domain_rules: Arc<InMemoryDomainRules>,
+    pub newsletter_templates: Arc<InMemoryNewsletterTemplates>,
+    pub newsletter_issues: Arc<InMemoryNewsletterIssues>,
+    pub state: AppState,
+}
+
+impl InMemoryApp {
+    pub async fn new() -> Self {
+        Self::configured(|_| {}).await
+    }
+
+    /// An application with `configure` applied on top of the test configuration.
+    pub async fn configured(configure: impl FnOnce(&mut Settings)) -> Self {
+        Lazy::force(&TRACING);
+
+        let email_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&email_server)
+            .await;
+        let mut configuration = get_configuration().expect("Failed to get configuration");
+        configuration.email_client.base_url = email_server.uri();
+        configure(&mut configuration);
+        // Never connects unless a handler reaches for the database.
+        let db_pool = PgPoolOptions::new().connect_lazy_with(configuration.database.with_db());
+        let subscribers = Arc::new(InMemorySubscriberRepository::new());
+        let audit_log = Arc::new(InMemoryAuditLog::new());
+        let domain_rules = Arc::new(InMemoryDomainRules::new());
+        let newsletter_templates = Arc::new(InMemoryNewsletterTemplates::new());
+        let newsletter_issues = Arc::new(InMemoryNewsletterIssues::new());
+        let state = AppState::new(db_pool, SettingsHandle::fixed(configuration))
+            .expect("Failed to build the application state")
+            .with_subscribers(subscribers.clone())
+            .with_audit_log(audit_log.clone())
+            .with_email_policy(EmailPolicy::new(
+                domain_rules.clone(),
+                Arc::new(FakeMxResolver::new()),
+            ))
+            .with_newsletter_templates(newsletter_templates.clone())
+            .with_newsletter_issues(newsletter_issues.clone());
+        Self {
+            subscribers,
+            email_server,
+            audit_log,
+            domain_rules,
+            newsletter_templates,
+            newsletter_issues,
+            state,
+        }
+    }
+
+    pub fn router(&self) -> Router {
+        new_router(self.state.clone())
+    }
+
+    /// The emails sent so far, as JSON.
+    pub async fn sent_emails(&self) -> Vec<serde_json::Value> {
+        received_emails(&self.email_server).await
+    }
+}
+
+#[async_trait]
+impl AsyncTestContext for TestApp {
+    async fn setup() -> TestApp {
+        Lazy::force(&TRACING);
+
+        let email_server = MockServer::start().await;
+        let mut configuration = get_configuration().expect("Failed to get configuration");
+        configuration.database.database_name = Uuid::new_v4().to_string();
+        configuration.email_client.base_url = email_server.uri();
+
+        let db_pool = configure_database(&configuration.database).await;
+
+        TestApp {
+            db_name: configuration.database.database_name.clone(),
+            db_pool,
+            settings: configuration.database.clone(),
+            app_settings: SettingsHandle::fixed(configuration),
+            email_server,
+        }
+    }
+
+    async fn teardown(self) {
+        self.drop_db()
+            .await
+            .expect("Failed to drop the test database");
+        println!("finished drop database {:?}", self.db_name);
+    }
+}
+
+async fn configure_database(settings: &DatabaseSettings) -> PgPool {
+    let connection = PgPoolOptions::new()
+        .connect_with(settings.without_db())
+        .await
+        .expect("Failed to connect to Postgres");
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, settings.database_name).as_str())
+        .await
+        .expect("Failed to create database.");
+
+    println!("database {:?} created!", settings.database_name);
+
+    let return_connection = PgPoolOptions::new()
+        .max_connections(10)
+        .connect_with(settings.with_db())
+        .await
+        .expect("Failed to connect to Postgres");
+
+    // Migrate database
+    zero2prod::migrations::run_migrations(&return_connection)
+        .await
+        .expect("Failed to migrate the database");
+
+    return_connection
+}
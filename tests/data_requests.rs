@@ -0,0 +1,260 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::response::Response;
+use chrono::Utc;
+use common::TestApp;
+use test_context::test_context;
+use tower::ServiceExt;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use zero2prod::newsletter_issues::{NewsletterIssues, PgNewsletterIssues, Schedule};
+use zero2prod::newsletter_templates::{
+    NewsletterTemplates, PgNewsletterTemplates, TemplateContent,
+};
+use zero2prod::privacy;
+use zero2prod::repository::{PgSubscriberRepository, Subscriber, SubscriberRepository};
+
+async fn insert_subscriber(app: &TestApp, email: &str) -> Subscriber {
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(email.to_owned()).unwrap(),
+        name: SubscriberName::parse("Ursula".to_owned()).unwrap(),
+        timezone: None,
+    };
+    PgSubscriberRepository::new(app.db_pool.clone())
+        .insert(&new_subscriber)
+        .await
+        .unwrap()
+}
+
+async fn post_form(app: &TestApp, uri: &str, body: &str) -> Response {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    app.router().oneshot(request).await.unwrap()
+}
+
+async fn get(app: &TestApp, uri: &str) -> Response {
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    app.router().oneshot(request).await.unwrap()
+}
+
+/// The path and query of the first link in a plain-text email.
+fn confirmation_link(email: &serde_json::Value) -> String {
+    let text = email["TextBody"].as_str().unwrap();
+    let link = text
+        .split_whitespace()
+        .find(|word| word.starts_with("http"))
+        .unwrap();
+    let link = url::Url::parse(link).unwrap();
+    format!("{}?{}", link.path(), link.query().unwrap())
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn data_exports_are_emailed_to_the_subscriber(app: &mut TestApp) {
+    app.accept_emails().await;
+    let subscriber = insert_subscriber(app, "ursula@example.com").await;
+
+    let response = post_form(app, "/data-requests/export", "email=ursula%40example.com").await;
+
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let emails = app.wait_for_emails(1).await;
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["To"], "ursula@example.com");
+    assert!(emails[0]["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains(&subscriber.id.to_string()));
+    assert_eq!(emails[0]["Attachments"][0]["Name"], "data.json");
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn requests_for_unknown_addresses_look_the_same_but_send_nothing(app: &mut TestApp) {
+    app.accept_emails().await;
+
+    for uri in ["/data-requests/export", "/data-requests/erasure"] {
+        let response = post_form(app, uri, "email=nobody%40example.com").await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+    }
+
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert!(app.sent_emails().await.is_empty());
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn requests_are_accepted_even_when_the_email_provider_fails(app: &mut TestApp) {
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    insert_subscriber(app, "ursula@example.com").await;
+
+    for uri in ["/data-requests/export", "/data-requests/erasure"] {
+        let response = post_form(app, uri, "email=ursula%40example.com").await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+    }
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn following_the_erasure_link_erases_the_subscriber(app: &mut TestApp) {
+    app.accept_emails().await;
+    let subscriber = insert_subscriber(app, "ursula@example.com").await;
+
+    let response = post_form(app, "/data-requests/erasure", "email=ursula%40example.com").await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let link = confirmation_link(&app.wait_for_emails(1).await[0]);
+
+    let response = get(app, &link).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let remaining = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(remaining.is_empty());
+    let erasure =
+        sqlx::query!("SELECT subscriber_id, status, requested_by FROM subscriber_erasures")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(erasure.subscriber_id, subscriber.id);
+    assert_eq!(erasure.status, "pending_confirmation");
+    assert_eq!(erasure.requested_by, "subscriber");
+
+    // The token went away with the subscriber.
+    let response = get(app, &link).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn expired_erasure_links_are_rejected(app: &mut TestApp) {
+    app.accept_emails().await;
+    insert_subscriber(app, "ursula@example.com").await;
+    post_form(app, "/data-requests/erasure", "email=ursula%40example.com").await;
+    let link = confirmation_link(&app.wait_for_emails(1).await[0]);
+    sqlx::query!("UPDATE erasure_tokens SET created_at = created_at - INTERVAL '25 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = get(app, &link).await;
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let remaining = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn admins_can_inspect_and_erase_subscribers(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    let subscriber = insert_subscriber(app, "ursula@example.com").await;
+    let authenticated = |method: Method, uri: String| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, admin.basic_auth())
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app
+        .router()
+        .oneshot(authenticated(
+            Method::GET,
+            format!("/admin/subscribers/{}/data", subscriber.id),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let data: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(data["subscriber"]["email"], "ursula@example.com");
+
+    let uri = format!("/admin/subscribers/{}/erase", subscriber.id);
+    let response = app
+        .router()
+        .oneshot(authenticated(Method::POST, uri.clone()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let erasure = sqlx::query!("SELECT requested_by FROM subscriber_erasures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(erasure.requested_by, format!("admin:{}", admin.username));
+
+    let response = app
+        .router()
+        .oneshot(authenticated(Method::POST, uri))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn erasure_keeps_the_delivery_counts_of_issues(app: &mut TestApp) {
+    let subscriber = insert_subscriber(app, "ursula@example.com").await;
+    let content = TemplateContent {
+        name: "Weekly".into(),
+        subject: "News".into(),
+        html_body: "<p>News</p>".into(),
+        text_body: "News".into(),
+    };
+    let template = PgNewsletterTemplates::new(app.db_pool.clone())
+        .insert(&content)
+        .await
+        .unwrap();
+    let issues = PgNewsletterIssues::new(app.db_pool.clone());
+    let issue = issues
+        .insert(template.id, &content, Schedule::immediate(Utc::now()))
+        .await
+        .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO deliveries
+            (newsletter_issue_id, subscriber_id, status, queued_at, sent_at, updated_at)
+        VALUES ($1, $2, 'sent', $3, $3, $3)"#,
+        issue.id,
+        subscriber.id,
+        Utc::now()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let data = privacy::collect_subscriber_data(&app.db_pool, subscriber.clone())
+        .await
+        .unwrap();
+    assert_eq!(data.deliveries.len(), 1);
+    assert_eq!(data.deliveries[0].newsletter_issue_id, issue.id);
+    assert_eq!(data.deliveries[0].status, "sent");
+
+    assert!(
+        privacy::erase_subscriber(&app.db_pool, subscriber.id, "subscriber")
+            .await
+            .unwrap()
+    );
+
+    let counts = issues.delivery_counts(issue.id).await.unwrap();
+    assert_eq!(counts.sent, 1);
+    let anonymised = sqlx::query!("SELECT subscriber_id FROM deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(anonymised.subscriber_id, None);
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::response::Response;
+use axum::Router;
+use common::{InMemoryApp, TestApp};
+use serde_json::{json, Value};
+use test_context::test_context;
+use tower::ServiceExt;
+use zero2prod::email_policy::{DomainRule, DomainRules};
+use zero2prod::repository::{SubscriberFilter, SubscriberRepository};
+
+async fn subscribe(router: Router, email: &str) -> Response {
+    let request = Request::builder()
+        .uri("/subscriptions")
+        .method(Method::POST)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(format!(
+            "name=Ursula&email={}",
+            email.replace('@', "%40")
+        )))
+        .unwrap();
+    router.oneshot(request).await.expect("Failed to call api")
+}
+
+async fn json_body(response: Response) -> Value {
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&body).expect("The body is not valid JSON")
+}
+
+#[tokio::test]
+async fn signups_from_disposable_domains_are_rejected_with_a_reason() {
+    let app = InMemoryApp::new().await;
+
+    let response = subscribe(app.router(), "ursula@mailinator.com").await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(json_body(response).await["reason"], "disposable_domain");
+    let saved = app
+        .subscribers
+        .list(&SubscriberFilter::default())
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn allowed_domains_skip_the_disposable_list() {
+    let app = InMemoryApp::new().await;
+    app.domain_rules
+        .set("mailinator.com", DomainRule::Allow, "admin:root")
+        .await
+        .unwrap();
+
+    let response = subscribe(app.router(), "ursula@mailinator.com").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn admins_can_block_and_unblock_domains(app: &mut TestApp) {
+    app.accept_emails().await;
+    let admin = app.create_test_admin().await;
+    let send = |method: Method, uri: &str, body: Option<Value>| {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, admin.basic_auth())
+            .header(header::CONTENT_TYPE, "application/json");
+        let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+        app.router().oneshot(request.body(body).unwrap())
+    };
+
+    let response = send(
+        Method::PUT,
+        "/admin/email-domains/Example.NET",
+        Some(json!({ "rule": "block" })),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json_body(response).await["domain"], "example.net");
+
+    let response = subscribe(app.router(), "ursula@news.example.net").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(json_body(response).await["reason"], "blocked_domain");
+
+    let response = send(Method::GET, "/admin/email-domains", None)
+        .await
+        .unwrap();
+    let rules = json_body(response).await;
+    assert_eq!(rules.as_array().unwrap().len(), 1);
+    assert_eq!(rules[0]["rule"], "block");
+    assert_eq!(rules[0]["created_by"], format!("admin:{}", admin.username));
+
+    let response = send(Method::DELETE, "/admin/email-domains/example.net", None)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = subscribe(app.router(), "ursula@news.example.net").await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = send(Method::DELETE, "/admin/email-domains/example.net", None)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = send(
+        Method::PUT,
+        "/admin/email-domains/not%20a%20domain",
+        Some(json!({ "rule": "block" })),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -1,136 +1,20 @@
-use async_trait::async_trait;
+mod common;
+
 use axum::body::Body;
 use axum::http::{Method, Request, StatusCode};
-use once_cell::sync::Lazy;
-use secrecy::ExposeSecret;
-use sqlx::postgres::PgPoolOptions;
-use sqlx::{Executor, PgPool};
-use std::net::TcpListener;
-use std::str::FromStr;
-use std::time::Duration;
-use test_context::{test_context, AsyncTestContext};
+use common::{InMemoryApp, TestApp};
+use test_context::test_context;
 use tower::ServiceExt;
-use tracing::log::LevelFilter;
-use tracing::{debug, error, info};
-use uuid::Uuid;
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
-use zero2prod::new_router;
-use zero2prod::telemetry;
-
-static TRACING: Lazy<()> = Lazy::new(|| {
-    let test_log_level = match std::env::var("TEST_LOG_LEVEL") {
-        Ok(log_level) => match LevelFilter::from_str(log_level.as_str()) {
-            Ok(filter_level) => filter_level,
-            Err(_) => LevelFilter::Off,
-        },
-        Err(_) => LevelFilter::Off,
-    };
-
-    let tracing_options = telemetry::TracingOptionsBuilder::default()
-        .crate_level(test_log_level)
-        .tower_http_level(LevelFilter::Off)
-        .build()
-        .unwrap();
-    telemetry::init_tracing("zero2prod".into(), tracing_options);
-});
-
-struct TestApp {
-    pub db_name: String,
-    pub db_pool: PgPool,
-    pub settings: DatabaseSettings,
-}
-
-impl TestApp {
-    // pub async fn new() -> Self {
-    //     Lazy::force(&TRACING);
-
-    //     let mut configuration = get_configuration().expect("Failed to get configuration");
-    //     configuration.database.database_name = Uuid::new_v4().to_string();
-
-    //     let db_pool = configure_database(&configuration.database).await;
-
-    //     TestApp {
-    //         db_name: configuration.database.database_name.clone(),
-    //         db_pool,
-    //     }
-    // }
-
-    pub async fn drop_db(&self) -> anyhow::Result<()> {
-        // need to drop current connection to database first
-        self.db_pool.close().await;
-
-        let connection = PgPoolOptions::new()
-            .max_connections(10)
-            .acquire_timeout(Duration::from_secs(10))
-            .connect_with(self.settings.without_db())
-            .await
-            .expect("Failed to connect to Postgres");
-        let _ = connection
-            .execute(format!(r#"DROP DATABASE "{}";"#, self.db_name).as_str())
-            .await
-            .expect("Failed to drop database");
-        Ok(())
-    }
-}
-
-#[async_trait]
-impl AsyncTestContext for TestApp {
-    async fn setup() -> TestApp {
-        Lazy::force(&TRACING);
-
-        let mut configuration = get_configuration().expect("Failed to get configuration");
-        configuration.database.database_name = Uuid::new_v4().to_string();
-
-        let db_pool = configure_database(&configuration.database).await;
-
-        TestApp {
-            db_name: configuration.database.database_name.clone(),
-            db_pool,
-            settings: configuration.database.clone(),
-        }
-    }
-
-    async fn teardown(self) {
-        match self.drop_db().await {
-            Ok(_) => println!("finished drop database {:?}", self.db_name),
-            Err(err) => println!("error drop database {:?}", err),
-        }
-    }
-}
-
-async fn configure_database(settings: &DatabaseSettings) -> PgPool {
-    let connection = PgPoolOptions::new()
-        .connect_with(settings.without_db())
-        .await
-        .expect("Failed to connect to Postgres");
-    connection
-        .execute(format!(r#"CREATE DATABASE "{}";"#, settings.database_name).as_str())
-        .await
-        .expect("Failed to create database.");
+use zero2prod::audit::{AuditAction, AuditFilter, AuditLog};
+use zero2prod::domain::SubscriptionStatus;
+use zero2prod::repository::{SubscriberFilter, SubscriberRepository};
 
-    println!("database {:?} created!", settings.database_name);
-
-    let return_connection = PgPoolOptions::new()
-        .max_connections(10)
-        .connect_with(settings.with_db())
-        .await
-        .expect("Failed to connect to Postgres");
-
-    // Migrate database
-    sqlx::migrate!("./migrations")
-        .run(&return_connection)
-        .await
-        .expect("Failed to migrate the database");
-
-    return_connection
-}
-
-#[test_context(TestApp)]
 #[tokio::test]
-async fn health_check_works(app: &mut TestApp) {
-    // let app = TestApp::new().await;
+async fn health_check_works() {
+    let app = InMemoryApp::new().await;
 
-    let response = new_router(app.db_pool.clone())
+    let response = app
+        .router()
         .oneshot(
             Request::builder()
                 .uri("/health_check")
@@ -150,10 +34,12 @@ async fn health_check_works(app: &mut TestApp) {
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data(app: &mut TestApp) {
     // let app = TestApp::new().await;
+    app.accept_emails().await;
 
     let body = Body::from("name=le%20guin&email=ursula_le_guin%40gmail.com");
 
-    let response = new_router(app.db_pool.clone())
+    let response = app
+        .router()
         .oneshot(
             Request::builder()
                 .uri("/subscriptions")
@@ -174,12 +60,15 @@ async fn subscribe_returns_a_200_for_valid_form_data(app: &mut TestApp) {
 
     assert_eq!(saved.email, "ursula_le_guin@gmail.com");
     assert_eq!(saved.name, "le guin");
+    // With a link to confirm the subscription.
+    let emails = app.sent_emails().await;
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["To"], "\"le guin\" <ursula_le_guin@gmail.com>");
 }
 
-#[test_context(TestApp)]
 #[tokio::test]
-async fn subscribe_returns_a_422_when_data_is_missing(app: &mut TestApp) {
-    // let app = TestApp::new().await;
+async fn subscribe_returns_a_422_when_data_is_missing() {
+    let app = InMemoryApp::new().await;
 
     let test_cases = vec![
         ("name=le%20guin", "missing the email"),
@@ -193,7 +82,8 @@ async fn subscribe_returns_a_422_when_data_is_missing(app: &mut TestApp) {
     for (invalid_body, error_message) in test_cases {
         let body = Body::from(invalid_body);
 
-        let response = new_router(app.db_pool.clone())
+        let response = app
+            .router()
             .oneshot(
                 Request::builder()
                     .uri("/subscriptions")
@@ -205,7 +95,161 @@ async fn subscribe_returns_a_422_when_data_is_missing(app: &mut TestApp) {
             .await
             .expect("Failed to call api");
 
-        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
-        // assert_eq!(response.body(), error_message);
+        assert_eq!(
+            response.status(),
+            StatusCode::BAD_REQUEST,
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message
+        );
     }
 }
+
+#[tokio::test]
+async fn subscribe_stores_new_subscribers_as_pending_confirmation() {
+    let app = InMemoryApp::new().await;
+
+    let response = app
+        .router()
+        .oneshot(
+            Request::builder()
+                .uri("/subscriptions")
+                .method(Method::POST)
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .body(Body::from(
+                    "name=le%20guin&email=ursula_le_guin%40gmail.com",
+                ))
+                .expect("Failed to create request"),
+        )
+        .await
+        .expect("Failed to call api");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let saved = app
+        .subscribers
+        .list(&SubscriberFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved[0].status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn subscriptions_are_audited_with_the_request_id() {
+    let app = InMemoryApp::new().await;
+
+    let response = app
+        .router()
+        .oneshot(
+            Request::builder()
+                .uri("/subscriptions")
+                .method(Method::POST)
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .header("x-request-id", "abc-123")
+                .body(Body::from(
+                    "name=le%20guin&email=ursula_le_guin%40gmail.com",
+                ))
+                .expect("Failed to create request"),
+        )
+        .await
+        .expect("Failed to call api");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-request-id"], "abc-123");
+
+    let saved = app
+        .subscribers
+        .list(&SubscriberFilter::default())
+        .await
+        .unwrap();
+    let events = app.audit_log.query(&AuditFilter::default()).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].action, AuditAction::SubscriberCreated);
+    assert_eq!(events[0].actor, "subscriber");
+    assert_eq!(events[0].target, format!("subscriber:{}", saved[0].id));
+    assert_eq!(events[0].request_id.as_deref(), Some("abc-123"));
+}
+
+#[tokio::test]
+async fn subscriber_names_are_normalised() {
+    let app = InMemoryApp::new().await;
+
+    for (body, expected_status) in [
+        (
+            "name=%20Le%20%20%09Guin%20&email=ursula%40example.com",
+            StatusCode::OK,
+        ),
+        (
+            "name=Le%E2%80%AEGuin&email=octavia%40example.com",
+            StatusCode::BAD_REQUEST,
+        ),
+    ] {
+        let response = app
+            .router()
+            .oneshot(
+                Request::builder()
+                    .uri("/subscriptions")
+                    .method(Method::POST)
+                    .header("Content-Type", "application/x-www-form-urlencoded")
+                    .body(Body::from(body))
+                    .expect("Failed to create request"),
+            )
+            .await
+            .expect("Failed to call api");
+        assert_eq!(response.status(), expected_status);
+    }
+
+    let saved = app
+        .subscribers
+        .list(&SubscriberFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].name, "Le Guin");
+}
+
+#[tokio::test]
+async fn subscribers_can_give_their_time_zone() {
+    let app = InMemoryApp::new().await;
+
+    for (body, expected_status) in [
+        (
+            "name=Ursula&email=ursula%40example.com&timezone=America%2FLos_Angeles",
+            StatusCode::OK,
+        ),
+        (
+            "name=Octavia&email=octavia%40example.com&timezone=",
+            StatusCode::OK,
+        ),
+        (
+            "name=Le%20Guin&email=le_guin%40example.com&timezone=Mars%2FOlympus_Mons",
+            StatusCode::BAD_REQUEST,
+        ),
+    ] {
+        let response = app
+            .router()
+            .oneshot(
+                Request::builder()
+                    .uri("/subscriptions")
+                    .method(Method::POST)
+                    .header("Content-Type", "application/x-www-form-urlencoded")
+                    .body(Body::from(body))
+                    .expect("Failed to create request"),
+            )
+            .await
+            .expect("Failed to call api");
+        assert_eq!(response.status(), expected_status);
+    }
+
+    let saved = app
+        .subscribers
+        .list(&SubscriberFilter::default())
+        .await
+        .unwrap();
+    let timezone = |name: &str| {
+        let subscriber = saved.iter().find(|s| s.name == name).unwrap();
+        subscriber.timezone.clone()
+    };
+    assert_eq!(saved.len(), 2);
+    assert_eq!(timezone("Ursula").as_deref(), Some("America/Los_Angeles"));
+    assert_eq!(timezone("Octavia"), None);
+}
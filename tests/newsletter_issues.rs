@@ -0,0 +1,516 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::response::Response;
+use base64::Engine;
+use chrono::{Duration, NaiveTime, TimeZone, Utc};
+use common::{InMemoryApp, TestApp};
+use secrecy::ExposeSecret;
+use serde_json::{json, Value};
+use test_context::test_context;
+use tower::ServiceExt;
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::audit::PgAuditLog;
+use zero2prod::domain::{
+    NewSubscriber, SubscriberEmail, SubscriberName, SubscriberTimezone, SubscriptionStatus,
+};
+use zero2prod::email_client::EmailClient;
+use zero2prod::newsletter_issues::{
+    DeliveryWorker, ExecutionOutcome, IssueStatus, NewsletterIssues, PgNewsletterIssues, Schedule,
+};
+use zero2prod::newsletter_templates::{
+    NewsletterTemplate, NewsletterTemplates, PgNewsletterTemplates, TemplateContent,
+};
+use zero2prod::repository::{PgSubscriberRepository, SubscriberRepository};
+
+fn template_content() -> TemplateContent {
+    TemplateContent {
+        name: "Weekly".into(),
+        subject: "News for {{ name }}".into(),
+        html_body: "<p>Hi {{ name }}</p><a href=\"{{ view_in_browser_link }}\">View</a>".into(),
+        text_body: "Hi {{ name }}\nUnsubscribe: {{ unsubscribe_link }}".into(),
+    }
+}
+
+async fn json_body(response: Response) -> Value {
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&body).expect("The body is not valid JSON")
+}
+
+async fn create_template(app: &TestApp) -> NewsletterTemplate {
+    PgNewsletterTemplates::new(app.db_pool.clone())
+        .insert(&template_content())
+        .await
+        .unwrap()
+}
+
+async fn create_subscriber(
+    app: &TestApp,
+    name: &str,
+    email: &str,
+    timezone: Option<&str>,
+    status: SubscriptionStatus,
+) {
+    let subscribers = PgSubscriberRepository::new(app.db_pool.clone());
+    let subscriber = subscribers
+        .insert(&NewSubscriber {
+            name: SubscriberName::parse(name.into()).unwrap(),
+            email: SubscriberEmail::parse(email.into()).unwrap(),
+            timezone: timezone.map(|tz| SubscriberTimezone::parse(tz.into()).unwrap()),
+        })
+        .await
+        .unwrap();
+    if status != SubscriptionStatus::PendingConfirmation {
+        subscribers
+            .update_status(subscriber.id, status)
+            .await
+            .unwrap();
+    }
+}
+
+fn worker(app: &TestApp) -> DeliveryWorker {
+    let settings = app.app_settings.current();
+    DeliveryWorker::new(
+        app.db_pool.clone(),
+        Arc::new(EmailClient::from_settings(&app.app_settings).unwrap()),
+        settings.application.base_url.clone(),
+        Arc::new(PgAuditLog::new(app.db_pool.clone())),
+    )
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn admins_can_schedule_reschedule_and_cancel_issues(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    let template = create_template(app).await;
+    let send = |method: Method, uri: &str, body: Option<Value>| {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, admin.basic_auth())
+            .header(header::CONTENT_TYPE, "application/json");
+        let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+        app.router().oneshot(request.body(body).unwrap())
+    };
+    let monday = Utc.with_ymd_and_hms(2030, 1, 7, 9, 0, 0).unwrap();
+
+    let response = send(
+        Method::POST,
+        "/admin/newsletters",
+        Some(json!({ "template_id": Uuid::new_v4() })),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = send(
+        Method::POST,
+        "/admin/newsletters",
+        Some(json!({
+            "template_id": template.id,
+            "scheduled_at": monday,
+            "local_time": "09:00:00"
+        })),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let issue = json_body(response).await;
+    assert_eq!(issue["status"], "scheduled");
+    assert_eq!(issue["subject"], "News for {{ name }}");
+    assert_eq!(issue["scheduled_at"], "2030-01-07T09:00:00Z");
+    assert_eq!(issue["local_time"], "09:00:00");
+    let schedule_uri = format!(
+        "/admin/newsletters/{}/schedule",
+        issue["id"].as_str().unwrap()
+    );
+
+    let tuesday = monday + Duration::days(1);
+    let response = send(
+        Method::PUT,
+        &schedule_uri,
+        Some(json!({ "scheduled_at": tuesday })),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let issue = json_body(response).await;
+    assert_eq!(issue["scheduled_at"], "2030-01-08T09:00:00Z");
+    assert_eq!(issue["local_time"], Value::Null);
+
+    let response = send(Method::DELETE, &schedule_uri, None).await.unwrap();
+    assert_eq!(json_body(response).await["status"], "cancelled");
+    let response = send(Method::DELETE, &schedule_uri, None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = send(
+        Method::PUT,
+        &schedule_uri,
+        Some(json!({ "scheduled_at": monday })),
+    )
+    .await
+    .unwrap();
+    assert_eq!(json_body(response).await["status"], "scheduled");
+
+    let response = send(Method::GET, "/admin/newsletters", None).await.unwrap();
+    assert_eq!(json_body(response).await.as_array().unwrap().len(), 1);
+    let response = send(
+        Method::DELETE,
+        &format!("/admin/newsletters/{}/schedule", Uuid::new_v4()),
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn due_issues_are_published_once_across_workers(app: &mut TestApp) {
+    app.accept_emails().await;
+    create_subscriber(
+        app,
+        "Ursula",
+        "ursula@example.com",
+        None,
+        SubscriptionStatus::Confirmed,
+    )
+    .await;
+    create_subscriber(
+        app,
+        "Octavia",
+        "octavia@example.com",
+        None,
+        SubscriptionStatus::PendingConfirmation,
+    )
+    .await;
+    let template = create_template(app).await;
+    let issues = PgNewsletterIssues::new(app.db_pool.clone());
+    let monday = Utc.with_ymd_and_hms(2030, 1, 7, 9, 0, 0).unwrap();
+    let issue = issues
+        .insert(template.id, &template.content, Schedule::immediate(monday))
+        .await
+        .unwrap();
+    let (first, second) = (worker(app), worker(app));
+
+    let friday = monday - Duration::days(3);
+    assert!(first.publish_due_issues(friday).await.unwrap().is_empty());
+
+    let (a, b) = tokio::join!(
+        first.publish_due_issues(monday),
+        second.publish_due_issues(monday)
+    );
+    let mut published = a.unwrap();
+    published.extend(b.unwrap());
+    assert_eq!(published, vec![issue.id]);
+    let stored = issues.find(issue.id).await.unwrap().unwrap();
+    assert_eq!(stored.status, IssueStatus::Published);
+    assert_eq!(stored.published_at, Some(monday));
+
+    while first.try_execute_task(monday).await.unwrap() == ExecutionOutcome::TaskCompleted {}
+    let emails = app.sent_emails().await;
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["Subject"], "News for Ursula");
+    assert!(emails[0]["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains(&format!("{}&#x2f;view?token=", issue.id)));
+
+    assert!(issues
+        .reschedule(issue.id, Schedule::immediate(monday))
+        .await
+        .is_err());
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn issues_sent_at_a_local_time_reach_each_subscriber_at_that_time(app: &mut TestApp) {
+    app.accept_emails().await;
+    create_subscriber(
+        app,
+        "Ursula",
+        "ursula@example.com",
+        Some("Asia/Tokyo"),
+        SubscriptionStatus::Confirmed,
+    )
+    .await;
+    create_subscriber(
+        app,
+        "Octavia",
+        "octavia@example.com",
+        None,
+        SubscriptionStatus::Confirmed,
+    )
+    .await;
+    let template = create_template(app).await;
+    let sunday_noon = Utc.with_ymd_and_hms(2030, 1, 6, 12, 0, 0).unwrap();
+    let schedule = Schedule {
+        at: sunday_noon,
+        local_time: Some(NaiveTime::from_hms_opt(9, 0, 0).unwrap()),
+    };
+    PgNewsletterIssues::new(app.db_pool.clone())
+        .insert(template.id, &template.content, schedule)
+        .await
+        .unwrap();
+    let worker = &worker(app);
+    worker.publish_due_issues(sunday_noon).await.unwrap();
+    let deliver_all = |now| async move {
+        while worker.try_execute_task(now).await.unwrap() == ExecutionOutcome::TaskCompleted {}
+    };
+
+    deliver_all(sunday_noon).await;
+    assert!(app.sent_emails().await.is_empty());
+    // 09:00 in Tokyo.
+    deliver_all(Utc.with_ymd_and_hms(2030, 1, 7, 0, 0, 0).unwrap()).await;
+    let emails = app.sent_emails().await;
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["Subject"], "News for Ursula");
+    // 09:00 in UTC, for subscribers without a time zone.
+    deliver_all(Utc.with_ymd_and_hms(2030, 1, 7, 9, 0, 0).unwrap()).await;
+    let emails = app.sent_emails().await;
+    assert_eq!(emails.len(), 2);
+    assert_eq!(emails[1]["Subject"], "News for Octavia");
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn admins_can_follow_an_issue_going_out(app: &mut TestApp) {
+    app.accept_emails().await;
+    let admin = app.create_test_admin().await;
+    for (name, email) in [
+        ("Ursula", "ursula@example.com"),
+        ("Octavia", "octavia@example.com"),
+    ] {
+        create_subscriber(app, name, email, None, SubscriptionStatus::Confirmed).await;
+    }
+    let template = create_template(app).await;
+    let now = Utc::now();
+    let issue = PgNewsletterIssues::new(app.db_pool.clone())
+        .insert(template.id, &template.content, Schedule::immediate(now))
+        .await
+        .unwrap();
+    let progress = || async {
+        let request = Request::builder()
+            .uri(format!("/admin/newsletters/{}", issue.id))
+            .header(header::AUTHORIZATION, admin.basic_auth())
+            .body(Body::empty())
+            .unwrap();
+        let response = app.router().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        json_body(response).await
+    };
+
+    let body = progress().await;
+    assert_eq!(body["status"], "scheduled");
+    assert_eq!(body["recipients"], 0);
+    assert_eq!(body["progress"], 0.0);
+
+    let worker = worker(app);
+    worker.publish_due_issues(now).await.unwrap();
+    let body = progress().await;
+    assert_eq!(body["deliveries"]["queued"], 2);
+    assert_eq!(body["progress"], 0.0);
+
+    worker.try_execute_task(now).await.unwrap();
+    let body = progress().await;
+    assert_eq!(body["deliveries"]["sent"], 1);
+    assert_eq!(body["progress"], 50.0);
+
+    worker.try_execute_task(now).await.unwrap();
+    let body = progress().await;
+    assert_eq!(body["deliveries"]["sent"], 2);
+    assert_eq!(body["progress"], 100.0);
+
+    let message_id = sqlx::query_scalar!(
+        "SELECT provider_message_id FROM deliveries WHERE newsletter_issue_id = $1 LIMIT 1",
+        issue.id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .expect("The provider message id was not recorded");
+    let report_bounce = |authorization: String, bounce_type: &str| {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/webhooks/postmark/bounces")
+            .header(header::AUTHORIZATION, authorization)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({ "RecordType": "Bounce", "Type": bounce_type, "MessageID": message_id })
+                    .to_string(),
+            ))
+            .unwrap();
+        app.router().oneshot(request)
+    };
+    let secret = app
+        .app_settings
+        .current()
+        .email_client
+        .webhook_secret
+        .clone()
+        .unwrap();
+    let webhook_auth = format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD
+            .encode(format!("postmark:{}", secret.expose_secret()))
+    );
+
+    // Admin credentials are not the webhook's.
+    let response = report_bounce(admin.basic_auth(), "HardBounce")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    // Soft bounces may still be delivered later.
+    let response = report_bounce(webhook_auth.clone(), "SoftBounce")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(progress().await["deliveries"]["bounced"], 0);
+
+    let response = report_bounce(webhook_auth, "HardBounce").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = progress().await;
+    assert_eq!(body["deliveries"]["sent"], 1);
+    assert_eq!(body["deliveries"]["bounced"], 1);
+    assert_eq!(body["recipients"], 2);
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn cancelled_issues_are_not_published(app: &mut TestApp) {
+    let template = create_template(app).await;
+    let issues = PgNewsletterIssues::new(app.db_pool.clone());
+    let issue = issues
+        .insert(
+            template.id,
+            &template.content,
+            Schedule::immediate(Utc::now()),
+        )
+        .await
+        .unwrap();
+    issues.cancel(issue.id).await.unwrap();
+
+    let published = worker(app).publish_due_issues(Utc::now()).await.unwrap();
+
+    assert!(published.is_empty());
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn failed_deliveries_are_retried_later(app: &mut TestApp) {
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    create_subscriber(
+        app,
+        "Ursula",
+        "ursula@example.com",
+        None,
+        SubscriptionStatus::Confirmed,
+    )
+    .await;
+    let template = create_template(app).await;
+    let now = Utc::now();
+    let issues = PgNewsletterIssues::new(app.db_pool.clone());
+    let issue = issues
+        .insert(template.id, &template.content, Schedule::immediate(now))
+        .await
+        .unwrap();
+    let worker = worker(app);
+    worker.publish_due_issues(now).await.unwrap();
+
+    let outcome = worker.try_execute_task(now).await.unwrap();
+
+    assert_eq!(outcome, ExecutionOutcome::TaskCompleted);
+    assert_eq!(
+        worker.try_execute_task(now).await.unwrap(),
+        ExecutionOutcome::EmptyQueue
+    );
+    assert_eq!(
+        worker
+            .try_execute_task(now + Duration::minutes(1))
+            .await
+            .unwrap(),
+        ExecutionOutcome::TaskCompleted
+    );
+    let counts = issues.delivery_counts(issue.id).await.unwrap();
+    assert_eq!((counts.queued, counts.failed), (1, 0));
+
+    // Given up on after the fifth attempt.
+    for day in 1..=3 {
+        let outcome = worker.try_execute_task(now + Duration::days(day)).await;
+        assert_eq!(outcome.unwrap(), ExecutionOutcome::TaskCompleted);
+    }
+    let counts = issues.delivery_counts(issue.id).await.unwrap();
+    assert_eq!((counts.queued, counts.failed), (0, 1));
+}
+
+#[tokio::test]
+async fn scheduling_requires_an_admin() {
+    let app = InMemoryApp::new().await;
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/admin/newsletters")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({ "template_id": Uuid::new_v4() }).to_string(),
+        ))
+        .unwrap();
+
+    let response = app.router().oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn published_issues_can_be_viewed_in_the_browser_as_they_were_sent() {
+    let app = InMemoryApp::new().await;
+    let subscriber = app
+        .subscribers
+        .insert(&NewSubscriber {
+            name: SubscriberName::parse("Ursula".into()).unwrap(),
+            email: SubscriberEmail::parse("ursula@example.com".into()).unwrap(),
+            timezone: None,
+        })
+        .await
+        .unwrap();
+    app.subscribers
+        .insert_token(subscriber.id, "token")
+        .await
+        .unwrap();
+    let issue = app
+        .newsletter_issues
+        .insert(
+            Uuid::new_v4(),
+            &template_content(),
+            Schedule::immediate(Utc::now()),
+        )
+        .await
+        .unwrap();
+    let view = |id: Uuid| {
+        let request = Request::builder()
+            .uri(format!("/newsletters/{}/view?token=token", id))
+            .body(Body::empty())
+            .unwrap();
+        app.router().oneshot(request)
+    };
+
+    let response = view(issue.id).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    app.newsletter_issues.publish(issue.id).unwrap();
+    let response = view(issue.id).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert!(String::from_utf8(body.to_vec())
+        .unwrap()
+        .contains("<p>Hi Ursula</p>"));
+    let response = view(Uuid::new_v4()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
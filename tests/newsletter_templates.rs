@@ -0,0 +1,140 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::response::Response;
+use common::{InMemoryApp, TestApp};
+use serde_json::{json, Value};
+use test_context::test_context;
+use tower::ServiceExt;
+use zero2prod::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use zero2prod::newsletter_templates::{NewsletterTemplates, TemplateContent};
+use zero2prod::repository::{PgSubscriberRepository, SubscriberRepository};
+
+fn template_body(name: &str) -> Value {
+    json!({
+        "name": name,
+        "subject": "News for {{ name }}",
+        "html_body": "<p>Hi {{ name }}</p><a href=\"{{ view_in_browser_link }}\">View</a>",
+        "text_body": "Hi {{ name }}\nUnsubscribe: {{ unsubscribe_link }}",
+    })
+}
+
+fn new_subscriber(name: &str, email: &str) -> NewSubscriber {
+    NewSubscriber {
+        name: SubscriberName::parse(name.into()).unwrap(),
+        email: SubscriberEmail::parse(email.into()).unwrap(),
+        timezone: None,
+    }
+}
+
+async fn json_body(response: Response) -> Value {
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&body).expect("The body is not valid JSON")
+}
+
+#[tokio::test]
+async fn templates_are_not_public() {
+    let app = InMemoryApp::new().await;
+    let content: TemplateContent = serde_json::from_value(template_body("Weekly")).unwrap();
+    let template = app.newsletter_templates.insert(&content).await.unwrap();
+    let request = Request::builder()
+        .uri(format!(
+            "/newsletter-templates/{}/view?token=token",
+            template.id
+        ))
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.router().oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn admins_can_manage_and_preview_newsletter_templates(app: &mut TestApp) {
+    let admin = app.create_test_admin().await;
+    let send = |method: Method, uri: &str, body: Option<Value>| {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, admin.basic_auth())
+            .header(header::CONTENT_TYPE, "application/json");
+        let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+        app.router().oneshot(request.body(body).unwrap())
+    };
+
+    let mut typo = template_body("Weekly");
+    typo["text_body"] = json!("Hi {{ nmae }}");
+    let response = send(Method::POST, "/admin/newsletter-templates", Some(typo))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(json_body(response).await["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("The text body does not render"));
+
+    let response = send(
+        Method::POST,
+        "/admin/newsletter-templates",
+        Some(template_body("Weekly")),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let id = json_body(response).await["id"].as_str().unwrap().to_owned();
+    let response = send(
+        Method::POST,
+        "/admin/newsletter-templates",
+        Some(template_body("Weekly")),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let preview_uri = format!("/admin/newsletter-templates/{}/preview", id);
+    let response = send(Method::GET, &preview_uri, None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let preview = json_body(response).await;
+    assert_eq!(preview["subject"], "News for Ursula");
+    assert!(preview["text"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/unsubscribe?token=preview"));
+
+    let subscriber = PgSubscriberRepository::new(app.db_pool.clone())
+        .insert(&new_subscriber("Le Guin", "le.guin@example.com"))
+        .await
+        .unwrap();
+    let response = send(
+        Method::GET,
+        &format!("{}?subscriber_id={}", preview_uri, subscriber.id),
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(json_body(response).await["subject"], "News for Le Guin");
+
+    let response = send(
+        Method::PUT,
+        &format!("/admin/newsletter-templates/{}", id),
+        Some(template_body("Monthly")),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = send(Method::GET, "/admin/newsletter-templates", None)
+        .await
+        .unwrap();
+    let templates = json_body(response).await;
+    assert_eq!(templates.as_array().unwrap().len(), 1);
+    assert_eq!(templates[0]["name"], "Monthly");
+
+    let uri = format!("/admin/newsletter-templates/{}", id);
+    let response = send(Method::DELETE, &uri, None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = send(Method::GET, &uri, None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
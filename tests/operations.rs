@@ -0,0 +1,131 @@
+mod common;
+
+use claims::{assert_err, assert_ok};
+use common::TestApp;
+use secrecy::Secret;
+use sqlx::Executor;
+use test_context::test_context;
+use zero2prod::{authentication, migrations};
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn no_migration_is_pending_after_migrating(app: &mut TestApp) {
+    let pending = migrations::pending_migrations(&app.db_pool)
+        .await
+        .expect("Failed to list pending migrations");
+    assert!(pending.is_empty());
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn internationalised_domains_are_canonicalised_after_migrating(app: &mut TestApp) {
+    // As left by the SQL migration: lowercased, but not punycoded.
+    app.db_pool
+        .execute(
+            r#"
+            INSERT INTO subscriptions
+                (id, email, email_canonical, name, subscribed_at, updated_at, status)
+            VALUES
+                (gen_random_uuid(), 'ursula@bücher.example', 'ursula@bücher.example',
+                 'Ursula', now() - interval '1 day', now(), 'pending_confirmation'),
+                (gen_random_uuid(), 'ursula@xn--bcher-kva.example', 'ursula@xn--bcher-kva.example',
+                 'Ursula', now(), now(), 'confirmed'),
+                (gen_random_uuid(), 'octavia@bücher.example', 'octavia@bücher.example',
+                 'Octavia', now(), now(), 'confirmed')"#,
+        )
+        .await
+        .unwrap();
+
+    assert_ok!(migrations::run_migrations(&app.db_pool).await);
+
+    let remaining: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT email, email_canonical, status FROM subscriptions \
+        WHERE deleted_at IS NULL ORDER BY email_canonical",
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    // The confirmed duplicate is kept, although it is the most recent, and the
+    // addresses stay as entered.
+    assert_eq!(
+        remaining,
+        vec![
+            (
+                "octavia@bücher.example".into(),
+                "octavia@xn--bcher-kva.example".into(),
+                "confirmed".into()
+            ),
+            (
+                "ursula@xn--bcher-kva.example".into(),
+                "ursula@xn--bcher-kva.example".into(),
+                "confirmed".into()
+            ),
+        ]
+    );
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn create_admin_stores_a_hashed_password(app: &mut TestApp) {
+    let password = "correct horse battery staple".to_string();
+    assert_ok!(
+        authentication::create_admin(&app.db_pool, "admin", Secret::new(password.clone())).await
+    );
+
+    let saved = sqlx::query!("SELECT username, password_hash FROM users")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved user");
+    assert_eq!(saved.username, "admin");
+    assert!(saved.password_hash.starts_with("$argon2id$"));
+    assert!(!saved.password_hash.contains(&password));
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn create_admin_rejects_duplicates_and_short_passwords(app: &mut TestApp) {
+    let password = || Secret::new("correct horse battery staple".to_string());
+    assert_ok!(authentication::create_admin(&app.db_pool, "admin", password()).await);
+    assert_err!(authentication::create_admin(&app.db_pool, "admin", password()).await);
+    assert_err!(
+        authentication::create_admin(&app.db_pool, "other", Secret::new("short".into())).await
+    );
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn concurrent_startup_migrations_on_a_fresh_database_succeed(app: &mut TestApp) {
+    app.db_pool
+        .execute("DROP SCHEMA public CASCADE; CREATE SCHEMA public;")
+        .await
+        .expect("Failed to reset the schema");
+
+    let (first, second) = tokio::join!(
+        migrations::prepare_database(&app.db_pool, true),
+        migrations::prepare_database(&app.db_pool, true)
+    );
+    assert_ok!(first);
+    assert_ok!(second);
+    let pending = migrations::pending_migrations(&app.db_pool)
+        .await
+        .expect("Failed to list pending migrations");
+    assert!(pending.is_empty());
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn startup_is_refused_when_the_database_is_ahead_of_the_binary(app: &mut TestApp) {
+    sqlx::query!(
+        r#"
+        INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+        VALUES (99991231000000, 'from the future', true, '\x00', 0)"#
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to record a fake migration");
+
+    for migrate in [true, false] {
+        let error = assert_err!(migrations::prepare_database(&app.db_pool, migrate).await);
+        assert!(error.to_string().contains("99991231000000"));
+    }
+}
@@ -0,0 +1,235 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::response::Response;
+use common::InMemoryApp;
+use tower::ServiceExt;
+use zero2prod::audit::{AuditAction, AuditFilter, AuditLog};
+use zero2prod::domain::SubscriptionStatus;
+use zero2prod::repository::{SubscriberFilter, SubscriberRepository};
+
+async fn get(app: &InMemoryApp, uri: &str, cookie: Option<&str>) -> Response {
+    let mut request = Request::builder().uri(uri);
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, cookie);
+    }
+    app.router()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .expect("Failed to call api")
+}
+
+async fn post_form(app: &InMemoryApp, uri: &str, body: &str) -> Response {
+    let request = Request::builder()
+        .uri(uri)
+        .method(Method::POST)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    app.router()
+        .oneshot(request)
+        .await
+        .expect("Failed to call api")
+}
+
+async fn html(response: Response) -> String {
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+fn location(response: &Response) -> &str {
+    response.headers()[header::LOCATION].to_str().unwrap()
+}
+
+/// The path and query of the first link in the plain-text part of the last email sent.
+async fn link_in_last_email(app: &InMemoryApp, path: &str) -> String {
+    let emails = app.sent_emails().await;
+    let text = emails.last().unwrap()["TextBody"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    let link = text
+        .split_whitespace()
+        .map(|word| word.trim_end_matches('.'))
+        .filter_map(|word| url::Url::parse(word).ok())
+        .find(|link| link.path() == path)
+        .unwrap();
+    format!("{}?{}", link.path(), link.query().unwrap())
+}
+
+async fn sign_up(app: &InMemoryApp) {
+    let response = post_form(app, "/", "name=Ursula&email=ursula%40example.com").await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&response), "/subscriptions/check-your-inbox");
+}
+
+async fn status(app: &InMemoryApp) -> SubscriptionStatus {
+    app.subscribers
+        .list(&SubscriberFilter::default())
+        .await
+        .unwrap()[0]
+        .status
+}
+
+#[tokio::test]
+async fn the_signup_page_is_a_form_with_a_honeypot() {
+    let app = InMemoryApp::new().await;
+
+    let response = get(&app, "/", None).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    let page = html(response).await;
+    assert!(page.contains(r#"<form action="/" method="post">"#));
+    assert!(page.contains(r#"name="website""#));
+    assert!(page.contains(r#"name="form_token""#));
+    assert!(page.contains(r#"name="timezone""#));
+}
+
+#[tokio::test]
+async fn invalid_signups_are_shown_once_as_a_flash_message() {
+    let app = InMemoryApp::new().await;
+
+    let response = post_form(&app, "/", "name=&email=ursula%40example.com").await;
+
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&response), "/");
+    let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+    let cookie = set_cookie.split(';').next().unwrap().to_owned();
+
+    let response = get(&app, "/", Some(&cookie)).await;
+    assert!(response.headers().contains_key(header::SET_COOKIE));
+    assert!(html(response)
+        .await
+        .contains("A subscriber name cannot be empty."));
+
+    // A tampered cookie is ignored.
+    let tampered = cookie.replacen("flash=", "flash=x", 1);
+    let page = html(get(&app, "/", Some(&tampered)).await).await;
+    assert!(!page.contains("role=\"alert\""));
+}
+
+#[tokio::test]
+async fn the_link_in_the_confirmation_email_confirms_the_subscription() {
+    let app = InMemoryApp::new().await;
+    sign_up(&app).await;
+    assert_eq!(status(&app).await, SubscriptionStatus::PendingConfirmation);
+    let page = html(get(&app, "/subscriptions/check-your-inbox", None).await).await;
+    assert!(page.contains("Check your inbox"));
+
+    let link = link_in_last_email(&app, "/subscriptions/confirm").await;
+    let response = get(&app, &link, None).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(html(response).await.contains("Welcome, Ursula!"));
+    assert_eq!(status(&app).await, SubscriptionStatus::Confirmed);
+    // Following the link twice is harmless.
+    assert_eq!(get(&app, &link, None).await.status(), StatusCode::OK);
+    let confirmations = app
+        .audit_log
+        .query(&AuditFilter {
+            action: Some(AuditAction::SubscriptionConfirmed),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(confirmations.len(), 1);
+}
+
+#[tokio::test]
+async fn signing_up_again_sends_a_new_confirmation_email() {
+    let app = InMemoryApp::new().await;
+    sign_up(&app).await;
+
+    // The same address, written differently.
+    let response = post_form(&app, "/", "name=Ursula&email=Ursula%40Example.com").await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&response), "/subscriptions/check-your-inbox");
+    assert_eq!(app.sent_emails().await.len(), 2);
+    let link = link_in_last_email(&app, "/subscriptions/confirm").await;
+    assert_eq!(get(&app, &link, None).await.status(), StatusCode::OK);
+    assert_eq!(status(&app).await, SubscriptionStatus::Confirmed);
+
+    let response = post_form(&app, "/", "name=Ursula&email=ursula%40example.com").await;
+    assert_eq!(location(&response), "/");
+    let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+    let cookie = set_cookie.split(';').next().unwrap().to_owned();
+    let page = html(get(&app, "/", Some(&cookie)).await).await;
+    assert!(page.contains("A subscriber with this email already exists"));
+    assert_eq!(app.sent_emails().await.len(), 2);
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_can_sign_up_again_through_the_api() {
+    let app = InMemoryApp::new().await;
+    let body = "name=Ursula&email=ursula%40example.com";
+    assert_eq!(
+        post_form(&app, "/subscriptions", body).await.status(),
+        StatusCode::OK
+    );
+    let subscriber = &app
+        .subscribers
+        .list(&SubscriberFilter::default())
+        .await
+        .unwrap()[0];
+    app.subscribers
+        .update_status(subscriber.id, SubscriptionStatus::Unsubscribed)
+        .await
+        .unwrap();
+
+    let response = post_form(&app, "/subscriptions", body).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(status(&app).await, SubscriptionStatus::PendingConfirmation);
+    assert_eq!(app.sent_emails().await.len(), 2);
+    let link = link_in_last_email(&app, "/subscriptions/confirm").await;
+    assert_eq!(get(&app, &link, None).await.status(), StatusCode::OK);
+    let response = post_form(&app, "/subscriptions", body).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn unsubscribing_asks_for_confirmation_first() {
+    let app = InMemoryApp::new().await;
+    sign_up(&app).await;
+    let link = link_in_last_email(&app, "/subscriptions/unsubscribe").await;
+    let token = link.split("token=").nth(1).unwrap().to_owned();
+
+    let response = get(&app, &link, None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(html(response).await.contains("ursula@example.com"));
+    assert_eq!(status(&app).await, SubscriptionStatus::PendingConfirmation);
+
+    let response = post_form(
+        &app,
+        "/subscriptions/unsubscribe",
+        &format!("token={}", token),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(status(&app).await, SubscriptionStatus::Unsubscribed);
+
+    // The confirmation link no longer works.
+    let confirmation_link = link_in_last_email(&app, "/subscriptions/confirm").await;
+    let response = get(&app, &confirmation_link, None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(status(&app).await, SubscriptionStatus::Unsubscribed);
+}
+
+#[tokio::test]
+async fn unknown_tokens_get_an_error_page() {
+    let app = InMemoryApp::new().await;
+
+    for uri in [
+        "/subscriptions/confirm?token=unknown",
+        "/subscriptions/unsubscribe?token=unknown",
+    ] {
+        let response = get(&app, uri, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(html(response).await.contains("invalid"));
+    }
+}
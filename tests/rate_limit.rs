@@ -0,0 +1,151 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::response::Response;
+use claims::{assert_err, assert_ok};
+use common::{InMemoryApp, TestApp};
+use test_context::test_context;
+use tower::ServiceExt;
+use zero2prod::rate_limit::{
+    InMemoryRateLimitStore, PgRateLimitStore, RateLimitStore, TokenBucket,
+};
+
+async fn subscribe(app: &InMemoryApp, email: &str, peer: Option<SocketAddr>) -> Response {
+    let mut request = Request::builder()
+        .uri("/subscriptions")
+        .method(Method::POST)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(format!(
+            "name=Ursula&email={}",
+            email.replace('@', "%40")
+        )))
+        .unwrap();
+    if let Some(peer) = peer {
+        request.extensions_mut().insert(ConnectInfo(peer));
+    }
+    app.router()
+        .oneshot(request)
+        .await
+        .expect("Failed to call api")
+}
+
+#[tokio::test]
+async fn repeated_signups_for_an_address_are_limited() {
+    let app = InMemoryApp::new().await;
+    let per_email = app.state.settings.current().rate_limit.per_email;
+
+    for _ in 0..per_email.capacity {
+        let response = subscribe(&app, "ursula@example.com", None).await;
+        assert_ne!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+    // Whatever the case of the address.
+    let response = subscribe(&app, "URSULA@example.com", None).await;
+
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()[header::RETRY_AFTER]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0);
+}
+
+#[tokio::test]
+async fn data_requests_for_an_address_are_limited() {
+    let app = InMemoryApp::new().await;
+    let per_email = app.state.settings.current().rate_limit.per_email;
+    let request = |uri: &str, email: &str| {
+        Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(Body::from(format!("email={}", email)))
+            .unwrap()
+    };
+
+    for _ in 0..per_email.capacity {
+        let response = app
+            .router()
+            .oneshot(request("/data-requests/export", "ursula%40example.com"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+    }
+    let response = app
+        .router()
+        .oneshot(request("/data-requests/erasure", "URSULA%40example.com"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn signups_from_a_single_client_are_limited() {
+    let app = InMemoryApp::new().await;
+    let per_ip = app.state.settings.current().rate_limit.per_ip;
+    let client: SocketAddr = "203.0.113.7:4242".parse().unwrap();
+
+    for i in 0..per_ip.capacity {
+        let email = format!("reader{}@example.com", i);
+        let response = subscribe(&app, &email, Some(client)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let response = subscribe(&app, "late@example.com", Some(client)).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key(header::RETRY_AFTER));
+
+    let other: SocketAddr = "198.51.100.1:4242".parse().unwrap();
+    let response = subscribe(&app, "late@example.com", Some(other)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+// Every store must behave the same: each check runs against both.
+
+async fn buckets_are_kept_per_key(store: &dyn RateLimitStore) {
+    let bucket = TokenBucket {
+        capacity: 2,
+        refill_per_hour: 1,
+    };
+
+    assert_ok!(store.acquire("ip:a", &bucket).await.unwrap());
+    assert_ok!(store.acquire("ip:a", &bucket).await.unwrap());
+    let limited = assert_err!(store.acquire("ip:a", &bucket).await.unwrap());
+    assert!(limited.retry_after_seconds() > 3000);
+    assert_ok!(store.acquire("ip:b", &bucket).await.unwrap());
+}
+
+#[tokio::test]
+async fn in_memory_buckets_are_kept_per_key() {
+    buckets_are_kept_per_key(&InMemoryRateLimitStore::new()).await;
+}
+
+#[tokio::test]
+async fn a_full_in_memory_store_forgets_the_least_recently_updated_buckets() {
+    let store = InMemoryRateLimitStore::with_max_buckets(3);
+    let bucket = TokenBucket {
+        capacity: 1,
+        refill_per_hour: 1,
+    };
+
+    for key in ["ip:a", "ip:b", "ip:c", "ip:d"] {
+        assert_ok!(store.acquire(key, &bucket).await.unwrap());
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+    }
+
+    // `ip:a` was dropped to make room for `ip:d`, so it starts full again.
+    assert_ok!(store.acquire("ip:a", &bucket).await.unwrap());
+    for key in ["ip:c", "ip:d"] {
+        assert_err!(store.acquire(key, &bucket).await.unwrap());
+    }
+}
+
+#[test_context(TestApp)]
+#[tokio::test]
+async fn postgres_buckets_are_kept_per_key(app: &mut TestApp) {
+    buckets_are_kept_per_key(&PgRateLimitStore::new(app.db_pool.clone())).await;
+}
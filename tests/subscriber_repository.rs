@@ -0,0 +1,320 @@
+mod common;
+
+use claims::{assert_none, assert_ok, assert_some};
+use common::TestApp;
+use futures::TryStreamExt;
+use test_context::test_context;
+use uuid::Uuid;
+use zero2prod::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use zero2prod::repository::{
+    InMemorySubscriberRepository, PgSubscriberRepository, RepositoryError, SubscriberFilter,
+    SubscriberRepository,
+};
+
+// Every implementation must behave the same: each check runs against both.
+
+fn new_subscriber(name: &str, email: &str) -> NewSubscriber {
+    NewSubscriber {
+        name: SubscriberName::parse(name.into()).unwrap(),
+        email: SubscriberEmail::parse(email.into()).unwrap(),
+        timezone: None,
+    }
+}
+
+fn email(email: &str) -> SubscriberEmail {
+    SubscriberEmail::parse(email.into()).unwrap()
+}
+
+async fn inserted_subscribers_can_be_found(repository: &dyn SubscriberRepository) {
+    let inserted = assert_ok!(
+        repository
+            .insert(&new_subscriber("Ursula", "ursula@example.com"))
+            .await
+    );
+    assert_eq!(inserted.status, SubscriptionStatus::PendingConfirmation);
+
+    let by_id = assert_some!(repository.find_by_id(inserted.id).await.unwrap());
+    assert_eq!(by_id, inserted);
+    let by_email = assert_some!(repository
+        .find_by_email(&email("ursula@example.com"))
+        .await
+        .unwrap());
+    assert_eq!(by_email, inserted);
+    assert_none!(repository.find_by_id(Uuid::new_v4()).await.unwrap());
+}
+
+async fn duplicate_emails_are_rejected(repository: &dyn SubscriberRepository) {
+    let subscriber = new_subscriber("Ursula", "ursula@example.com");
+    assert_ok!(repository.insert(&subscriber).await);
+    assert!(matches!(
+        repository.insert(&subscriber).await,
+        Err(RepositoryError::DuplicateEmail)
+    ));
+}
+
+async fn emails_are_unique_whatever_their_case(repository: &dyn SubscriberRepository) {
+    let inserted = repository
+        .insert(&new_subscriber("Ursula", "Ursula@Example.com"))
+        .await
+        .unwrap();
+    assert_eq!(inserted.email, "Ursula@Example.com");
+
+    let found = assert_some!(repository
+        .find_by_email(&email("URSULA@example.COM"))
+        .await
+        .unwrap());
+    assert_eq!(found.id, inserted.id);
+    assert!(matches!(
+        repository
+            .insert(&new_subscriber("Ursula", "ursula@example.com"))
+            .await,
+        Err(RepositoryError::DuplicateEmail)
+    ));
+    let batch = repository
+        .insert_many(
+            &[new_subscriber("Ursula", "uRSULA@EXAMPLE.com")],
+            SubscriptionStatus::Confirmed,
+        )
+        .await
+        .unwrap();
+    assert!(batch.is_empty());
+}
+
+async fn statuses_can_be_updated(repository: &dyn SubscriberRepository) {
+    let inserted = repository
+        .insert(&new_subscriber("Ursula", "ursula@example.com"))
+        .await
+        .unwrap();
+    let updated = assert_some!(repository
+        .update_status(inserted.id, SubscriptionStatus::Confirmed)
+        .await
+        .unwrap());
+    assert_eq!(updated.status, SubscriptionStatus::Confirmed);
+    assert_some!(updated.confirmed_at);
+    assert_none!(updated.unsubscribed_at);
+    assert!(updated.updated_at >= inserted.updated_at);
+    assert_none!(repository
+        .update_status(Uuid::new_v4(), SubscriptionStatus::Confirmed)
+        .await
+        .unwrap());
+}
+
+async fn invalid_transitions_are_rejected(repository: &dyn SubscriberRepository) {
+    let inserted = repository
+        .insert(&new_subscriber("Ursula", "ursula@example.com"))
+        .await
+        .unwrap();
+    let unsubscribed = assert_some!(repository
+        .update_status(inserted.id, SubscriptionStatus::Unsubscribed)
+        .await
+        .unwrap());
+    assert_some!(unsubscribed.unsubscribed_at);
+
+    // Coming back requires a fresh confirmation.
+    assert!(matches!(
+        repository
+            .update_status(inserted.id, SubscriptionStatus::Confirmed)
+            .await,
+        Err(RepositoryError::InvalidTransition(_))
+    ));
+    let stored = assert_some!(repository.find_by_id(inserted.id).await.unwrap());
+    assert_eq!(stored.status, SubscriptionStatus::Unsubscribed);
+}
+
+async fn lists_can_be_filtered(repository: &dyn SubscriberRepository) {
+    let ursula = repository
+        .insert(&new_subscriber("Ursula", "ursula@example.com"))
+        .await
+        .unwrap();
+    let octavia = repository
+        .insert(&new_subscriber("Octavia", "butler@example.com"))
+        .await
+        .unwrap();
+    repository
+        .update_status(octavia.id, SubscriptionStatus::Confirmed)
+        .await
+        .unwrap();
+
+    let all = repository.list(&SubscriberFilter::default()).await.unwrap();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[0].id, ursula.id);
+
+    let confirmed = repository
+        .list(&SubscriberFilter {
+            status: Some(SubscriptionStatus::Confirmed),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(confirmed.len(), 1);
+    assert_eq!(confirmed[0].id, octavia.id);
+
+    for search in ["octa", "BUTLER", "Octavia"] {
+        let found = repository
+            .list(&SubscriberFilter {
+                search: Some(search.into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1, "searching for {}", search);
+        assert_eq!(found[0].id, octavia.id);
+    }
+
+    let limited = repository
+        .list(&SubscriberFilter {
+            limit: Some(1),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(limited.len(), 1);
+
+    let since_octavia = repository
+        .list(&SubscriberFilter {
+            subscribed_since: Some(octavia.subscribed_at),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(since_octavia.len(), 1);
+}
+
+async fn subscribers_can_be_deleted(repository: &dyn SubscriberRepository) {
+    let inserted = repository
+        .insert(&new_subscriber("Ursula", "ursula@example.com"))
+        .await
+        .unwrap();
+    assert!(repository.delete(inserted.id).await.unwrap());
+    assert!(!repository.delete(inserted.id).await.unwrap());
+    assert_none!(repository.find_by_id(inserted.id).await.unwrap());
+    assert_none!(repository
+        .find_by_email(&email("ursula@example.com"))
+        .await
+        .unwrap());
+    assert!(repository
+        .list(&SubscriberFilter::default())
+        .await
+        .unwrap()
+        .is_empty());
+    assert_none!(repository
+        .update_status(inserted.id, SubscriptionStatus::Confirmed)
+        .await
+        .unwrap());
+
+    // The address is free to sign up again.
+    let again = assert_ok!(
+        repository
+            .insert(&new_subscriber("Ursula", "ursula@example.com"))
+            .await
+    );
+    assert_ne!(again.id, inserted.id);
+}
+
+async fn batches_skip_taken_emails(repository: &dyn SubscriberRepository) {
+    repository
+        .insert(&new_subscriber("Ursula", "ursula@example.com"))
+        .await
+        .unwrap();
+    let batch = vec![
+        new_subscriber("Octavia", "octavia@example.com"),
+        new_subscriber("Ursula", "ursula@example.com"),
+        new_subscriber("Octavia again", "octavia@example.com"),
+        new_subscriber("Ted", "ted@example.com"),
+    ];
+
+    let inserted = repository
+        .insert_many(&batch, SubscriptionStatus::Confirmed)
+        .await
+        .unwrap();
+
+    let mut emails: Vec<_> = inserted.iter().map(|s| s.email.as_str()).collect();
+    emails.sort();
+    assert_eq!(emails, vec!["octavia@example.com", "ted@example.com"]);
+    assert!(inserted
+        .iter()
+        .all(|s| s.status == SubscriptionStatus::Confirmed));
+    let octavia = assert_some!(repository
+        .find_by_email(&email("octavia@example.com"))
+        .await
+        .unwrap());
+    assert_eq!(octavia.name, "Octavia");
+}
+
+async fn streams_match_lists(repository: &dyn SubscriberRepository) {
+    for i in 0..3 {
+        repository
+            .insert(&new_subscriber(
+                "Reader",
+                &format!("reader{}@example.com", i),
+            ))
+            .await
+            .unwrap();
+    }
+    let filter = SubscriberFilter {
+        search: Some("reader".into()),
+        ..SubscriberFilter::default()
+    };
+
+    let streamed: Vec<_> = repository
+        .stream(filter.clone())
+        .try_collect()
+        .await
+        .unwrap();
+
+    assert_eq!(streamed, repository.list(&filter).await.unwrap());
+    assert_eq!(streamed.len(), 3);
+}
+
+async fn subscribers_can_be_found_by_token(repository: &dyn SubscriberRepository) {
+    let inserted = repository
+        .insert(&new_subscriber("Ursula", "ursula@example.com"))
+        .await
+        .unwrap();
+    repository.insert_token(inserted.id, "token").await.unwrap();
+
+    let found = assert_some!(repository.find_by_token("token").await.unwrap());
+    assert_eq!(found.id, inserted.id);
+    assert_none!(repository.find_by_token("other").await.unwrap());
+
+    repository.delete(inserted.id).await.unwrap();
+    assert_none!(repository.find_by_token("token").await.unwrap());
+}
+
+macro_rules! repository_tests {
+    ($($check:ident),* $(,)?) => {
+        mod in_memory {
+            use super::*;
+            $(
+                #[tokio::test]
+                async fn $check() {
+                    super::$check(&InMemorySubscriberRepository::new()).await;
+                }
+            )*
+        }
+
+        mod postgres {
+            use super::*;
+            $(
+                #[test_context(TestApp)]
+                #[tokio::test]
+                async fn $check(app: &mut TestApp) {
+                    super::$check(&PgSubscriberRepository::new(app.db_pool.clone())).await;
+                }
+            )*
+        }
+    };
+}
+
+repository_tests!(
+    inserted_subscribers_can_be_found,
+    duplicate_emails_are_rejected,
+    emails_are_unique_whatever_their_case,
+    statuses_can_be_updated,
+    invalid_transitions_are_rejected,
+    lists_can_be_filtered,
+    subscribers_can_be_deleted,
+    batches_skip_taken_emails,
+    streams_match_lists,
+    subscribers_can_be_found_by_token,
+);